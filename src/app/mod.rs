@@ -6,7 +6,7 @@
 //
 // - state.rs: Core types (AppState, ProgressInfo, InstallerApp struct) and initialization
 // - theme.rs: Theme configuration
-// - logic.rs: Installation logic and orchestration
+// - logic.rs: Asset selection and driving the install pipeline (see pipeline.rs)
 // - ui.rs: UI rendering (eframe::App implementation)
 
 mod state;
@@ -15,4 +15,4 @@ mod logic;
 mod ui;
 
 // Re-export public types so they can be used by other modules via super::
-pub use state::{InstallerApp, AppState, ProgressInfo, get_available_disk_space};
+pub use state::{InstallerApp, AppState};
@@ -1,9 +1,11 @@
 // Copyright (C) 2026 SpruceOS Team
 // Licensed under GPL-3.0-or-later
 
+use crate::cache::DownloadCache;
 use crate::config::{setup_theme, DEFAULT_REPO_INDEX};
 use crate::drives::{get_removable_drives, DriveInfo};
-use crate::github::{Release, Asset};
+use crate::github::{Release, Asset, Checksums};
+use crate::pipeline::{InstallEvent, InstallStage};
 use egui_thematic::ThemeEditorState;
 use std::sync::{Arc, Mutex};
 use tokio::runtime::Runtime;
@@ -13,16 +15,22 @@ use tokio_util::sync::CancellationToken;
 #[derive(Debug, Clone, PartialEq)]
 pub enum AppState {
     Idle,
+    FetchingReleases,
+    SelectingRelease,
     FetchingAssets,
     SelectingAsset,
     PreviewingUpdate,
     AwaitingConfirmation,
     FetchingRelease,
     Downloading,
+    BackingUp,
+    CheckingCapacity,
+    Benchmarking,
     Formatting,
     Deleting,
     Extracting,
     Copying,
+    Verifying,
     Burning,
     Complete,
     Ejecting,
@@ -31,6 +39,28 @@ pub enum AppState {
     Error,
 }
 
+impl From<InstallStage> for AppState {
+    fn from(stage: InstallStage) -> Self {
+        match stage {
+            InstallStage::SavingUserData => AppState::BackingUp,
+            InstallStage::BackingUp => AppState::BackingUp,
+            InstallStage::CheckingCapacity => AppState::CheckingCapacity,
+            InstallStage::Benchmarking => AppState::Benchmarking,
+            InstallStage::Formatting => AppState::Formatting,
+            InstallStage::Deleting => AppState::Deleting,
+            InstallStage::Downloading => AppState::Downloading,
+            InstallStage::Burning => AppState::Burning,
+            InstallStage::Extracting => AppState::Extracting,
+            InstallStage::Copying => AppState::Copying,
+            InstallStage::Verifying => AppState::Verifying,
+            InstallStage::RestoringUserData => AppState::Copying,
+        }
+    }
+}
+
+/// manifest.json and SHA256SUMS of a release (Err if their signature checks fail)
+pub(super) type ReleaseExtras = Result<(Option<crate::manifest::Manifest>, Checksums), String>;
+
 #[derive(Debug, Clone)]
 pub struct ProgressInfo {
     pub current: u64,
@@ -49,6 +79,16 @@ pub struct InstallerApp {
     // HIDE UPDATE MODE: To completely remove, delete this field and all references
     // (Easier approach: just hide the checkbox in ui.rs - this field stays but is unused)
     pub(super) update_mode: bool,
+    // Back the card up to an image file before it is erased (asks for the file on install)
+    pub(super) backup_before_erase: bool,
+    pub(super) backup_used_only: bool,
+    // Save the repo's backup_directories to the computer first, optionally copying them back afterwards
+    pub(super) backup_user_data: bool,
+    pub(super) restore_user_data: bool,
+    // Probe the card for fake capacity before erasing it
+    pub(super) check_capacity: bool,
+    // Measure the card's speed before erasing it
+    pub(super) benchmark_speed: bool,
 
     // Progress tracking
     pub(super) state: AppState,
@@ -61,21 +101,36 @@ pub struct InstallerApp {
     // Cancellation token for aborting installation
     pub(super) cancel_token: Option<CancellationToken>,
 
+    // Events from the running install pipeline
+    pub(super) install_rx: Option<mpsc::UnboundedReceiver<InstallEvent>>,
+
     // Channel for background drive updates
     pub(super) drive_rx: mpsc::UnboundedReceiver<Vec<DriveInfo>>,
     pub(super) drive_poll_tx: mpsc::UnboundedSender<bool>,
     pub(super) manual_refresh_tx: mpsc::UnboundedSender<()>,
 
+    // Release selection (chosen_release None = latest)
+    pub(super) releases_rx: Option<mpsc::UnboundedReceiver<Result<Vec<Release>, String>>>,
+    pub(super) available_releases: Vec<Release>,
+    pub(super) selected_release_idx: Option<usize>,
+    pub(super) chosen_release: Option<Release>,
+
     // Asset selection
     pub(super) fetched_release: Option<Release>,
     pub(super) available_assets: Vec<Asset>,
     pub(super) selected_asset_idx: Option<usize>,
+    // Archive/image picked with "Use local file…" (replaces the release download)
+    pub(super) local_file: Option<std::path::PathBuf>,
     pub(super) release_rx: Option<mpsc::UnboundedReceiver<Result<Release, String>>>,
 
-    // Manifest support for external asset hosting
-    pub(super) manifest_rx: Option<mpsc::UnboundedReceiver<Option<crate::manifest::Manifest>>>,
+    // Manifest support for external asset hosting (plus checksums from SHA256SUMS)
+    pub(super) manifest_rx: Option<mpsc::UnboundedReceiver<ReleaseExtras>>,
     pub(super) pending_release: Option<(Release, Option<&'static [&'static str]>)>,
 
+    // Download cache (None if disabled in config.rs) and its size, measured when it changes
+    pub(super) download_cache: Option<DownloadCache>,
+    pub(super) download_cache_size: u64,
+
     // Theme editor
     pub(super) theme_state: ThemeEditorState,
     pub(super) show_theme_editor: bool,
@@ -83,57 +138,6 @@ pub struct InstallerApp {
     pub(super) last_system_dark_mode: bool,
 }
 
-/// Get available disk space for a given path (in bytes)
-pub fn get_available_disk_space(path: &std::path::Path) -> u64 {
-    #[cfg(target_os = "windows")]
-    {
-        use std::os::windows::ffi::OsStrExt;
-        use windows::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
-
-        let path_wide: Vec<u16> = path.as_os_str()
-            .encode_wide()
-            .chain(Some(0))
-            .collect();
-
-        let mut free_bytes = 0u64;
-        unsafe {
-            if GetDiskFreeSpaceExW(
-                windows::core::PCWSTR(path_wide.as_ptr()),
-                None,
-                None,
-                Some(&mut free_bytes),
-            ).is_ok() {
-                return free_bytes;
-            }
-        }
-        crate::debug::log("WARNING: Failed to get disk space on Windows, assuming sufficient space");
-        u64::MAX // Assume sufficient space if we can't check
-    }
-
-    #[cfg(any(target_os = "linux", target_os = "macos"))]
-    {
-        use std::os::unix::ffi::OsStrExt;
-        let path_cstr = std::ffi::CString::new(path.as_os_str().as_bytes()).unwrap_or_default();
-        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
-
-        unsafe {
-            if libc::statvfs(path_cstr.as_ptr(), &mut stat) == 0 {
-                // Available space = block size * available blocks
-                // Cast both to u64 to handle platforms where they're u32 (macOS, ARM32)
-                return (stat.f_bavail as u64) * (stat.f_bsize as u64);
-            }
-        }
-        crate::debug::log("WARNING: Failed to get disk space on Unix, assuming sufficient space");
-        u64::MAX // Assume sufficient space if we can't check
-    }
-
-    #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
-    {
-        crate::debug::log("WARNING: Disk space check not supported on this platform");
-        u64::MAX // Assume sufficient space on unsupported platforms
-    }
-}
-
 impl InstallerApp {
     pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
         // Apply theme from config
@@ -186,6 +190,12 @@ impl InstallerApp {
             selected_repo_idx: DEFAULT_REPO_INDEX,
             // HIDE UPDATE MODE: Remove this if you delete the update_mode field above
             update_mode: false,
+            backup_before_erase: false,
+            backup_used_only: true,
+            backup_user_data: false,
+            restore_user_data: true,
+            check_capacity: false,
+            benchmark_speed: false,
             state: AppState::Idle,
             progress: Arc::new(Mutex::new(ProgressInfo {
                 current: 0,
@@ -195,15 +205,23 @@ impl InstallerApp {
             log_messages: Arc::new(Mutex::new(Vec::new())),
             installed_drive: None,
             cancel_token: None,
+            install_rx: None,
             drive_rx: rx,
             drive_poll_tx: poll_tx,
             manual_refresh_tx: manual_refresh_tx,
+            releases_rx: None,
+            available_releases: Vec::new(),
+            selected_release_idx: None,
+            chosen_release: None,
             fetched_release: None,
             available_assets: Vec::new(),
             selected_asset_idx: None,
+            local_file: None,
             release_rx: None,
             manifest_rx: None,
             pending_release: None,
+            download_cache: DownloadCache::from_config(),
+            download_cache_size: 0,
             theme_state: ThemeEditorState::default(),
             show_theme_editor: false,
             show_log: false,
@@ -215,6 +233,7 @@ impl InstallerApp {
         // Initial sync load
         app.drives = get_removable_drives();
         app.ensure_selection_valid();
+        app.refresh_download_cache_size();
 
         app
     }
@@ -31,7 +31,8 @@ impl eframe::App for InstallerApp {
         // Show modal dialogs for confirmation or status
         let show_modal = matches!(
             self.state,
-            AppState::SelectingAsset
+            AppState::SelectingRelease
+                | AppState::SelectingAsset
                 | AppState::PreviewingUpdate
                 | AppState::AwaitingConfirmation
                 | AppState::Complete
@@ -63,6 +64,32 @@ impl eframe::App for InstallerApp {
             self.ensure_selection_valid();
         }
 
+        // Check for release list results (version picker)
+        if let Some(rx) = &mut self.releases_rx {
+            if let Ok(result) = rx.try_recv() {
+                match result {
+                    Ok(releases) if releases.is_empty() => {
+                        self.log("No releases found for this repository");
+                        self.state = AppState::Idle;
+                    }
+                    Ok(releases) => {
+                        // Pre-select the currently chosen version, or the newest
+                        self.selected_release_idx = self.chosen_release.as_ref()
+                            .and_then(|chosen| releases.iter().position(|r| r.tag_name == chosen.tag_name))
+                            .or(Some(0));
+                        self.available_releases = releases;
+                        self.state = AppState::SelectingRelease;
+                    }
+                    Err(e) => {
+                        self.log(&format!("Error fetching releases: {}", e));
+                        self.state = AppState::Error;
+                    }
+                }
+                self.releases_rx = None;
+                ctx.request_repaint();
+            }
+        }
+
         // Check for release fetch results
         if let Some(rx) = &mut self.release_rx {
             if let Ok(result) = rx.try_recv() {
@@ -80,8 +107,13 @@ impl eframe::App for InstallerApp {
                         // Spawn async task to check for manifest
                         let (manifest_tx, mut manifest_rx) = mpsc::unbounded_channel();
                         self.runtime.spawn(async move {
-                            let manifest_result = crate::github::get_manifest_from_release(&release_clone).await;
-                            let _ = manifest_tx.send(manifest_result);
+                            let require_signature = repo_option.require_signature;
+                            let result = async {
+                                let manifest = crate::github::get_manifest_from_release(&release_clone, require_signature).await?;
+                                let checksums = crate::github::get_checksums_from_release(&release_clone, require_signature).await?;
+                                Ok((manifest, checksums))
+                            }.await;
+                            let _ = manifest_tx.send(result);
                             ctx_clone.request_repaint();
                         });
 
@@ -103,79 +135,87 @@ impl eframe::App for InstallerApp {
 
         // Check for manifest fetch results
         if let Some(rx) = &mut self.manifest_rx {
-            if let Ok(manifest_opt) = rx.try_recv() {
-                // Get the pending release
-                if let Some((release, allowed_extensions)) = self.pending_release.take() {
-                    // Determine which assets to use
-                    let mut assets = if let Some(manifest) = manifest_opt {
-                        self.log("Using external assets from manifest.json");
-                        crate::debug::log("Converting manifest assets to Asset structs");
-
-                        // Convert manifest assets to Asset structs
-                        let manifest_assets: Vec<crate::github::Asset> = manifest.assets
-                            .into_iter()
-                            .map(|ma| ma.into())
-                            .collect();
-
-                        // Still apply extension filtering to manifest assets
-                        Self::filter_assets(manifest_assets, allowed_extensions)
-                    } else {
-                        // No manifest found, use GitHub assets
-                        crate::debug::log("No manifest found, using GitHub release assets");
-                        Self::filter_assets(release.assets.clone(), allowed_extensions)
-                    };
-
-                    // Continue with existing asset processing logic
-                    if assets.is_empty() {
-                        let msg = if allowed_extensions.is_some() {
-                            "No compatible files found matching the allowed extensions for this repository"
-                        } else {
-                            "No compatible files found in release"
-                        };
-                        self.log(msg);
+            if let Ok(result) = rx.try_recv() {
+                match result {
+                    Err(e) => {
+                        // Mis-signed (or required but unsigned) manifest/checksums
+                        self.log(&format!("Error verifying release: {}", e));
                         self.state = AppState::Error;
+                        self.pending_release = None;
                         self.manifest_rx = None;
-                    } else {
-                        // Sort assets alphabetically
-                        assets.sort_by(|a, b| a.name.cmp(&b.name));
+                    }
+                    Ok((manifest_opt, checksums)) => {
+                        // Get the pending release
+                        if let Some((release, allowed_extensions)) = self.pending_release.take() {
+                            // Determine which assets to use
+                            let mut assets = if let Some(manifest) = manifest_opt {
+                                self.log("Using external assets from manifest.json");
+                                crate::debug::log("Converting manifest assets to Asset structs");
+
+                                // Convert manifest assets to Asset structs
+                                let manifest_assets: Vec<crate::github::Asset> = manifest.assets
+                                    .into_iter()
+                                    .map(|ma| ma.into())
+                                    .collect();
+
+                                // Still apply extension filtering to manifest assets
+                                Self::filter_assets(manifest_assets, allowed_extensions)
+                            } else {
+                                // No manifest found, use GitHub assets
+                                crate::debug::log("No manifest found, using GitHub release assets");
+                                Self::filter_assets(release.assets.clone(), allowed_extensions)
+                            };
+                            crate::github::apply_checksums(&mut assets, &checksums);
+                            crate::github::apply_bmaps(&mut assets, &release, &checksums);
 
-                        // Check if we should auto-select
-                        let (should_auto, auto_idx) = Self::should_auto_select(&assets);
+                            // Continue with existing asset processing logic
+                            if assets.is_empty() {
+                                let msg = if allowed_extensions.is_some() {
+                                    "No compatible files found matching the allowed extensions for this repository"
+                                } else {
+                                    "No compatible files found in release"
+                                };
+                                self.log(msg);
+                                self.state = AppState::Error;
+                                self.manifest_rx = None;
+                            } else {
+                                // Sort assets alphabetically
+                                assets.sort_by(|a, b| a.name.cmp(&b.name));
 
-                        self.fetched_release = Some(release);
-                        self.available_assets = assets;
+                                // Check if we should auto-select
+                                let (should_auto, auto_idx) = Self::should_auto_select(&assets);
 
-                        if should_auto {
-                            // Auto-select and proceed
-                            self.selected_asset_idx = auto_idx;
-                            if let Some(idx) = auto_idx {
-                                self.log(&format!("Auto-selected: {}", self.available_assets[idx].name));
-                            }
+                                self.fetched_release = Some(release);
+                                self.available_assets = assets;
 
-                            // Check if selected asset is a raw image
-                            let is_raw_image = if let Some(idx) = auto_idx {
-                                let asset_name = &self.available_assets[idx].name;
-                                asset_name.ends_with(".img.gz") ||
-                                asset_name.ends_with(".img.xz") ||
-                                asset_name.ends_with(".img")
-                            } else {
-                                false
-                            };
+                                if should_auto {
+                                    // Auto-select and proceed
+                                    self.selected_asset_idx = auto_idx;
+                                    if let Some(idx) = auto_idx {
+                                        self.log(&format!("Auto-selected: {}", self.available_assets[idx].name));
+                                    }
 
-                            // If update mode and NOT a raw image, show preview modal; otherwise go to confirmation
-                            if self.update_mode && !is_raw_image {
-                                self.state = AppState::PreviewingUpdate;
-                            } else {
-                                // Skip preview for image mode (update mode doesn't apply to raw images)
-                                self.state = AppState::AwaitingConfirmation;
+                                    // Check if selected asset is a raw image
+                                    let is_raw_image = auto_idx
+                                        .map(|idx| crate::pipeline::is_raw_image(&self.available_assets[idx].name))
+                                        .unwrap_or(false);
+
+                                    // If update mode and NOT a raw image, show preview modal; otherwise go to confirmation
+                                    if self.update_mode && !is_raw_image {
+                                        self.state = AppState::PreviewingUpdate;
+                                    } else {
+                                        // Skip preview for image mode (update mode doesn't apply to raw images)
+                                        self.state = AppState::AwaitingConfirmation;
+                                    }
+                                } else {
+                                    // Show asset selection UI
+                                    self.selected_asset_idx = Some(0); // Pre-select first item
+                                    self.state = AppState::SelectingAsset;
+                                }
+
+                                self.manifest_rx = None;
                             }
-                        } else {
-                            // Show asset selection UI
-                            self.selected_asset_idx = Some(0); // Pre-select first item
-                            self.state = AppState::SelectingAsset;
                         }
-
-                        self.manifest_rx = None;
                     }
                 }
                 ctx.request_repaint();
@@ -196,58 +236,33 @@ impl eframe::App for InstallerApp {
             }
         }
 
-        // Check for state updates from main installation process
-        if let Ok(mut progress) = self.progress.lock() {
-            if progress.message == "COMPLETE" {
-                self.state = AppState::Complete;
-                self.cancel_token = None;
-                self.update_mode = false; // Reset update mode
-                progress.message.clear();
-            } else if progress.message == "ERROR" {
-                self.state = AppState::Error;
-                self.cancel_token = None;
-                self.update_mode = false; // Reset update mode
-                progress.message.clear();
-            } else if progress.message == "CANCELLED" {
-                self.state = AppState::Idle;
-                self.cancel_token = None;
-                self.update_mode = false; // Reset update mode
-                progress.message.clear();
-            } else {
-                // Update state based on progress message
-                if progress.message.contains("Downloading") {
-                    self.state = AppState::Downloading;
-                } else if progress.message.contains("Formatting")
-                    || progress.message.contains("format")
-                    || progress.message.contains("Unmounting")
-                    || progress.message.contains("Cleaning")
-                    || progress.message.contains("partition")
-                {
-                    self.state = AppState::Formatting;
-                } else if progress.message.contains("Deleting") || progress.message.contains("deletion")
-                {
-                    self.state = AppState::Deleting;
-                } else if progress.message.contains("Extracting") || progress.message.contains("Extract")
-                {
-                    self.state = AppState::Extracting;
-                } else if progress.message.contains("Copying") || progress.message.contains("Copy")
-                {
-                    self.state = AppState::Copying;
-                }
+        // Apply events from the install pipeline
+        if let Some(rx) = &mut self.install_rx {
+            let mut events = Vec::new();
+            while let Ok(event) = rx.try_recv() {
+                events.push(event);
+            }
+            for event in events {
+                self.handle_install_event(event);
             }
         }
 
-
         // Keep requesting repaints while busy so UI stays responsive
         let is_busy = matches!(
             self.state,
-            AppState::FetchingAssets
+            AppState::FetchingReleases
+                | AppState::FetchingAssets
                 | AppState::FetchingRelease
                 | AppState::Downloading
+                | AppState::BackingUp
+                | AppState::CheckingCapacity
+                | AppState::Benchmarking
                 | AppState::Formatting
                 | AppState::Deleting
                 | AppState::Extracting
                 | AppState::Copying
+                | AppState::Verifying
+                | AppState::Burning
                 | AppState::Ejecting
                 | AppState::Cancelling
         );
@@ -269,6 +284,7 @@ impl eframe::App for InstallerApp {
                 .stroke(ctx.style().visuals.window_stroke);
 
             let window_title = match self.state {
+                AppState::SelectingRelease => "Select Version".to_string(),
                 AppState::SelectingAsset => "Select Download".to_string(),
                 AppState::PreviewingUpdate => "Update Confirmation".to_string(),
                 AppState::AwaitingConfirmation => {
@@ -296,6 +312,85 @@ impl eframe::App for InstallerApp {
                 .show(ctx, |ui| {
                     ui.vertical_centered(|ui| {
                         match self.state {
+                            AppState::SelectingRelease => {
+                                ui.add_space(12.0);
+                                ui.heading("Select a version to install:");
+                                ui.add_space(12.0);
+
+                                egui::ScrollArea::vertical()
+                                    .max_height(300.0)
+                                    .show(ui, |ui| {
+                                        for (idx, release) in self.available_releases.iter().enumerate() {
+                                            let is_selected = self.selected_release_idx == Some(idx);
+                                            ui.group(|ui| {
+                                                ui.set_min_width(400.0);
+                                                ui.horizontal(|ui| {
+                                                    if ui.selectable_label(is_selected, &release.tag_name).clicked() {
+                                                        self.selected_release_idx = Some(idx);
+                                                    }
+                                                    ui.label(
+                                                        egui::RichText::new(release.date())
+                                                            .small()
+                                                            .color(ui.style().visuals.weak_text_color())
+                                                    );
+                                                    if release.prerelease {
+                                                        ui.colored_label(ui.visuals().warn_fg_color, "pre-release");
+                                                    }
+                                                });
+                                            });
+                                        }
+                                    });
+
+                                ui.add_space(12.0);
+                                ui.separator();
+                                ui.add_space(8.0);
+
+                                ui.columns(3, |columns| {
+                                    columns[0].allocate_ui_with_layout(
+                                        egui::Vec2::ZERO,
+                                        egui::Layout::right_to_left(egui::Align::Center),
+                                        |ui| {
+                                            if ui.button("Cancel").clicked() {
+                                                self.state = AppState::Idle;
+                                                self.available_releases.clear();
+                                            }
+                                        },
+                                    );
+
+                                    columns[1].allocate_ui_with_layout(
+                                        egui::Vec2::ZERO,
+                                        egui::Layout::top_down(egui::Align::Center),
+                                        |ui| {
+                                            if ui.button("Use Latest").clicked() {
+                                                self.chosen_release = None;
+                                                self.log("Version: latest release");
+                                                self.state = AppState::Idle;
+                                                self.available_releases.clear();
+                                            }
+                                        },
+                                    );
+
+                                    columns[2].allocate_ui_with_layout(
+                                        egui::Vec2::ZERO,
+                                        egui::Layout::left_to_right(egui::Align::Center),
+                                        |ui| {
+                                            let can_select = self.selected_release_idx.is_some();
+                                            ui.add_enabled_ui(can_select, |ui| {
+                                                if ui.button("Select").clicked() {
+                                                    if let Some(release) = self.selected_release_idx
+                                                        .and_then(|idx| self.available_releases.get(idx))
+                                                    {
+                                                        self.log(&format!("Version: {}", release.summary()));
+                                                        self.chosen_release = Some(release.clone());
+                                                    }
+                                                    self.state = AppState::Idle;
+                                                    self.available_releases.clear();
+                                                }
+                                            });
+                                        },
+                                    );
+                                });
+                            }
                             AppState::SelectingAsset => {
                                 ui.add_space(12.0);
                                 ui.heading("Select a file to install:");
@@ -426,14 +521,9 @@ impl eframe::App for InstallerApp {
                                             ui.add_enabled_ui(can_continue, |ui| {
                                                 if ui.button("Continue").clicked() {
                                                     // Check if selected asset is a raw image
-                                                    let is_raw_image = if let Some(idx) = self.selected_asset_idx {
-                                                        let asset_name = &self.available_assets[idx].name;
-                                                        asset_name.ends_with(".img.gz") ||
-                                                        asset_name.ends_with(".img.xz") ||
-                                                        asset_name.ends_with(".img")
-                                                    } else {
-                                                        false
-                                                    };
+                                                    let is_raw_image = self.selected_asset_idx
+                                                        .map(|idx| crate::pipeline::is_raw_image(&self.available_assets[idx].name))
+                                                        .unwrap_or(false);
 
                                                     // If update mode and NOT a raw image, show preview; otherwise go to confirmation
                                                     if self.update_mode && !is_raw_image {
@@ -483,6 +573,7 @@ impl eframe::App for InstallerApp {
                                             if ui.button("Cancel").clicked() {
                                                 self.state = AppState::Idle;
                                                 self.update_mode = false;
+                                                self.local_file = None;
                                                 self.fetched_release = None;
                                                 self.available_assets.clear();
                                                 self.selected_asset_idx = None;
@@ -520,6 +611,11 @@ impl eframe::App for InstallerApp {
                                         }
                                     }
 
+                                    if let Some(name) = self.local_file.as_ref().and_then(|p| p.file_name()) {
+                                        ui.add_space(8.0);
+                                        ui.label(format!("Installing from local file: {}", name.to_string_lossy()));
+                                    }
+
                                     ui.add_space(12.0);
                                     ui.label("Are you sure you want to continue?");
                                 }
@@ -536,6 +632,7 @@ impl eframe::App for InstallerApp {
                                             if ui.button("Cancel").clicked() {
                                                 self.state = AppState::Idle;
                                                 self.update_mode = false;
+                                                self.local_file = None;
                                             }
                                         },
                                     );
@@ -725,13 +822,19 @@ impl eframe::App for InstallerApp {
                 ui.add_enabled_ui(!show_modal, |ui| {
                     let show_progress = matches!(
                         self.state,
-                        AppState::FetchingAssets
+                        AppState::FetchingReleases
+                            | AppState::FetchingAssets
                             | AppState::FetchingRelease
                             | AppState::Downloading
+                            | AppState::BackingUp
+                            | AppState::CheckingCapacity
+                            | AppState::Benchmarking
                             | AppState::Formatting
                             | AppState::Deleting
                             | AppState::Extracting
                             | AppState::Copying
+                            | AppState::Verifying
+                            | AppState::Burning
                             | AppState::Cancelling
                     );
 
@@ -781,6 +884,12 @@ impl eframe::App for InstallerApp {
                                 };
                                 ctx.send_viewport_cmd(egui::ViewportCommand::InnerSize(egui::vec2(new_width, current_size.y)));
                             }
+                            if self.download_cache.is_some() {
+                                let hover = format!("Clear download cache ({} MB)", self.download_cache_size / 1_048_576);
+                                if ui.add_enabled(!show_progress, egui::Button::new("🗑")).on_hover_text(hover).clicked() {
+                                    self.clear_download_cache();
+                                }
+                            }
                             },
                     );
                 });
@@ -856,8 +965,10 @@ impl eframe::App for InstallerApp {
                                         if ui.add(egui::Button::selectable(
                                             self.selected_repo_idx == idx,
                                             repo.name,
-                                        ).frame_when_inactive(true)).clicked() {
+                                        ).frame_when_inactive(true)).clicked() && self.selected_repo_idx != idx {
                                             self.selected_repo_idx = idx;
+                                            // A chosen version belongs to the previous repository
+                                            self.chosen_release = None;
                                         }
                                     });
                                 }
@@ -868,6 +979,23 @@ impl eframe::App for InstallerApp {
 
                 ui.add_space(8.0);
 
+                // Version picker (latest release unless a specific one was chosen)
+                if !show_progress {
+                    ui.horizontal(|ui| {
+                        ui.vertical_centered(|ui| {
+                            let version_text = match &self.chosen_release {
+                                Some(release) => format!("Version: {}", release.summary()),
+                                None => "Version: latest".to_string(),
+                            };
+                            ui.label(egui::RichText::new(version_text).small());
+                            if ui.small_button("Choose version…").clicked() {
+                                self.fetch_releases(ctx.clone());
+                            }
+                        });
+                    });
+                    ui.add_space(4.0);
+                }
+
                 // ========================================================================
                 // HIDE UPDATE MODE: Comment out this entire block to disable the feature
                 // ========================================================================
@@ -903,6 +1031,37 @@ impl eframe::App for InstallerApp {
                 // END HIDE UPDATE MODE - Comment through here to disable the checkbox
                 // ========================================================================
 
+                // Backup and card check options (only when the card is going to be erased)
+                if !show_progress && !self.update_mode {
+                    ui.vertical_centered(|ui| {
+                        let backup_directories = REPO_OPTIONS[self.selected_repo_idx].backup_directories;
+                        if !backup_directories.is_empty() {
+                            ui.checkbox(&mut self.backup_user_data, format!("Keep {}", backup_directories.join(", ")))
+                                .on_hover_text("Copy these folders from the card to this computer before it is erased");
+                            if self.backup_user_data {
+                                ui.checkbox(&mut self.restore_user_data, "Copy them back after installing");
+                            }
+                        }
+
+                        ui.checkbox(&mut self.backup_before_erase, "Back up card before erasing")
+                            .on_hover_text("Save the current contents of the card to a .img.zst or .img.gz file first");
+                        if self.backup_before_erase {
+                            ui.checkbox(&mut self.backup_used_only, "Only the used partitions")
+                                .on_hover_text("Skip the unpartitioned space at the end of the card");
+                        }
+                        ui.checkbox(&mut self.check_capacity, "Check for fake capacity")
+                            .on_hover_text("Write and read back test blocks across the whole card to catch counterfeit cards (original data is kept)");
+                    });
+                }
+
+                // Speed test (raw before erasing, through a test file in update mode)
+                if !show_progress {
+                    ui.vertical_centered(|ui| {
+                        ui.checkbox(&mut self.benchmark_speed, "Test card speed")
+                            .on_hover_text("Measure sequential and random read/write speed and estimate the card's speed class (A1/A2, U1/U3) - shown in the log");
+                    });
+                }
+
                 ui.add_space(12.0);
 
                 // Progress bar
@@ -927,7 +1086,7 @@ impl eframe::App for InstallerApp {
                             // Downloading, Formatting, Extracting, and Copying report percentages
                             let is_indeterminate = matches!(
                                 self.state,
-                                AppState::FetchingAssets | AppState::FetchingRelease | AppState::Deleting | AppState::Idle
+                                AppState::FetchingReleases | AppState::FetchingAssets | AppState::FetchingRelease | AppState::Deleting | AppState::Idle
                             );
 
                             if is_indeterminate {
@@ -985,15 +1144,22 @@ impl eframe::App for InstallerApp {
                         // Install button
                         let is_busy = matches!(
                             self.state,
-                            AppState::FetchingAssets
+                            AppState::FetchingReleases
+                                | AppState::SelectingRelease
+                                | AppState::FetchingAssets
                                 | AppState::SelectingAsset
                                 | AppState::PreviewingUpdate
                                 | AppState::FetchingRelease
                                 | AppState::Downloading
+                                | AppState::BackingUp
+                                | AppState::CheckingCapacity
+                                | AppState::Benchmarking
                                 | AppState::Formatting
                                 | AppState::Deleting
                                 | AppState::Extracting
                                 | AppState::Copying
+                                | AppState::Verifying
+                                | AppState::Burning
                                 | AppState::AwaitingConfirmation
                                 | AppState::Ejecting
                                 | AppState::Cancelling
@@ -1007,6 +1173,14 @@ impl eframe::App for InstallerApp {
                                 if ui.add(button).clicked() {
                                     self.fetch_and_check_assets(ctx.clone());
                                 }
+
+                                ui.add_space(6.0);
+                                if ui.small_button("Use local file…")
+                                    .on_hover_text("Install an archive (.7z, .zip, .tar.*) or disk image from this computer")
+                                    .clicked()
+                                {
+                                    self.pick_local_file();
+                                }
                             });
                         }
 
@@ -1015,10 +1189,15 @@ impl eframe::App for InstallerApp {
                             self.state,
                             AppState::FetchingRelease
                                 | AppState::Downloading
+                                | AppState::BackingUp
+                                | AppState::CheckingCapacity
+                                | AppState::Benchmarking
                                 | AppState::Formatting
                                 | AppState::Deleting
                                 | AppState::Extracting
                                 | AppState::Copying
+                                | AppState::Verifying
+                                | AppState::Burning
                         ) && self.cancel_token.is_some();
 
                         if can_cancel {
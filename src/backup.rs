@@ -0,0 +1,427 @@
+// Copyright (C) 2026 SpruceOS Team
+// Licensed under GPL-3.0-or-later
+
+// SD card backup
+// Reads a whole card (or just the part covered by its partitions) into a
+// compressed image that can be burned back later like any .img.gz/.img.zst,
+// and saves user data folders (saves, ROMs, BIOS) to the computer before a
+// fresh install so they can be copied back afterwards.
+
+use crate::burn::{device_size, open_device_for_reading, unmount_device};
+use crate::compression::Compression;
+use crate::drives::DriveInfo;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::sync::CancellationToken;
+
+const CHUNK_SIZE: usize = 4 * 1024 * 1024; // 4MB chunks
+const SECTOR_SIZE: u64 = 512;
+
+#[derive(Debug, Clone)]
+pub enum BackupProgress {
+    Started { total_bytes: u64 },
+    Reading { read: u64, total: u64 },
+    Completed,
+    Cancelled,
+    Error(String),
+}
+
+/// How much of the card to back up
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackupExtent {
+    /// Every byte of the device
+    #[default]
+    WholeDevice,
+    /// Up to the end of the last partition in the MBR/GPT (whole device if there is none)
+    UsedPartitions,
+}
+
+/// Where to back a card up to before it is erased
+#[derive(Debug, Clone)]
+pub struct BackupTarget {
+    /// .img.gz or .img.zst file to create
+    pub path: PathBuf,
+    pub extent: BackupExtent,
+}
+
+/// Backup file extensions offered in save dialogs (without the leading dot)
+pub const BACKUP_EXTENSIONS: &[&str] = &["img.zst", "img.gz"];
+
+/// Compression of a backup file, from its name
+pub fn backup_compression(path: &Path) -> Result<Compression, String> {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if name.ends_with(".gz") {
+        Ok(Compression::Gzip)
+    } else if name.ends_with(".zst") {
+        Ok(Compression::Zstd)
+    } else {
+        Err(format!("Backup file must end in .img.gz or .img.zst: {}", path.display()))
+    }
+}
+
+/// Default backup file name for a drive
+pub fn default_backup_name(drive: &DriveInfo) -> String {
+    let name = if drive.label.is_empty() { &drive.name } else { &drive.label };
+    let name: String = name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+        .collect();
+    format!("{}-backup.img.zst", name.trim_matches('_'))
+}
+
+/// Streaming compressor for image files (backups and built card images)
+pub(crate) enum Encoder {
+    Gzip(flate2::write::GzEncoder<std::fs::File>),
+    Zstd(zstd::stream::write::Encoder<'static, std::fs::File>),
+}
+
+impl Encoder {
+    pub(crate) fn new(compression: Compression, file: std::fs::File) -> Result<Self, String> {
+        match compression {
+            Compression::Gzip => Ok(Encoder::Gzip(flate2::write::GzEncoder::new(file, flate2::Compression::new(3)))),
+            Compression::Zstd => zstd::stream::write::Encoder::new(file, 3)
+                .map(Encoder::Zstd)
+                .map_err(|e| format!("Failed to initialise zstd encoder: {}", e)),
+            other => Err(format!("Unsupported image compression: {}", other.name())),
+        }
+    }
+
+    pub(crate) fn write_all(&mut self, data: &[u8]) -> std::io::Result<()> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.write_all(data),
+            Encoder::Zstd(encoder) => encoder.write_all(data),
+        }
+    }
+
+    pub(crate) fn finish(self) -> std::io::Result<std::fs::File> {
+        match self {
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Zstd(encoder) => encoder.finish(),
+        }
+    }
+}
+
+// ============================================================================
+// User Data Folders
+// ============================================================================
+
+/// Save RepoOption::backup_directories to the computer before a fresh install
+#[derive(Debug, Clone)]
+pub struct UserDataBackup {
+    /// Each backup goes into a timestamped folder inside this one
+    pub root: PathBuf,
+    /// Copy the folders back onto the card once the install has finished
+    pub restore: bool,
+}
+
+impl UserDataBackup {
+    pub fn new(restore: bool) -> Self {
+        Self { root: user_data_backup_root(), restore }
+    }
+
+    /// Folder for a backup started at `unix_secs`
+    pub fn dir_for(&self, unix_secs: u64) -> PathBuf {
+        self.root.join(format_utc_timestamp(unix_secs))
+    }
+}
+
+/// Default location for user data backups: "<Documents>/<APP_NAME> Backups"
+pub fn user_data_backup_root() -> PathBuf {
+    dirs::document_dir()
+        .or_else(dirs::home_dir)
+        .unwrap_or_else(std::env::temp_dir)
+        .join(format!("{} Backups", crate::config::APP_NAME))
+}
+
+/// UTC date and time as "YYYY-MM-DD_HH-MM-SS" (safe in file names on every platform)
+pub fn format_utc_timestamp(unix_secs: u64) -> String {
+    let (year, month, day) = utc_date(unix_secs);
+    let secs = unix_secs % 86_400;
+    format!(
+        "{:04}-{:02}-{:02}_{:02}-{:02}-{:02}",
+        year, month, day, secs / 3_600, secs / 60 % 60, secs % 60
+    )
+}
+
+/// UTC (year, month, day) of a Unix time
+pub(crate) fn utc_date(unix_secs: u64) -> (i64, i64, i64) {
+    let days = (unix_secs / 86_400) as i64;
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's days_from_civil inverse)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Find a folder on the card by name, ignoring case (FAT32 doesn't preserve it reliably)
+pub fn find_card_folder(mount_path: &Path, name: &str) -> Option<PathBuf> {
+    let exact = mount_path.join(name);
+    if exact.is_dir() {
+        return Some(exact);
+    }
+    std::fs::read_dir(mount_path)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .find(|path| {
+            path.is_dir() && path.file_name().is_some_and(|n| n.to_string_lossy().eq_ignore_ascii_case(name))
+        })
+}
+
+/// End of the last partition in an MBR or GPT partition table, in bytes
+/// `start` must hold the beginning of the device (the GPT entries usually end at 16.5 KB).
+/// Returns None without a table, or when the table's offsets don't fit in `start` or a u64.
+pub fn partitions_end(start: &[u8]) -> Option<u64> {
+    let sector = SECTOR_SIZE as usize;
+    if start.len() < sector || start[510..512] != [0x55, 0xAA] {
+        return None;
+    }
+
+    let mut end = 0u64;
+    let mut protective = false;
+    for entry in start[446..510].chunks(16) {
+        let kind = entry[4];
+        let first = u32::from_le_bytes(entry[8..12].try_into().ok()?) as u64;
+        let count = u32::from_le_bytes(entry[12..16].try_into().ok()?) as u64;
+        if kind == 0xEE {
+            protective = true;
+        } else if kind != 0 && count > 0 {
+            // Extended partitions (0x05/0x0F) enclose their logical partitions
+            end = end.max((first + count) * SECTOR_SIZE);
+        }
+    }
+
+    if protective {
+        let header = start.get(sector..2 * sector)?;
+        if &header[0..8] != b"EFI PART" {
+            return None;
+        }
+        let entries_lba = u64::from_le_bytes(header[72..80].try_into().ok()?);
+        let entry_count = u32::from_le_bytes(header[80..84].try_into().ok()?) as usize;
+        let entry_size = u32::from_le_bytes(header[84..88].try_into().ok()?) as usize;
+        if entry_size < 128 {
+            return None;
+        }
+
+        let table_start = usize::try_from(entries_lba.checked_mul(SECTOR_SIZE)?).ok()?;
+        let table_end = table_start.checked_add(entry_count.checked_mul(entry_size)?)?;
+        let table = start.get(table_start..table_end)?;
+        for entry in table.chunks(entry_size) {
+            // An all-zero type GUID marks an unused entry
+            if entry[0..16].iter().any(|&b| b != 0) {
+                let last = u64::from_le_bytes(entry[40..48].try_into().ok()?);
+                end = end.max(last.checked_add(1)?.checked_mul(SECTOR_SIZE)?);
+            }
+        }
+    }
+
+    (end > 0).then_some(end)
+}
+
+/// Back up a card to a compressed image file
+/// The file is written as `<path>.partial` and renamed once complete, so an
+/// interrupted backup never looks like a good one. Returns the bytes read from the card.
+pub async fn backup_device(
+    drive: &DriveInfo,
+    target: &BackupTarget,
+    progress_tx: UnboundedSender<BackupProgress>,
+    cancel_token: CancellationToken,
+) -> Result<u64, String> {
+    crate::debug::log_section("Card Backup");
+    crate::debug::log(&format!("Device: {}", drive.device_path));
+    crate::debug::log(&format!("Backup file: {:?} ({:?})", target.path, target.extent));
+
+    let compression = backup_compression(&target.path)?;
+    if let Some(parent) = target.path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| format!("Failed to create backup folder: {}", e))?;
+    }
+
+    // A mounted filesystem could change while it is being read
+    unmount_device(&drive.device_path).await?;
+
+    let mut partial_name = target.path.as_os_str().to_os_string();
+    partial_name.push(".partial");
+    let partial_path = PathBuf::from(partial_name);
+
+    let result = tokio::task::spawn_blocking({
+        let device_path = drive.device_path.clone();
+        let fallback_size = drive.size_bytes;
+        let extent = target.extent;
+        let partial_path = partial_path.clone();
+        let progress_tx = progress_tx.clone();
+        let cancel_token = cancel_token.clone();
+
+        move || -> Result<u64, String> {
+            let mut device = open_device_for_reading(&device_path)?;
+            let device_size = device_size(&device);
+            crate::debug::log(&format!("Device size: {:?} bytes", device_size));
+
+            let file = std::fs::File::create(&partial_path)
+                .map_err(|e| format!("Failed to create backup file: {}", e))?;
+            let mut encoder = Encoder::new(compression, file)?;
+
+            let mut buffer = vec![0u8; CHUNK_SIZE];
+            let first = read_chunk(&mut device, &mut buffer)
+                .map_err(|e| format!("Failed to read device: {}", e))?;
+
+            let limit = match extent {
+                BackupExtent::WholeDevice => device_size,
+                BackupExtent::UsedPartitions => match partitions_end(&buffer[..first]) {
+                    Some(end) => {
+                        crate::debug::log(&format!("Partitions end at {} bytes", end));
+                        Some(device_size.map_or(end, |size| end.min(size)))
+                    }
+                    None => {
+                        crate::debug::log("No usable partition table found, backing up the whole device");
+                        device_size
+                    }
+                },
+            };
+            // Without a known size the device is read to its end; progress uses the reported size
+            let total = limit.unwrap_or(fallback_size);
+            let _ = progress_tx.send(BackupProgress::Started { total_bytes: total });
+
+            let mut read = 0u64;
+            let mut filled = first;
+            loop {
+                if cancel_token.is_cancelled() {
+                    crate::debug::log("Backup cancelled by user");
+                    return Err("Backup cancelled".to_string());
+                }
+
+                let wanted = match limit {
+                    Some(limit) => (limit - read).min(filled as u64) as usize,
+                    None => filled,
+                };
+                if wanted == 0 {
+                    break;
+                }
+
+                encoder.write_all(&buffer[..wanted])
+                    .map_err(|e| format!("Failed to write backup file: {}", e))?;
+                read += wanted as u64;
+                let _ = progress_tx.send(BackupProgress::Reading { read, total: total.max(read) });
+
+                if limit.is_some_and(|limit| read >= limit) {
+                    break;
+                }
+                filled = read_chunk(&mut device, &mut buffer)
+                    .map_err(|e| format!("Failed to read device at offset {}: {}", read, e))?;
+            }
+
+            let file = encoder.finish()
+                .map_err(|e| format!("Failed to write backup file: {}", e))?;
+            file.sync_all()
+                .map_err(|e| format!("Failed to write backup file: {}", e))?;
+
+            crate::debug::log(&format!("Backup read {} bytes", read));
+            Ok(read)
+        }
+    })
+    .await
+    .map_err(|e| format!("Backup task failed: {}", e))?;
+
+    let result = result.and_then(|read| {
+        std::fs::rename(&partial_path, &target.path)
+            .map_err(|e| format!("Failed to save backup file: {}", e))?;
+        Ok(read)
+    });
+
+    match &result {
+        Ok(_) => {
+            let _ = progress_tx.send(BackupProgress::Completed);
+        }
+        Err(_) if cancel_token.is_cancelled() => {
+            let _ = std::fs::remove_file(&partial_path);
+            let _ = progress_tx.send(BackupProgress::Cancelled);
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial_path);
+            let _ = progress_tx.send(BackupProgress::Error(e.clone()));
+        }
+    }
+    result
+}
+
+/// Read until the buffer is full or the device ends
+fn read_chunk(device: &mut std::fs::File, buffer: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        match device.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mbr(partitions: &[(u8, u32, u32)]) -> Vec<u8> {
+        let mut sector = vec![0u8; 512];
+        for (i, (kind, first, count)) in partitions.iter().enumerate() {
+            let entry = &mut sector[446 + i * 16..462 + i * 16];
+            entry[4] = *kind;
+            entry[8..12].copy_from_slice(&first.to_le_bytes());
+            entry[12..16].copy_from_slice(&count.to_le_bytes());
+        }
+        sector[510] = 0x55;
+        sector[511] = 0xAA;
+        sector
+    }
+
+    #[test]
+    fn test_partitions_end() {
+        assert_eq!(partitions_end(&mbr(&[(0x0C, 2048, 100_000), (0x83, 102_048, 50_000)])), Some(152_048 * 512));
+        assert_eq!(partitions_end(&mbr(&[])), None);
+        assert_eq!(partitions_end(&[0u8; 512]), None);
+
+        // GPT: protective MBR, header at LBA 1, entries at LBA 2
+        let mut disk = mbr(&[(0xEE, 1, u32::MAX)]);
+        disk.resize(34 * 512, 0);
+        let header = &mut disk[512..1024];
+        header[0..8].copy_from_slice(b"EFI PART");
+        header[72..80].copy_from_slice(&2u64.to_le_bytes());
+        header[80..84].copy_from_slice(&128u32.to_le_bytes());
+        header[84..88].copy_from_slice(&128u32.to_le_bytes());
+        for (i, last) in [(0usize, 411_647u64), (1, 8_000_000)] {
+            let entry = &mut disk[1024 + i * 128..1152 + i * 128];
+            entry[0] = 0xAF;
+            entry[40..48].copy_from_slice(&last.to_le_bytes());
+        }
+        assert_eq!(partitions_end(&disk), Some(8_000_001 * 512));
+
+        // Corrupt offsets fall back to a whole-device backup instead of overflowing
+        let mut bad_end = disk.clone();
+        bad_end[1024 + 40..1024 + 48].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(partitions_end(&bad_end), None);
+        disk[512 + 72..512 + 80].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(partitions_end(&disk), None);
+    }
+
+    #[test]
+    fn test_format_utc_timestamp() {
+        assert_eq!(format_utc_timestamp(0), "1970-01-01_00-00-00");
+        assert_eq!(format_utc_timestamp(951_782_400), "2000-02-29_00-00-00");
+        assert_eq!(format_utc_timestamp(1_791_471_845), "2026-10-08_15-04-05");
+    }
+
+    #[test]
+    fn test_backup_compression() {
+        assert_eq!(backup_compression(Path::new("card.img.zst")).unwrap(), Compression::Zstd);
+        assert_eq!(backup_compression(Path::new("card.IMG.GZ")).unwrap(), Compression::Gzip);
+        assert!(backup_compression(Path::new("card.img")).is_err());
+    }
+}
@@ -0,0 +1,375 @@
+// Copyright (C) 2026 SpruceOS Team
+// Licensed under GPL-3.0-or-later
+
+// SD card speed benchmark
+// Measures sequential and 4 KB random throughput around the OS cache and maps the
+// results to the SD Association's speed (Class 10/U1/U3) and application
+// performance (A1/A2) classes. Runs either on a test file in the mounted
+// filesystem (non-destructive) or on the raw device when it is about to be wiped.
+
+use crate::burn::{device_size, open_device_for_writing, unmount_device, DeviceWriter, DEVICE_IO_ALIGNMENT};
+use crate::capacity::xorshift64;
+use crate::drives::DriveInfo;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::sync::CancellationToken;
+
+/// Data written and read sequentially
+const SEQUENTIAL_SIZE: u64 = 64 * 1024 * 1024;
+const SEQUENTIAL_BLOCK_SIZE: usize = 1024 * 1024;
+const RANDOM_BLOCK_SIZE: usize = 4096;
+/// How long each random I/O test runs
+const RANDOM_TEST_DURATION: Duration = Duration::from_secs(3);
+const TEST_FILE_NAME: &str = ".speed_test.tmp";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BenchmarkMode {
+    /// Test file on the mounted filesystem, removed afterwards
+    Filesystem,
+    /// The raw device: overwrites 64 MB in the middle of the card
+    Raw,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BenchmarkTest {
+    SequentialWrite,
+    SequentialRead,
+    RandomWrite,
+    RandomRead,
+}
+
+impl BenchmarkTest {
+    pub fn label(&self) -> &'static str {
+        match self {
+            BenchmarkTest::SequentialWrite => "Sequential write",
+            BenchmarkTest::SequentialRead => "Sequential read",
+            BenchmarkTest::RandomWrite => "Random 4K write",
+            BenchmarkTest::RandomRead => "Random 4K read",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum BenchmarkProgress {
+    Started,
+    Running { test: BenchmarkTest, percent: u8 },
+    Completed,
+    Cancelled,
+    Error(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BenchmarkResult {
+    /// MB/s (10^6 bytes per second, as on card labels)
+    pub sequential_write: f64,
+    pub sequential_read: f64,
+    /// 4 KB operations per second
+    pub random_write_iops: f64,
+    pub random_read_iops: f64,
+}
+
+impl BenchmarkResult {
+    /// Speed class the sequential write speed qualifies for
+    pub fn speed_class(&self) -> &'static str {
+        match self.sequential_write {
+            w if w >= 30.0 => "U3",
+            w if w >= 10.0 => "U1 / Class 10",
+            w if w >= 6.0 => "Class 6",
+            w if w >= 4.0 => "Class 4",
+            _ => "below Class 4",
+        }
+    }
+
+    /// Application performance class (A2: 4000/2000 IOPS, A1: 1500/500 IOPS, both with 10 MB/s sequential)
+    pub fn app_class(&self) -> Option<&'static str> {
+        if self.sequential_write < 10.0 {
+            return None;
+        }
+        if self.random_read_iops >= 4000.0 && self.random_write_iops >= 2000.0 {
+            Some("A2")
+        } else if self.random_read_iops >= 1500.0 && self.random_write_iops >= 500.0 {
+            Some("A1")
+        } else {
+            None
+        }
+    }
+
+    /// e.g. "U3, A1" or "Class 6"
+    pub fn class_estimate(&self) -> String {
+        match self.app_class() {
+            Some(app) => format!("{}, {}", self.speed_class(), app),
+            None => self.speed_class().to_string(),
+        }
+    }
+
+    /// Results as log lines
+    pub fn summary(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("Sequential write: {:.1} MB/s", self.sequential_write),
+            format!("Sequential read: {:.1} MB/s", self.sequential_read),
+            format!("Random 4K write: {:.0} IOPS", self.random_write_iops),
+            format!("Random 4K read: {:.0} IOPS", self.random_read_iops),
+            format!("Estimated class: {}", self.class_estimate()),
+        ];
+        if self.app_class().is_none() {
+            lines.push("Slow random I/O makes menus and booting sluggish; an A1 or A2 card is recommended".to_string());
+        }
+        lines
+    }
+}
+
+/// Where the benchmark reads and writes: a region of a test file or of the device
+struct TestRegion {
+    file: std::fs::File,
+    start: u64,
+    /// Keeps the device open (and, on Windows, its volumes locked) in raw mode
+    _device: Option<DeviceWriter>,
+}
+
+/// Benchmark a card
+/// Filesystem mode needs the card mounted with 64 MB free; raw mode destroys data in the
+/// middle of the card and must only be used when it is about to be wiped.
+pub async fn benchmark_drive(
+    drive: &DriveInfo,
+    mode: BenchmarkMode,
+    progress_tx: UnboundedSender<BenchmarkProgress>,
+    cancel_token: CancellationToken,
+) -> Result<BenchmarkResult, String> {
+    crate::debug::log_section("Speed Benchmark");
+    crate::debug::log(&format!("Device: {} ({:?})", drive.device_path, mode));
+
+    let test_file = match mode {
+        BenchmarkMode::Filesystem => {
+            let mount_path = drive.mount_path.as_ref()
+                .ok_or("The card must be mounted to test it without erasing it")?;
+            let available = crate::pipeline::get_available_disk_space(mount_path);
+            if available < SEQUENTIAL_SIZE + (16 << 20) {
+                return Err(format!(
+                    "Not enough free space on the card for a speed test (need {} MB)",
+                    SEQUENTIAL_SIZE / 1_048_576 + 16
+                ));
+            }
+            Some(mount_path.join(TEST_FILE_NAME))
+        }
+        BenchmarkMode::Raw => {
+            unmount_device(&drive.device_path).await?;
+            None
+        }
+    };
+
+    let _ = progress_tx.send(BenchmarkProgress::Started);
+    let result = tokio::task::spawn_blocking({
+        let device_path = drive.device_path.clone();
+        let test_file = test_file.clone();
+        let progress_tx = progress_tx.clone();
+        let cancel_token = cancel_token.clone();
+
+        move || -> Result<BenchmarkResult, String> {
+            let mut region = match &test_file {
+                Some(path) => TestRegion {
+                    file: open_test_file(path)?,
+                    start: 0,
+                    _device: None,
+                },
+                None => {
+                    let device = open_device_for_writing(&device_path)?;
+                    let size = device_size(&device.file).unwrap_or(0);
+                    if size < SEQUENTIAL_SIZE * 2 {
+                        return Err(format!("Device is too small to test ({} bytes)", size));
+                    }
+                    // Away from the partition tables at both ends of the card
+                    let start = (size / 2) / DEVICE_IO_ALIGNMENT as u64 * DEVICE_IO_ALIGNMENT as u64;
+                    TestRegion {
+                        file: device.file.try_clone().map_err(|e| format!("Failed to open device: {}", e))?,
+                        start,
+                        _device: Some(device),
+                    }
+                }
+            };
+            run_tests(&mut region, &progress_tx, &cancel_token)
+        }
+    })
+    .await
+    .map_err(|e| format!("Benchmark task failed: {}", e))?;
+
+    if let Some(path) = &test_file {
+        let _ = std::fs::remove_file(path);
+    }
+
+    match &result {
+        Ok(result) => {
+            for line in result.summary() {
+                crate::debug::log(&line);
+            }
+            let _ = progress_tx.send(BenchmarkProgress::Completed);
+        }
+        Err(_) if cancel_token.is_cancelled() => {
+            let _ = progress_tx.send(BenchmarkProgress::Cancelled);
+        }
+        Err(e) => {
+            let _ = progress_tx.send(BenchmarkProgress::Error(e.clone()));
+        }
+    }
+    result
+}
+
+fn run_tests(
+    region: &mut TestRegion,
+    progress_tx: &UnboundedSender<BenchmarkProgress>,
+    cancel_token: &CancellationToken,
+) -> Result<BenchmarkResult, String> {
+    // Unbuffered I/O needs an aligned buffer; random data so compressing controllers can't cheat
+    let mut storage = vec![0u8; SEQUENTIAL_BLOCK_SIZE + DEVICE_IO_ALIGNMENT];
+    let offset = storage.as_ptr().align_offset(DEVICE_IO_ALIGNMENT);
+    let buffer = &mut storage[offset..offset + SEQUENTIAL_BLOCK_SIZE];
+    let mut state = 0x9E37_79B9_7F4A_7C15u64;
+    for chunk in buffer.chunks_mut(8) {
+        chunk.copy_from_slice(&xorshift64(&mut state).to_le_bytes());
+    }
+
+    let check_cancel = || {
+        if cancel_token.is_cancelled() {
+            crate::debug::log("Benchmark cancelled by user");
+            Err("Benchmark cancelled".to_string())
+        } else {
+            Ok(())
+        }
+    };
+    let io_error = |test: BenchmarkTest| move |e: std::io::Error| format!("{} failed: {}", test.label(), e);
+    let report = |test: BenchmarkTest, percent: u64| {
+        let _ = progress_tx.send(BenchmarkProgress::Running { test, percent: percent.min(100) as u8 });
+    };
+    let blocks = SEQUENTIAL_SIZE / SEQUENTIAL_BLOCK_SIZE as u64;
+
+    // Sequential write (including the final flush)
+    let test = BenchmarkTest::SequentialWrite;
+    region.file.seek(SeekFrom::Start(region.start)).map_err(io_error(test))?;
+    let started = Instant::now();
+    for i in 0..blocks {
+        check_cancel()?;
+        region.file.write_all(buffer).map_err(io_error(test))?;
+        report(test, i * 100 / blocks);
+    }
+    region.file.sync_all().map_err(io_error(test))?;
+    let sequential_write = mb_per_sec(SEQUENTIAL_SIZE, started.elapsed());
+
+    // Sequential read
+    let test = BenchmarkTest::SequentialRead;
+    region.file.seek(SeekFrom::Start(region.start)).map_err(io_error(test))?;
+    let started = Instant::now();
+    for i in 0..blocks {
+        check_cancel()?;
+        region.file.read_exact(buffer).map_err(io_error(test))?;
+        report(test, i * 100 / blocks);
+    }
+    let sequential_read = mb_per_sec(SEQUENTIAL_SIZE, started.elapsed());
+
+    // Random 4K writes and reads, aligned, anywhere in the region
+    let slots = SEQUENTIAL_SIZE / RANDOM_BLOCK_SIZE as u64;
+    let small = &mut buffer[..RANDOM_BLOCK_SIZE];
+    let mut random_iops = |test: BenchmarkTest, file: &mut std::fs::File, start: u64| -> Result<f64, String> {
+        let started = Instant::now();
+        let mut ops = 0u64;
+        while started.elapsed() < RANDOM_TEST_DURATION {
+            check_cancel()?;
+            let offset = start + xorshift64(&mut state) % slots * RANDOM_BLOCK_SIZE as u64;
+            file.seek(SeekFrom::Start(offset)).map_err(io_error(test))?;
+            match test {
+                BenchmarkTest::RandomWrite => file.write_all(small),
+                _ => file.read_exact(small),
+            }
+            .map_err(io_error(test))?;
+            ops += 1;
+            if ops.is_multiple_of(64) {
+                report(test, (started.elapsed().as_millis() * 100 / RANDOM_TEST_DURATION.as_millis()) as u64);
+            }
+        }
+        if test == BenchmarkTest::RandomWrite {
+            file.sync_all().map_err(io_error(test))?;
+        }
+        Ok(ops as f64 / started.elapsed().as_secs_f64())
+    };
+    let random_write_iops = random_iops(BenchmarkTest::RandomWrite, &mut region.file, region.start)?;
+    let random_read_iops = random_iops(BenchmarkTest::RandomRead, &mut region.file, region.start)?;
+
+    Ok(BenchmarkResult { sequential_write, sequential_read, random_write_iops, random_read_iops })
+}
+
+fn mb_per_sec(bytes: u64, elapsed: Duration) -> f64 {
+    bytes as f64 / 1_000_000.0 / elapsed.as_secs_f64().max(0.001)
+}
+
+/// Create the test file so reads and writes go to the card rather than the OS cache
+fn open_test_file(path: &Path) -> Result<std::fs::File, String> {
+    let mut options = std::fs::OpenOptions::new();
+    options.read(true).write(true).create(true).truncate(true);
+
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        use windows::Win32::Storage::FileSystem::{FILE_FLAG_NO_BUFFERING, FILE_FLAG_WRITE_THROUGH};
+        options.custom_flags(FILE_FLAG_NO_BUFFERING.0 | FILE_FLAG_WRITE_THROUGH.0);
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        let mut direct = options.clone();
+        if let Ok(file) = direct.custom_flags(libc::O_DIRECT).open(path) {
+            return Ok(file);
+        }
+        crate::debug::log("O_DIRECT not supported on this filesystem, results may include caching");
+    }
+
+    let file = options.open(path)
+        .map_err(|e| format!("Failed to create speed test file {}: {}", path.display(), e))?;
+
+    #[cfg(target_os = "macos")]
+    unsafe {
+        use std::os::unix::io::AsRawFd;
+        libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1);
+    }
+
+    Ok(file)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn result(write: f64, read_iops: f64, write_iops: f64) -> BenchmarkResult {
+        BenchmarkResult {
+            sequential_write: write,
+            sequential_read: 90.0,
+            random_write_iops: write_iops,
+            random_read_iops: read_iops,
+        }
+    }
+
+    #[test]
+    fn test_class_estimate() {
+        assert_eq!(result(45.0, 4200.0, 2100.0).class_estimate(), "U3, A2");
+        assert_eq!(result(45.0, 4200.0, 900.0).class_estimate(), "U3, A1");
+        assert_eq!(result(12.0, 800.0, 100.0).class_estimate(), "U1 / Class 10");
+        // Application classes also require 10 MB/s sequential
+        assert_eq!(result(8.0, 5000.0, 3000.0).class_estimate(), "Class 6");
+        assert_eq!(result(2.0, 10.0, 1.0).class_estimate(), "below Class 4");
+    }
+
+    #[test]
+    fn test_run_tests_on_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut region = TestRegion {
+            file: std::fs::OpenOptions::new().read(true).write(true).create(true).truncate(true)
+                .open(dir.path().join(TEST_FILE_NAME)).unwrap(),
+            start: 0,
+            _device: None,
+        };
+        let (tx, _rx) = tokio::sync::mpsc::unbounded_channel();
+        let cancel_token = CancellationToken::new();
+        cancel_token.cancel();
+        assert_eq!(run_tests(&mut region, &tx, &cancel_token).unwrap_err(), "Benchmark cancelled");
+    }
+}
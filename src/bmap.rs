@@ -0,0 +1,220 @@
+// Copyright (C) 2026 SpruceOS Team
+// Licensed under GPL-3.0-or-later
+
+// Block maps (bmaptool .bmap files)
+// A .bmap published next to a raw image lists which blocks of the image hold data.
+// Everything else is free space, so burning only has to write (and verify) the
+// mapped ranges - usually a small part of a handheld OS image.
+
+use crate::compression::Compression;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+/// A range of mapped bytes in the (decompressed) image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MappedRange {
+    pub start: u64,
+    /// Exclusive
+    pub end: u64,
+    /// SHA-256 of the range's data (bmap format 2.0 only)
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Bmap {
+    pub image_size: u64,
+    pub block_size: u64,
+    /// Sorted, non-overlapping byte ranges
+    pub ranges: Vec<MappedRange>,
+}
+
+impl Bmap {
+    /// Parse a bmaptool XML block map
+    pub fn parse(xml: &str) -> Result<Self, String> {
+        let number = |tag: &str| -> Result<u64, String> {
+            element_text(xml, tag)
+                .ok_or_else(|| format!("Invalid bmap: missing <{}>", tag))?
+                .parse::<u64>()
+                .map_err(|e| format!("Invalid bmap: bad <{}>: {}", tag, e))
+        };
+        let image_size = number("ImageSize")?;
+        let block_size = number("BlockSize")?;
+        if block_size == 0 || block_size % 512 != 0 {
+            return Err(format!("Invalid bmap: unsupported block size {}", block_size));
+        }
+
+        // Per-range checksums are only SHA-256 in format 2.0 (1.x used SHA-1)
+        let has_sha256 = element_text(xml, "ChecksumType")
+            .is_some_and(|t| t.eq_ignore_ascii_case("sha256"));
+
+        // Format 2.0 checksums the file itself, taken with the checksum's own digits zeroed
+        if let Some(expected) = element_text(xml, "BmapFileChecksum").filter(|_| has_sha256) {
+            let start = expected.as_ptr() as usize - xml.as_ptr() as usize;
+            let zeroed = format!("{}{}{}", &xml[..start], "0".repeat(expected.len()), &xml[start + expected.len()..]);
+            let actual = format!("{:x}", Sha256::digest(zeroed.as_bytes()));
+            if !actual.eq_ignore_ascii_case(expected) {
+                return Err("Invalid bmap: file checksum mismatch (the bmap is corrupt or was modified)".to_string());
+            }
+        }
+
+        let mut ranges: Vec<MappedRange> = Vec::new();
+        let mut rest = xml;
+        while let Some(open) = rest.find("<Range") {
+            rest = &rest[open + "<Range".len()..];
+            let tag_end = rest.find('>').ok_or("Invalid bmap: unterminated <Range>")?;
+            let attributes = &rest[..tag_end];
+            rest = &rest[tag_end + 1..];
+            let close = rest.find("</Range>").ok_or("Invalid bmap: unterminated <Range>")?;
+            let text = rest[..close].trim();
+            rest = &rest[close..];
+
+            let (first, last) = match text.split_once('-') {
+                Some((first, last)) => (first.trim(), last.trim()),
+                None => (text, text),
+            };
+            let first: u64 = first.parse().map_err(|_| format!("Invalid bmap range: {}", text))?;
+            let last: u64 = last.parse().map_err(|_| format!("Invalid bmap range: {}", text))?;
+
+            // Checked, so a corrupt map can't wrap around to a small offset
+            let start = first.checked_mul(block_size);
+            let end = last.checked_add(1).and_then(|blocks| blocks.checked_mul(block_size));
+            let (Some(start), Some(end)) = (start, end) else {
+                return Err(format!("Invalid bmap range: {}", text));
+            };
+            let end = end.min(image_size);
+            if last < first || start >= end || ranges.last().is_some_and(|r| r.end > start) {
+                return Err(format!("Invalid bmap range: {}", text));
+            }
+
+            let sha256 = attribute(attributes, "chksum")
+                .filter(|_| has_sha256)
+                .map(|c| c.to_ascii_lowercase());
+            ranges.push(MappedRange { start, end, sha256 });
+        }
+
+        Ok(Self { image_size, block_size, ranges })
+    }
+
+    /// Total number of mapped bytes
+    pub fn mapped_bytes(&self) -> u64 {
+        self.ranges.iter().map(|r| r.end - r.start).sum()
+    }
+}
+
+fn element_text<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let open = format!("<{}>", tag);
+    let start = xml.find(&open)? + open.len();
+    let end = start + xml[start..].find(&format!("</{}>", tag))?;
+    Some(xml[start..end].trim())
+}
+
+fn attribute<'a>(attributes: &'a str, name: &str) -> Option<&'a str> {
+    let key = format!("{}=\"", name);
+    let start = attributes.find(&key)? + key.len();
+    let end = start + attributes[start..].find('"')?;
+    Some(&attributes[start..end])
+}
+
+/// Names a block map for an image may be published under, most specific first:
+/// `os.img.gz.bmap`, `os.img.bmap`, `os.bmap`
+pub fn bmap_names_for(image_name: &str) -> Vec<String> {
+    let mut names = vec![format!("{}.bmap", image_name)];
+    let mut base = image_name;
+    for ext in [".gz", ".xz", ".zst", ".bz2"] {
+        if let Some(stripped) = base.strip_suffix(ext) {
+            base = stripped;
+            names.push(format!("{}.bmap", base));
+            break;
+        }
+    }
+    if let Some(stripped) = base.strip_suffix(".img") {
+        names.push(format!("{}.bmap", stripped));
+    }
+    names
+}
+
+/// Find a block map next to a local image file
+pub fn find_local_bmap(image_path: &Path) -> Option<PathBuf> {
+    let name = image_path.file_name()?.to_string_lossy().to_string();
+    let dir = image_path.parent()?;
+    bmap_names_for(&name)
+        .into_iter()
+        .map(|bmap_name| dir.join(bmap_name))
+        .find(|path| path.is_file())
+}
+
+/// Load and check a block map against the image it describes
+/// An uncompressed image must be exactly as large as the bmap says.
+pub fn load_for_image(bmap_xml: &str, image_path: &Path) -> Result<Bmap, String> {
+    let bmap = Bmap::parse(bmap_xml)?;
+    if Compression::detect(image_path)? == Compression::None {
+        let size = std::fs::metadata(image_path)
+            .map_err(|e| format!("Failed to get image size: {}", e))?
+            .len();
+        if size != bmap.image_size {
+            return Err(format!("bmap is for a {} byte image, but the image is {} bytes", bmap.image_size, size));
+        }
+    }
+    Ok(bmap)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BMAP: &str = r#"<?xml version="1.0" ?>
+<bmap version="2.0">
+    <ImageSize> 821752 </ImageSize>
+    <BlockSize> 4096 </BlockSize>
+    <BlocksCount> 201 </BlocksCount>
+    <MappedBlocksCount> 117 </MappedBlocksCount>
+    <ChecksumType> sha256 </ChecksumType>
+    <BmapFileChecksum> 0000000000000000000000000000000000000000000000000000000000000000 </BmapFileChecksum>
+    <BlockMap>
+        <Range chksum="9eaf19215d55d23de1be1fe4bed4a95bfe620a404352fd06e782738fff58e500"> 0-1 </Range>
+        <Range chksum="E8A26F49A71262870F8294A73F40F122CCFBF57CFC3E6F6B1A9E9B6D7F2A8E29"> 3 </Range>
+        <Range chksum="f0c6c85d2e1b1e6d6c9a1f2e3d4c5b6a798a7b6c5d4e3f2a1b0c9d8e7f6a5b4c"> 199-200 </Range>
+    </BlockMap>
+</bmap>"#;
+
+    /// Fill in the BmapFileChecksum of `xml`, as bmaptool writes it
+    fn with_checksum(xml: &str) -> String {
+        xml.replacen(&"0".repeat(64), &format!("{:x}", Sha256::digest(xml.as_bytes())), 1)
+    }
+
+    fn parse(xml: &str) -> Result<Bmap, String> {
+        Bmap::parse(&with_checksum(xml))
+    }
+
+    #[test]
+    fn test_parse_bmap() {
+        let bmap = parse(BMAP).unwrap();
+        assert_eq!(bmap.image_size, 821752);
+        assert_eq!(bmap.block_size, 4096);
+        assert_eq!(bmap.ranges.len(), 3);
+        assert_eq!((bmap.ranges[0].start, bmap.ranges[0].end), (0, 8192));
+        assert_eq!((bmap.ranges[1].start, bmap.ranges[1].end), (12288, 16384));
+        // The last block is cut off at the end of the image
+        assert_eq!((bmap.ranges[2].start, bmap.ranges[2].end), (815104, 821752));
+        assert_eq!(bmap.ranges[1].sha256.as_deref(), Some("e8a26f49a71262870f8294a73f40f122ccfbf57cfc3e6f6b1a9e9b6d7f2a8e29"));
+        assert_eq!(bmap.mapped_bytes(), 8192 + 4096 + 6648);
+
+        // Overlapping ranges are rejected
+        assert!(parse(&BMAP.replace("> 3 <", "> 1 <")).is_err());
+        // Reversed or overflowing ranges too
+        assert!(parse(&BMAP.replace("> 3 <", "> 5-3 <")).is_err());
+        assert!(parse(&BMAP.replace("> 3 <", "> 4503599627370496 <")).is_err());
+        assert!(parse(&BMAP.replace("> 3 <", "> 3-18446744073709551615 <")).is_err());
+        assert!(Bmap::parse("<bmap></bmap>").is_err());
+
+        // A bmap edited after it was written fails its file checksum
+        let error = Bmap::parse(&with_checksum(BMAP).replace("> 199-200 <", "> 199 <")).unwrap_err();
+        assert!(error.contains("file checksum mismatch"), "{}", error);
+    }
+
+    #[test]
+    fn test_bmap_names() {
+        assert_eq!(bmap_names_for("os.img.gz"), ["os.img.gz.bmap", "os.img.bmap", "os.bmap"]);
+        assert_eq!(bmap_names_for("os.img"), ["os.img.bmap", "os.bmap"]);
+    }
+}
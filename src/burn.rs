@@ -2,13 +2,100 @@
 // Licensed under GPL-3.0-or-later
 
 use sha2::{Sha256, Digest};
+use std::ops::Range;
 use std::path::Path;
 use tokio::sync::mpsc::UnboundedSender;
 use tokio_util::sync::CancellationToken;
-use flate2::read::GzDecoder;
+use crate::bmap::Bmap;
+use crate::compression::{uncompressed_size_hint, Compression, ImageReader};
 
 const CHUNK_SIZE: usize = 4 * 1024 * 1024; // 4MB chunks
 
+#[derive(Debug, Default)]
+pub struct BurnOptions {
+    /// Decompressed size if known (e.g. from manifest.json), used for progress
+    pub size_hint: Option<u64>,
+    /// Block map of the image: only its mapped ranges are written and verified (Linux)
+    pub bmap: Option<Bmap>,
+}
+
+/// What was written to the device
+struct WrittenImage {
+    /// Decompressed image size
+    size: u64,
+    /// SHA256 of the written ranges, in order (of the whole image unless the burn was sparse)
+    sha256: String,
+    /// Byte ranges written, when only parts of the image were
+    ranges: Option<Vec<Range<u64>>>,
+}
+
+/// The decompressed image being burned
+/// Hashes and counts the bytes as they are read, so the source is only decompressed once.
+struct BurnSource {
+    image: ImageReader,
+    hasher: Sha256,
+    bytes_read: u64,
+    size_hint: Option<u64>,
+}
+
+impl BurnSource {
+    fn open(image_path: &Path, size_hint: Option<u64>) -> Result<Self, String> {
+        Ok(Self {
+            image: ImageReader::open(image_path)?,
+            hasher: Sha256::new(),
+            bytes_read: 0,
+            size_hint,
+        })
+    }
+
+    /// Total size for progress: the known size while it holds, otherwise
+    /// extrapolated from the compression ratio so far
+    fn estimated_total(&self) -> u64 {
+        if let Some(size) = self.size_hint.filter(|&size| size >= self.bytes_read) {
+            return size;
+        }
+        let consumed = self.image.compressed_read();
+        if consumed == 0 {
+            return self.image.compressed_size().max(self.bytes_read);
+        }
+        let ratio = self.bytes_read as f64 / consumed as f64;
+        ((self.image.compressed_size() as f64 * ratio) as u64).max(self.bytes_read)
+    }
+
+    fn finish(self) -> WrittenImage {
+        WrittenImage {
+            size: self.bytes_read,
+            sha256: format!("{:x}", self.hasher.finalize()),
+            ranges: None,
+        }
+    }
+
+    /// Read until the buffer is full or the image ends, so chunks stay aligned
+    #[cfg(target_os = "linux")]
+    fn read_chunk(&mut self, buffer: &mut [u8]) -> std::io::Result<usize> {
+        use std::io::Read;
+        let mut filled = 0;
+        while filled < buffer.len() {
+            match self.read(&mut buffer[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(filled)
+    }
+}
+
+impl std::io::Read for BurnSource {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.image.read(buf)?;
+        self.hasher.update(&buf[..n]);
+        self.bytes_read += n as u64;
+        Ok(n)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum BurnProgress {
     Started { total_bytes: u64 },
@@ -25,6 +112,7 @@ pub enum BurnProgress {
 pub async fn burn_image(
     image_path: &Path,
     device_path: &str,
+    options: BurnOptions,
     progress_tx: UnboundedSender<BurnProgress>,
     cancel_token: CancellationToken,
 ) -> Result<(), String> {
@@ -32,54 +120,41 @@ pub async fn burn_image(
     crate::debug::log(&format!("Image: {:?}", image_path));
     crate::debug::log(&format!("Device: {}", device_path));
 
-    // Get image size - for .gz files, we need to determine decompressed size
     let compressed_size = tokio::fs::metadata(image_path)
         .await
         .map_err(|e| format!("Failed to get image size: {}", e))?
         .len();
 
-    // Check if file is gzipped
-    let is_gzipped = image_path.extension()
-        .and_then(|s| s.to_str())
-        .map(|s| s.eq_ignore_ascii_case("gz"))
-        .unwrap_or(false);
-
-    let image_size = if is_gzipped {
-        crate::debug::log(&format!("Compressed size: {} bytes ({:.2} GB)", compressed_size, compressed_size as f64 / 1_073_741_824.0));
-        crate::debug::log("Pre-scanning .gz file to determine decompressed size...");
-
-        // Determine decompressed size by reading through the file
-        let decompressed_size = tokio::task::spawn_blocking({
-            let image_path = image_path.to_path_buf();
-            move || -> Result<u64, String> {
-                use std::io::Read;
-                let file = std::fs::File::open(&image_path)
-                    .map_err(|e| format!("Failed to open image for size check: {}", e))?;
-                let mut decoder = GzDecoder::new(file);
-                let mut total = 0u64;
-                let mut buffer = vec![0u8; 1024 * 1024]; // 1MB buffer for faster scanning
-
-                loop {
-                    let bytes_read = decoder.read(&mut buffer)
-                        .map_err(|e| format!("Failed to read compressed image: {}", e))?;
-                    if bytes_read == 0 {
-                        break;
-                    }
-                    total += bytes_read as u64;
-                }
-
-                Ok(total)
-            }
-        }).await
-        .map_err(|e| format!("Size scan task failed: {}", e))??;
-
-        crate::debug::log(&format!("Decompressed size: {} bytes ({:.2} GB)", decompressed_size, decompressed_size as f64 / 1_073_741_824.0));
-        decompressed_size
-    } else {
-        crate::debug::log(&format!("Image size: {} bytes ({:.2} GB)", compressed_size, compressed_size as f64 / 1_073_741_824.0));
-        compressed_size
+    // The image is decompressed only once, while writing. The decompressed size is
+    // taken from the manifest or the file's headers where possible, for progress.
+    let compression = Compression::detect(image_path)?;
+    let bmap_size = options.bmap.as_ref().map(|bmap| bmap.image_size);
+    let size_hint = match options.size_hint.or(bmap_size) {
+        Some(size) => Some(size),
+        None => uncompressed_size_hint(image_path)?,
     };
 
+    if compression != Compression::None {
+        crate::debug::log(&format!("Compressed ({}) size: {} bytes ({:.2} GB)", compression.name(), compressed_size, compressed_size as f64 / 1_073_741_824.0));
+    }
+    match size_hint {
+        Some(size) => crate::debug::log(&format!("Image size: {} bytes ({:.2} GB)", size, size as f64 / 1_073_741_824.0)),
+        None => crate::debug::log("Image size unknown until written, progress is estimated from the compression ratio"),
+    }
+    let image_size = size_hint.unwrap_or(compressed_size);
+
+    if let Some(bmap) = &options.bmap {
+        crate::debug::log(&format!(
+            "Using bmap: {} of {} MB mapped in {} ranges ({} byte blocks)",
+            bmap.mapped_bytes() / 1_048_576,
+            bmap.image_size / 1_048_576,
+            bmap.ranges.len(),
+            bmap.block_size
+        ));
+        #[cfg(not(target_os = "linux"))]
+        crate::debug::log("Sparse burning is only supported on Linux, writing the whole image");
+    }
+
     let _ = progress_tx.send(BurnProgress::Started { total_bytes: image_size });
 
     // Unmount the device first
@@ -88,21 +163,22 @@ pub async fn burn_image(
     // Platform-specific burn implementation
     // Returns the actual number of bytes written (decompressed size)
     #[cfg(target_os = "windows")]
-    let result = burn_image_windows(image_path, device_path, image_size, &progress_tx, &cancel_token).await;
+    let result = burn_image_windows(image_path, device_path, size_hint, &progress_tx, &cancel_token).await;
 
     #[cfg(target_os = "linux")]
-    let result = burn_image_linux(image_path, device_path, image_size, &progress_tx, &cancel_token).await;
+    let result = burn_image_linux(image_path, device_path, size_hint, options.bmap.as_ref(), &progress_tx, &cancel_token).await;
 
     #[cfg(target_os = "macos")]
-    let result = burn_image_macos(image_path, device_path, image_size, &progress_tx, &cancel_token).await;
+    let result = burn_image_macos(image_path, device_path, size_hint, &progress_tx, &cancel_token).await;
 
     match result {
-        Ok(actual_bytes_written) => {
+        Ok(written) => {
             crate::debug::log("Image write completed, starting verification...");
-            crate::debug::log(&format!("Actual bytes written: {} ({:.2} GB)", actual_bytes_written, actual_bytes_written as f64 / 1_073_741_824.0));
+            crate::debug::log(&format!("Actual bytes written: {} ({:.2} GB)", written.size, written.size as f64 / 1_073_741_824.0));
+            crate::debug::log(&format!("Image SHA256: {}", written.sha256));
 
-            // Verify the written image using the actual decompressed size
-            verify_image(image_path, device_path, actual_bytes_written, &progress_tx, &cancel_token).await?;
+            // Verify by reading back exactly what was written and comparing hashes
+            verify_image(device_path, &written, &progress_tx, &cancel_token).await?;
 
             let _ = progress_tx.send(BurnProgress::Completed);
             crate::debug::log("Image burn and verification complete");
@@ -116,7 +192,7 @@ pub async fn burn_image(
 }
 
 /// Unmount all partitions on the device
-async fn unmount_device(device_path: &str) -> Result<(), String> {
+pub(crate) async fn unmount_device(device_path: &str) -> Result<(), String> {
     #[cfg(target_os = "windows")]
     {
         // On Windows, unmount all volumes on this physical drive
@@ -149,66 +225,83 @@ async fn unmount_device_windows(_device_path: &str) -> Result<(), String> {
     Ok(())
 }
 
+/// Lock and dismount every volume on a physical drive so its sectors can be written
+/// The handles must stay open while writing; release them with unlock_volumes_windows.
 #[cfg(target_os = "windows")]
-async fn burn_image_windows(
-    image_path: &Path,
-    device_path: &str,
-    image_size: u64,
-    progress_tx: &UnboundedSender<BurnProgress>,
-    cancel_token: &CancellationToken,
-) -> Result<u64, String> {
-    // Device path should already be in \\.\PhysicalDriveN format from drives.rs
-    crate::debug::log(&format!("Opening physical drive: {}", device_path));
-
-    // Move ALL Windows API operations into spawn_blocking since HANDLE is !Send
-    let bytes_written = tokio::task::spawn_blocking({
-        let image_path = image_path.to_path_buf();
-        let device_path = device_path.to_string();
-        let progress_tx = progress_tx.clone();
-        let cancel_token = cancel_token.clone();
-
-        move || -> Result<u64, String> {
-            use windows::Win32::Foundation::*;
-            use windows::Win32::Storage::FileSystem::*;
-            use windows::Win32::System::IO::*;
-            use windows::Win32::System::Ioctl::*;
-            use std::io::Read;
+fn lock_volumes_windows(device_path: &str) -> Vec<windows::Win32::Foundation::HANDLE> {
+    use windows::Win32::Foundation::*;
+    use windows::Win32::Storage::FileSystem::*;
+    use windows::Win32::System::IO::*;
+    use windows::Win32::System::Ioctl::*;
+
+    // Import IOCTL for getting device number
+    const IOCTL_STORAGE_GET_DEVICE_NUMBER: u32 = 0x002D1080;
+
+    // Extract physical drive number from device_path (e.g., "3" from "\\.\PhysicalDrive3")
+    let target_drive_number: Option<u32> = device_path
+        .strip_prefix("\\\\.\\PhysicalDrive")
+        .and_then(|s| s.parse::<u32>().ok());
+
+    crate::debug::log(&format!("Locking volumes on target physical drive: {:?}", target_drive_number));
+    let mut volume_handles: Vec<HANDLE> = Vec::new();
+
+    #[repr(C)]
+    struct STORAGE_DEVICE_NUMBER {
+        device_type: u32,
+        device_number: u32,
+        partition_number: u32,
+    }
 
-            // Import IOCTL for getting device number
-            const IOCTL_STORAGE_GET_DEVICE_NUMBER: u32 = 0x002D1080;
+    unsafe {
+        let drive_bits = GetLogicalDrives();
+        for i in 0..26u8 {
+            if (drive_bits >> i) & 1 == 1 {
+                let letter = (b'A' + i) as char;
+                let volume_path: Vec<u16> = format!("\\\\.\\{}:", letter)
+                    .encode_utf16()
+                    .chain(Some(0))
+                    .collect();
 
-            // CRITICAL: Lock and dismount volumes on the TARGET PHYSICAL DRIVE and KEEP HANDLES OPEN
-            // This prevents Windows from auto-mounting partitions during the burn
+                // Open the volume to check which physical drive it belongs to
+                let check_handle = CreateFileW(
+                    windows::core::PCWSTR(volume_path.as_ptr()),
+                    GENERIC_READ.0,
+                    FILE_SHARE_READ | FILE_SHARE_WRITE,
+                    None,
+                    OPEN_EXISTING,
+                    Default::default(),
+                    None,
+                );
 
-            // Extract physical drive number from device_path (e.g., "3" from "\\.\PhysicalDrive3")
-            let target_drive_number: Option<u32> = device_path
-                .strip_prefix("\\\\.\\PhysicalDrive")
-                .and_then(|s| s.parse::<u32>().ok());
+                if let Ok(check_handle) = check_handle {
+                    let mut device_number = STORAGE_DEVICE_NUMBER {
+                        device_type: 0,
+                        device_number: 0,
+                        partition_number: 0,
+                    };
+                    let mut bytes_returned: u32 = 0;
+
+                    let result = DeviceIoControl(
+                        check_handle,
+                        IOCTL_STORAGE_GET_DEVICE_NUMBER,
+                        None,
+                        0,
+                        Some(&mut device_number as *mut _ as *mut _),
+                        std::mem::size_of::<STORAGE_DEVICE_NUMBER>() as u32,
+                        Some(&mut bytes_returned),
+                        None,
+                    );
 
-            crate::debug::log(&format!("Locking volumes on target physical drive: {:?}", target_drive_number));
-            let mut volume_handles: Vec<HANDLE> = Vec::new();
+                    let _ = CloseHandle(check_handle);
 
-            #[repr(C)]
-            struct STORAGE_DEVICE_NUMBER {
-                device_type: u32,
-                device_number: u32,
-                partition_number: u32,
-            }
+                    // Only lock volumes that match our target physical drive
+                    if result.is_ok() && Some(device_number.device_number) == target_drive_number {
+                        crate::debug::log(&format!("Volume {}: belongs to target physical drive {}", letter, device_number.device_number));
 
-            unsafe {
-                let drive_bits = GetLogicalDrives();
-                for i in 0..26u8 {
-                    if (drive_bits >> i) & 1 == 1 {
-                        let letter = (b'A' + i) as char;
-                        let volume_path: Vec<u16> = format!("\\\\.\\{}:", letter)
-                            .encode_utf16()
-                            .chain(Some(0))
-                            .collect();
-
-                        // Open the volume to check which physical drive it belongs to
-                        let check_handle = CreateFileW(
+                        // Re-open with write access for locking
+                        let vol_handle = CreateFileW(
                             windows::core::PCWSTR(volume_path.as_ptr()),
-                            GENERIC_READ.0,
+                            FILE_GENERIC_READ.0 | FILE_GENERIC_WRITE.0,
                             FILE_SHARE_READ | FILE_SHARE_WRITE,
                             None,
                             OPEN_EXISTING,
@@ -216,101 +309,102 @@ async fn burn_image_windows(
                             None,
                         );
 
-                        if let Ok(check_handle) = check_handle {
-                            let mut device_number = STORAGE_DEVICE_NUMBER {
-                                device_type: 0,
-                                device_number: 0,
-                                partition_number: 0,
-                            };
+                        if let Ok(vol_handle) = vol_handle {
                             let mut bytes_returned: u32 = 0;
 
-                            let result = DeviceIoControl(
-                                check_handle,
-                                IOCTL_STORAGE_GET_DEVICE_NUMBER,
+                            // Lock the volume
+                            let _ = DeviceIoControl(
+                                vol_handle,
+                                FSCTL_LOCK_VOLUME,
+                                None,
+                                0,
                                 None,
                                 0,
-                                Some(&mut device_number as *mut _ as *mut _),
-                                std::mem::size_of::<STORAGE_DEVICE_NUMBER>() as u32,
                                 Some(&mut bytes_returned),
                                 None,
                             );
 
-                            let _ = CloseHandle(check_handle);
-
-                            // Only lock volumes that match our target physical drive
-                            if result.is_ok() && Some(device_number.device_number) == target_drive_number {
-                                crate::debug::log(&format!("Volume {}: belongs to target physical drive {}", letter, device_number.device_number));
-
-                                // Re-open with write access for locking
-                                let vol_handle = CreateFileW(
-                                    windows::core::PCWSTR(volume_path.as_ptr()),
-                                    FILE_GENERIC_READ.0 | FILE_GENERIC_WRITE.0,
-                                    FILE_SHARE_READ | FILE_SHARE_WRITE,
-                                    None,
-                                    OPEN_EXISTING,
-                                    Default::default(),
-                                    None,
-                                );
-
-                                if let Ok(vol_handle) = vol_handle {
-                                    let mut bytes_returned: u32 = 0;
-
-                                    // Lock the volume
-                                    let _ = DeviceIoControl(
-                                        vol_handle,
-                                        FSCTL_LOCK_VOLUME,
-                                        None,
-                                        0,
-                                        None,
-                                        0,
-                                        Some(&mut bytes_returned),
-                                        None,
-                                    );
-
-                                    // Dismount the volume
-                                    let dismount_result = DeviceIoControl(
-                                        vol_handle,
-                                        FSCTL_DISMOUNT_VOLUME,
-                                        None,
-                                        0,
-                                        None,
-                                        0,
-                                        Some(&mut bytes_returned),
-                                        None,
-                                    );
-
-                                    if dismount_result.is_ok() {
-                                        crate::debug::log(&format!("Locked/dismounted {}:", letter));
-                                    }
-
-                                    // KEEP THE HANDLE OPEN - add to list for cleanup later
-                                    volume_handles.push(vol_handle);
-                                }
+                            // Dismount the volume
+                            let dismount_result = DeviceIoControl(
+                                vol_handle,
+                                FSCTL_DISMOUNT_VOLUME,
+                                None,
+                                0,
+                                None,
+                                0,
+                                Some(&mut bytes_returned),
+                                None,
+                            );
+
+                            if dismount_result.is_ok() {
+                                crate::debug::log(&format!("Locked/dismounted {}:", letter));
                             }
+
+                            // KEEP THE HANDLE OPEN - add to list for cleanup later
+                            volume_handles.push(vol_handle);
                         }
                     }
                 }
             }
+        }
+    }
+
+    volume_handles
+}
+
+#[cfg(target_os = "windows")]
+fn unlock_volumes_windows(volume_handles: &[windows::Win32::Foundation::HANDLE]) {
+    use windows::Win32::Foundation::*;
+    use windows::Win32::System::IO::*;
+    use windows::Win32::System::Ioctl::*;
+
+    unsafe {
+        for vol_handle in volume_handles {
+            let mut bytes_returned: u32 = 0;
+            let _ = DeviceIoControl(
+                *vol_handle,
+                FSCTL_UNLOCK_VOLUME,
+                None,
+                0,
+                None,
+                0,
+                Some(&mut bytes_returned),
+                None,
+            );
+            let _ = CloseHandle(*vol_handle);
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+async fn burn_image_windows(
+    image_path: &Path,
+    device_path: &str,
+    size_hint: Option<u64>,
+    progress_tx: &UnboundedSender<BurnProgress>,
+    cancel_token: &CancellationToken,
+) -> Result<WrittenImage, String> {
+    // Device path should already be in \\.\PhysicalDriveN format from drives.rs
+    crate::debug::log(&format!("Opening physical drive: {}", device_path));
+
+    // Move ALL Windows API operations into spawn_blocking since HANDLE is !Send
+    let bytes_written = tokio::task::spawn_blocking({
+        let image_path = image_path.to_path_buf();
+        let device_path = device_path.to_string();
+        let progress_tx = progress_tx.clone();
+        let cancel_token = cancel_token.clone();
+
+        move || -> Result<WrittenImage, String> {
+            use windows::Win32::Foundation::*;
+            use windows::Win32::Storage::FileSystem::*;
+            use std::io::Read;
+
+            // CRITICAL: Lock and dismount volumes on the TARGET PHYSICAL DRIVE and KEEP HANDLES OPEN
+            // This prevents Windows from auto-mounting partitions during the burn
+            let volume_handles = lock_volumes_windows(&device_path);
 
             // Helper function to cleanup volume handles
-            let cleanup_volumes = |volume_handles: &Vec<HANDLE>| {
-                unsafe {
-                    for vol_handle in volume_handles {
-                        let mut bytes_returned: u32 = 0;
-                        let _ = DeviceIoControl(
-                            *vol_handle,
-                            FSCTL_UNLOCK_VOLUME,
-                            None,
-                            0,
-                            None,
-                            0,
-                            Some(&mut bytes_returned),
-                            None,
-                        );
-                        let _ = CloseHandle(*vol_handle);
-                    }
-                }
-            };
+            let cleanup_volumes = |volume_handles: &Vec<HANDLE>| unlock_volumes_windows(volume_handles);
 
             let device_path_wide: Vec<u16> = device_path
                 .encode_utf16()
@@ -392,25 +486,17 @@ async fn burn_image_windows(
 
             crate::debug::log("File pointer reset, beginning image write...");
 
-            // Check if file is gzipped and create appropriate reader
-            let is_gzipped = image_path.extension()
-                .and_then(|s| s.to_str())
-                .map(|s| s.eq_ignore_ascii_case("gz"))
-                .unwrap_or(false);
-
-            let file = std::fs::File::open(&image_path)
+            // Open the image, decompressing on-the-fly if needed
+            let mut image_reader = BurnSource::open(&image_path, size_hint)
                 .map_err(|e| {
                     unsafe { let _ = CloseHandle(handle); }
                     cleanup_volumes(&volume_handles);
-                    format!("Failed to open image file: {}", e)
+                    e
                 })?;
-
-            let mut image_reader: Box<dyn Read> = if is_gzipped {
-                crate::debug::log("Detected .gz file, decompressing on-the-fly during burn");
-                Box::new(GzDecoder::new(file))
-            } else {
-                Box::new(file)
-            };
+            let compression = image_reader.image.compression();
+            if compression != Compression::None {
+                crate::debug::log(&format!("Detected {} image, decompressing on-the-fly during burn", compression.name()));
+            }
 
             // Windows requires 512-byte sector-aligned writes for physical drives (SECTOR_SIZE already defined above)
             // Allocate buffers: read buffer for decompression, sector buffer for aligned writes
@@ -505,7 +591,7 @@ async fn burn_image_windows(
                     total_written += bytes_written as u64;
                     let _ = progress_tx.send(BurnProgress::Writing {
                         written: total_written,
-                        total: image_size,
+                        total: image_reader.estimated_total(),
                     });
 
                     // Move remaining partial sector to start of buffer
@@ -533,7 +619,7 @@ async fn burn_image_windows(
             crate::debug::log("Unlocking and closing volume handles...");
             cleanup_volumes(&volume_handles);
             crate::debug::log("Device closed, volumes unlocked");
-            Ok(total_written)
+            Ok(image_reader.finish())
         }
     })
     .await
@@ -588,22 +674,23 @@ async fn unmount_device_linux(device_path: &str) -> Result<(), String> {
 async fn burn_image_linux(
     image_path: &Path,
     device_path: &str,
-    image_size: u64,
+    size_hint: Option<u64>,
+    bmap: Option<&Bmap>,
     progress_tx: &UnboundedSender<BurnProgress>,
     cancel_token: &CancellationToken,
-) -> Result<u64, String> {
+) -> Result<WrittenImage, String> {
     use std::os::unix::fs::OpenOptionsExt;
-    use std::io::{Read, Write};
 
     crate::debug::log(&format!("Opening device: {}", device_path));
 
     let bytes_written = tokio::task::spawn_blocking({
         let image_path = image_path.to_path_buf();
         let device_path = device_path.to_string();
+        let bmap = bmap.cloned();
         let progress_tx = progress_tx.clone();
         let cancel_token = cancel_token.clone();
 
-        move || -> Result<u64, String> {
+        move || -> Result<WrittenImage, String> {
             // Open device with O_WRONLY | O_SYNC | O_DIRECT flags
             let mut device = std::fs::OpenOptions::new()
                 .write(true)
@@ -611,24 +698,16 @@ async fn burn_image_linux(
                 .open(&device_path)
                 .map_err(|e| format!("Failed to open device {}: {}. Are you running with sudo/root?", device_path, e))?;
 
-            // Check if file is gzipped and create appropriate reader
-            let is_gzipped = image_path.extension()
-                .and_then(|s| s.to_str())
-                .map(|s| s.eq_ignore_ascii_case("gz"))
-                .unwrap_or(false);
-
-            let file = std::fs::File::open(&image_path)
-                .map_err(|e| format!("Failed to open image file: {}", e))?;
-
-            let mut image_reader: Box<dyn Read> = if is_gzipped {
-                crate::debug::log("Detected .gz file, decompressing on-the-fly during burn");
-                Box::new(GzDecoder::new(file))
-            } else {
-                Box::new(file)
-            };
+            // Open the image, decompressing on-the-fly if needed
+            let mut image_reader = BurnSource::open(&image_path, size_hint)?;
+            let compression = image_reader.image.compression();
+            if compression != Compression::None {
+                crate::debug::log(&format!("Detected {} image, decompressing on-the-fly during burn", compression.name()));
+            }
 
             let mut buffer = vec![0u8; CHUNK_SIZE];
             let mut total_written = 0u64;
+            let mut writer = SparseWriter::new(bmap.as_ref(), crate::config::BURN_SKIP_ZERO_CHUNKS);
 
             loop {
                 if cancel_token.is_cancelled() {
@@ -636,20 +715,19 @@ async fn burn_image_linux(
                     return Err("Burn cancelled".to_string());
                 }
 
-                let bytes_read = image_reader.read(&mut buffer)
+                let bytes_read = image_reader.read_chunk(&mut buffer)
                     .map_err(|e| format!("Failed to read from image: {}", e))?;
 
                 if bytes_read == 0 {
                     break; // EOF
                 }
 
-                device.write_all(&buffer[..bytes_read])
-                    .map_err(|e| format!("Failed to write to device at offset {}: {}", total_written, e))?;
+                writer.write_chunk(&mut device, total_written, &buffer[..bytes_read])?;
 
                 total_written += bytes_read as u64;
                 let _ = progress_tx.send(BurnProgress::Writing {
                     written: total_written,
-                    total: image_size,
+                    total: image_reader.estimated_total(),
                 });
             }
 
@@ -658,7 +736,9 @@ async fn burn_image_linux(
                 .map_err(|e| format!("Failed to sync device: {}", e))?;
 
             crate::debug::log(&format!("Write complete: {} bytes written", total_written));
-            Ok(total_written)
+            let mut written = image_reader.finish();
+            writer.finish(&device, &mut written)?;
+            Ok(written)
         }
     })
     .await
@@ -667,6 +747,180 @@ async fn burn_image_linux(
     bytes_written
 }
 
+// =============================================================================
+// Sparse Writing (Linux)
+// =============================================================================
+
+#[cfg(target_os = "linux")]
+const BLKDISCARD: libc::c_ulong = 0x1277; // _IO(0x12, 119)
+#[cfg(target_os = "linux")]
+const BLKZEROOUT: libc::c_ulong = 0x127f; // _IO(0x12, 127)
+
+/// Run a block device ioctl that takes a {start, length} byte range
+#[cfg(target_os = "linux")]
+fn block_range_ioctl(device: &std::fs::File, request: libc::c_ulong, range: Range<u64>) -> std::io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    let arg: [u64; 2] = [range.start, range.end - range.start];
+    let ret = unsafe { libc::ioctl(device.as_raw_fd(), request as _, arg.as_ptr()) };
+    if ret == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// Writes only the parts of an image that hold data: the ranges of its .bmap, or
+/// (without one) everything but all-zero chunks, which are zeroed with BLKZEROOUT
+#[cfg(target_os = "linux")]
+struct SparseWriter<'a> {
+    bmap: Option<&'a Bmap>,
+    skip_zero_chunks: bool,
+    /// First bmap range not yet completely written
+    next_range: usize,
+    /// Hash of the current bmap range, checked against the bmap when it's complete
+    range_hasher: Sha256,
+    /// Hash of everything written, in order - what verification reads back
+    written_hasher: Sha256,
+    written: Vec<Range<u64>>,
+    zeroed: u64,
+    /// Where the device's file position is, if known
+    device_position: Option<u64>,
+}
+
+#[cfg(target_os = "linux")]
+impl<'a> SparseWriter<'a> {
+    fn new(bmap: Option<&'a Bmap>, skip_zero_chunks: bool) -> Self {
+        Self {
+            bmap,
+            skip_zero_chunks,
+            next_range: 0,
+            range_hasher: Sha256::new(),
+            written_hasher: Sha256::new(),
+            written: Vec::new(),
+            zeroed: 0,
+            device_position: None,
+        }
+    }
+
+    /// Write the chunk of the image that starts at `offset`
+    fn write_chunk(&mut self, device: &mut std::fs::File, offset: u64, data: &[u8]) -> Result<(), String> {
+        let end = offset + data.len() as u64;
+
+        let Some(bmap) = self.bmap else {
+            let aligned = offset.is_multiple_of(512) && data.len().is_multiple_of(512);
+            if self.skip_zero_chunks && aligned && data.iter().all(|&b| b == 0)
+                && block_range_ioctl(device, BLKZEROOUT, offset..end).is_ok()
+            {
+                self.zeroed += data.len() as u64;
+                return Ok(());
+            }
+            return self.write_at(device, offset, data);
+        };
+
+        // Unmapped blocks are skipped, so they must really be empty: otherwise the bmap
+        // is stale or tampered with and the card would silently miss data
+        let check_unmapped = |from: u64, to: u64| -> Result<(), String> {
+            let gap = &data[(from - offset) as usize..(to - offset) as usize];
+            match gap.iter().position(|&b| b != 0) {
+                Some(i) => Err(format!(
+                    "Image doesn't match its bmap: data outside the mapped blocks at offset {}",
+                    from + i as u64
+                )),
+                None => Ok(()),
+            }
+        };
+
+        let mut position = offset;
+        while let Some(range) = bmap.ranges.get(self.next_range) {
+            if range.start >= end {
+                break;
+            }
+            if range.start > position {
+                check_unmapped(position, range.start)?;
+            }
+            let start = position.max(range.start);
+            let stop = end.min(range.end);
+            let slice = &data[(start - offset) as usize..(stop - offset) as usize];
+            self.range_hasher.update(slice);
+            self.write_at(device, start, slice)?;
+            position = stop;
+
+            if stop < range.end {
+                break; // Range continues in the next chunk
+            }
+            let hash = format!("{:x}", std::mem::take(&mut self.range_hasher).finalize());
+            if range.sha256.as_ref().is_some_and(|expected| *expected != hash) {
+                return Err(format!("Image doesn't match its bmap: checksum mismatch at offset {}", range.start));
+            }
+            self.next_range += 1;
+        }
+        check_unmapped(position, end)
+    }
+
+    fn write_at(&mut self, device: &mut std::fs::File, offset: u64, data: &[u8]) -> Result<(), String> {
+        use std::io::{Seek, Write};
+
+        if self.device_position != Some(offset) {
+            device.seek(std::io::SeekFrom::Start(offset))
+                .map_err(|e| format!("Failed to seek device to offset {}: {}", offset, e))?;
+        }
+        device.write_all(data)
+            .map_err(|e| format!("Failed to write to device at offset {}: {}", offset, e))?;
+
+        let end = offset + data.len() as u64;
+        self.written_hasher.update(data);
+        match self.written.last_mut() {
+            Some(last) if last.end == offset => last.end = end,
+            _ => self.written.push(offset..end),
+        }
+        self.device_position = Some(end);
+        Ok(())
+    }
+
+    /// Finish once the whole image went through write_chunk
+    /// If the burn was sparse, `image` is updated to cover only the written ranges.
+    fn finish(self, device: &std::fs::File, image: &mut WrittenImage) -> Result<(), String> {
+        let image_size = image.size;
+        let Some(bmap) = self.bmap else {
+            if self.zeroed > 0 {
+                crate::debug::log(&format!("Zeroed {} MB of empty chunks instead of writing them", self.zeroed / 1_048_576));
+                image.sha256 = format!("{:x}", self.written_hasher.finalize());
+                image.ranges = Some(self.written);
+            }
+            return Ok(());
+        };
+
+        if image_size != bmap.image_size {
+            return Err(format!("Image is {} bytes, but its bmap describes {} bytes", image_size, bmap.image_size));
+        }
+
+        if crate::config::BURN_DISCARD_UNMAPPED {
+            // Gaps between written ranges, rounded inwards to whole sectors
+            let mut discarded = 0;
+            let mut gap_start = 0u64;
+            for range in self.written.iter().cloned().chain(std::iter::once(image_size..image_size)) {
+                let start = gap_start.div_ceil(512) * 512;
+                let end = range.start / 512 * 512;
+                if end > start {
+                    match block_range_ioctl(device, BLKDISCARD, start..end) {
+                        Ok(()) => discarded += end - start,
+                        Err(e) => {
+                            crate::debug::log(&format!("Discard not supported ({}), leaving unmapped blocks as they are", e));
+                            break;
+                        }
+                    }
+                }
+                gap_start = range.end;
+            }
+            crate::debug::log(&format!("Discarded {} MB of unmapped blocks", discarded / 1_048_576));
+        }
+
+        image.sha256 = format!("{:x}", self.written_hasher.finalize());
+        image.ranges = Some(self.written);
+        Ok(())
+    }
+}
+
 // =============================================================================
 // macOS Implementation
 // =============================================================================
@@ -710,10 +964,10 @@ async fn unmount_device_macos(device_path: &str) -> Result<(), String> {
 async fn burn_image_macos(
     image_path: &Path,
     device_path: &str,
-    image_size: u64,
+    size_hint: Option<u64>,
     progress_tx: &UnboundedSender<BurnProgress>,
     cancel_token: &CancellationToken,
-) -> Result<u64, String> {
+) -> Result<WrittenImage, String> {
     use std::io::{Read, Write};
 
     // Use rdisk for faster writes (raw disk)
@@ -726,27 +980,12 @@ async fn burn_image_macos(
         let progress_tx = progress_tx.clone();
         let cancel_token = cancel_token.clone();
 
-        move || -> Result<u64, String> {
+        move || -> Result<WrittenImage, String> {
             crate::debug::log("Opening device using authopen (will prompt for authorization)...");
 
             // Use authopen to get privileged file descriptor with socketpair FD passing
             // This will show a native macOS authorization dialog
-            let mut device = match crate::mac::authopen::auth_open_device(std::path::Path::new(&device_path)) {
-                Ok(file) => file,
-                Err(crate::mac::authopen::AuthOpenError::Cancelled) => {
-                    crate::debug::log("User cancelled authorization");
-                    return Err("Authorization cancelled by user".to_string());
-                },
-                Err(crate::mac::authopen::AuthOpenError::Failed(msg)) => {
-                    crate::debug::log(&format!("Authorization failed: {}", msg));
-                    return Err(msg); // msg already includes log path from authopen.rs
-                },
-                Err(crate::mac::authopen::AuthOpenError::SystemError(msg)) => {
-                    crate::debug::log(&format!("System error during authorization: {}", msg));
-                    let log_path = crate::debug::get_log_path();
-                    return Err(format!("System error: {}\n\nDebug log: {:?}\nClick 'Copy Log to Clipboard' to share this error.", msg, log_path));
-                },
-            };
+            let mut device = auth_open_device_macos(&device_path)?;
 
             crate::debug::log("Device opened successfully via authopen");
 
@@ -777,21 +1016,12 @@ async fn burn_image_macos(
 
             crate::debug::log("Ready to write image");
 
-            // Check if file is gzipped and create appropriate reader
-            let is_gzipped = image_path.extension()
-                .and_then(|s| s.to_str())
-                .map(|s| s.eq_ignore_ascii_case("gz"))
-                .unwrap_or(false);
-
-            let file = std::fs::File::open(&image_path)
-                .map_err(|e| format!("Failed to open image file: {}", e))?;
-
-            let mut image_reader: Box<dyn Read> = if is_gzipped {
-                crate::debug::log("Detected .gz file, decompressing on-the-fly during burn");
-                Box::new(GzDecoder::new(file))
-            } else {
-                Box::new(file)
-            };
+            // Open the image, decompressing on-the-fly if needed
+            let mut image_reader = BurnSource::open(&image_path, size_hint)?;
+            let compression = image_reader.image.compression();
+            if compression != Compression::None {
+                crate::debug::log(&format!("Detected {} image, decompressing on-the-fly during burn", compression.name()));
+            }
 
             // macOS raw devices with F_NOCACHE require sector-aligned writes
             // Use similar buffering approach as Windows implementation
@@ -843,7 +1073,7 @@ async fn burn_image_macos(
                     total_written += sectors_to_write as u64;
                     let _ = progress_tx.send(BurnProgress::Writing {
                         written: total_written,
-                        total: image_size,
+                        total: image_reader.estimated_total(),
                     });
 
                     // Move remaining partial sector to start of buffer
@@ -864,7 +1094,7 @@ async fn burn_image_macos(
             }
 
             crate::debug::log(&format!("Write complete: {} bytes written", total_written));
-            Ok(total_written)
+            Ok(image_reader.finish())
         } // <-- Close the closure
     }) // <-- Close spawn_blocking
     .await
@@ -872,69 +1102,226 @@ async fn burn_image_macos(
 
     Ok(bytes_written)
 }
+/// Open a raw device through authopen (shows the macOS authorization dialog)
+#[cfg(target_os = "macos")]
+fn auth_open_device_macos(device_path: &str) -> Result<std::fs::File, String> {
+    match crate::mac::authopen::auth_open_device(std::path::Path::new(device_path)) {
+        Ok(file) => Ok(file),
+        Err(crate::mac::authopen::AuthOpenError::Cancelled) => {
+            crate::debug::log("User cancelled authorization");
+            Err("Authorization cancelled by user".to_string())
+        },
+        Err(crate::mac::authopen::AuthOpenError::Failed(msg)) => {
+            crate::debug::log(&format!("Authorization failed: {}", msg));
+            Err(msg) // msg already includes log path from authopen.rs
+        },
+        Err(crate::mac::authopen::AuthOpenError::SystemError(msg)) => {
+            crate::debug::log(&format!("System error during authorization: {}", msg));
+            let log_path = crate::debug::get_log_path();
+            Err(format!("System error: {}\n\nDebug log: {:?}\nClick 'Copy Log to Clipboard' to share this error.", msg, log_path))
+        },
+    }
+}
+
 // =============================================================================
-// Verification
+// Device Access
 // =============================================================================
 
-/// Verify the written image by reading back and comparing SHA256 hash
-async fn verify_image(
-    image_path: &Path,
-    device_path: &str,
-    #[allow(unused_variables)] image_size: u64,
-    progress_tx: &UnboundedSender<BurnProgress>,
-    cancel_token: &CancellationToken,
-) -> Result<(), String> {
-    crate::debug::log("Computing image hash...");
+/// Open a whole device for reading (used by verification and backups)
+/// Must be called from a blocking context: on macOS this waits for authorization.
+pub(crate) fn open_device_for_reading(device_path: &str) -> Result<std::fs::File, String> {
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::io::FromRawHandle;
+        use windows::Win32::Storage::FileSystem::*;
+
+        let device_path_wide: Vec<u16> = device_path
+            .encode_utf16()
+            .chain(Some(0))
+            .collect();
+
+        let handle = unsafe {
+            CreateFileW(
+                windows::core::PCWSTR(device_path_wide.as_ptr()),
+                FILE_GENERIC_READ.0,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                None,
+                OPEN_EXISTING,
+                FILE_ATTRIBUTE_NORMAL,
+                None,
+            )
+        }
+        .map_err(|e| format!("Failed to open device {}: {}. Are you running as administrator?", device_path, e))?;
 
-    // Compute hash of original image (decompress if .gz)
-    let image_hash = tokio::task::spawn_blocking({
-        let image_path = image_path.to_path_buf();
-        let cancel_token = cancel_token.clone();
+        Ok(unsafe { std::fs::File::from_raw_handle(handle.0 as _) })
+    }
 
-        move || -> Result<String, String> {
-            use std::io::Read;
+    #[cfg(target_os = "linux")]
+    {
+        std::fs::File::open(device_path)
+            .map_err(|e| format!("Failed to open device {}: {}. Are you running with sudo?", device_path, e))
+    }
 
-            // Check if file is gzipped and create appropriate reader
-            let is_gzipped = image_path.extension()
-                .and_then(|s| s.to_str())
-                .map(|s| s.eq_ignore_ascii_case("gz"))
-                .unwrap_or(false);
+    #[cfg(target_os = "macos")]
+    {
+        // rdisk avoids the buffer cache, like for burning
+        auth_open_device_macos(&device_path.replace("/dev/disk", "/dev/rdisk"))
+    }
+}
 
-            let file = std::fs::File::open(&image_path)
-                .map_err(|e| format!("Failed to open image for verification: {}", e))?;
+/// A whole device opened for unbuffered reads and writes
+/// Offsets, lengths and buffer addresses must be aligned to DEVICE_IO_ALIGNMENT.
+/// On Windows the drive's volumes stay locked and dismounted until this is dropped.
+pub(crate) struct DeviceWriter {
+    pub file: std::fs::File,
+    #[cfg(target_os = "windows")]
+    volumes: Vec<windows::Win32::Foundation::HANDLE>,
+}
 
-            let mut image_reader: Box<dyn Read> = if is_gzipped {
-                crate::debug::log("Decompressing .gz file for hash verification");
-                Box::new(GzDecoder::new(file))
-            } else {
-                Box::new(file)
-            };
+/// Alignment for DeviceWriter I/O (covers 512-byte and 4K-sector cards)
+pub(crate) const DEVICE_IO_ALIGNMENT: usize = 4096;
 
-            let mut hasher = Sha256::new();
-            let mut buffer = vec![0u8; CHUNK_SIZE];
+#[cfg(target_os = "windows")]
+impl Drop for DeviceWriter {
+    fn drop(&mut self) {
+        unlock_volumes_windows(&self.volumes);
+    }
+}
 
-            loop {
-                if cancel_token.is_cancelled() {
-                    return Err("Verification cancelled".to_string());
-                }
+/// Open a whole (unmounted) device for reading and writing around the OS cache,
+/// so what is read back is what the card stored
+/// Must be called from a blocking context: on macOS this waits for authorization.
+pub(crate) fn open_device_for_writing(device_path: &str) -> Result<DeviceWriter, String> {
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::io::FromRawHandle;
+        use windows::Win32::Storage::FileSystem::*;
+
+        let volumes = lock_volumes_windows(device_path);
+        let device_path_wide: Vec<u16> = device_path
+            .encode_utf16()
+            .chain(Some(0))
+            .collect();
+
+        let handle = unsafe {
+            CreateFileW(
+                windows::core::PCWSTR(device_path_wide.as_ptr()),
+                FILE_GENERIC_READ.0 | FILE_GENERIC_WRITE.0,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                None,
+                OPEN_EXISTING,
+                FILE_FLAG_NO_BUFFERING | FILE_FLAG_WRITE_THROUGH,
+                None,
+            )
+        };
+        match handle {
+            Ok(handle) => Ok(DeviceWriter {
+                file: unsafe { std::fs::File::from_raw_handle(handle.0 as _) },
+                volumes,
+            }),
+            Err(e) => {
+                unlock_volumes_windows(&volumes);
+                Err(format!("Failed to open device {}: {}. Are you running as administrator?", device_path, e))
+            }
+        }
+    }
 
-                let bytes_read = image_reader.read(&mut buffer)
-                    .map_err(|e| format!("Failed to read image: {}", e))?;
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_DIRECT | libc::O_SYNC)
+            .open(device_path)
+            .map(|file| DeviceWriter { file })
+            .map_err(|e| format!("Failed to open device {}: {}. Are you running with sudo?", device_path, e))
+    }
 
-                if bytes_read == 0 {
-                    break;
-                }
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::unix::io::AsRawFd;
+        // authopen opens with O_RDWR | O_SYNC; rdisk plus F_NOCACHE skips the buffer cache
+        let file = auth_open_device_macos(&device_path.replace("/dev/disk", "/dev/rdisk"))?;
+        unsafe {
+            libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1);
+        }
+        Ok(DeviceWriter { file })
+    }
+}
 
-                hasher.update(&buffer[..bytes_read]);
-            }
+/// Size of an opened device in bytes, if the platform can tell
+pub(crate) fn device_size(device: &std::fs::File) -> Option<u64> {
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::io::AsRawHandle;
+        use windows::Win32::Foundation::HANDLE;
+        use windows::Win32::System::IO::DeviceIoControl;
+
+        const IOCTL_DISK_GET_LENGTH_INFO: u32 = 0x0007405C;
+        let mut length: i64 = 0;
+        let mut bytes_returned = 0u32;
+        unsafe {
+            DeviceIoControl(
+                HANDLE(device.as_raw_handle() as _),
+                IOCTL_DISK_GET_LENGTH_INFO,
+                None,
+                0,
+                Some(&mut length as *mut _ as *mut _),
+                std::mem::size_of::<i64>() as u32,
+                Some(&mut bytes_returned),
+                None,
+            )
+        }
+        .ok()?;
+        (length > 0).then_some(length as u64)
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        use std::io::Seek;
+        let mut device = device;
+        let size = device.seek(std::io::SeekFrom::End(0)).ok()?;
+        device.seek(std::io::SeekFrom::Start(0)).ok()?;
+        (size > 0).then_some(size)
+    }
 
-            let result = hasher.finalize();
-            Ok(format!("{:x}", result))
+    #[cfg(target_os = "macos")]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        const DKIOCGETBLOCKSIZE: libc::c_ulong = 0x40046418;
+        const DKIOCGETBLOCKCOUNT: libc::c_ulong = 0x40086419;
+        let mut block_size: u32 = 0;
+        let mut block_count: u64 = 0;
+        unsafe {
+            if libc::ioctl(device.as_raw_fd(), DKIOCGETBLOCKSIZE, &mut block_size) != 0
+                || libc::ioctl(device.as_raw_fd(), DKIOCGETBLOCKCOUNT, &mut block_count) != 0
+            {
+                return None;
+            }
         }
-    }).await
-    .map_err(|e| format!("Hash computation failed: {}", e))??;
+        Some(block_count * block_size as u64).filter(|&size| size > 0)
+    }
+}
+
+// =============================================================================
+// Verification
+// =============================================================================
+
+/// Verify the written image by reading back the device and comparing its SHA256
+/// with the hash computed while writing
+async fn verify_image(
+    device_path: &str,
+    written: &WrittenImage,
+    progress_tx: &UnboundedSender<BurnProgress>,
+    cancel_token: &CancellationToken,
+) -> Result<(), String> {
+    #[allow(unused_variables)]
+    let image_size = written.size;
+    #[allow(unused_variables)]
+    let ranges = written.ranges.clone();
 
-    crate::debug::log(&format!("Image SHA256: {}", image_hash));
     crate::debug::log("Reading back device data...");
 
     // Read back device and compute hash
@@ -991,39 +1378,51 @@ async fn verify_image(
             }
 
             #[cfg(target_os = "linux")]
-            let mut device = {
-                let dev_path = device_path.clone();
-
-                std::fs::File::open(&dev_path)
-                    .map_err(|e| format!("Failed to open device for verification: {}. Are you running with sudo?", e))?
-            };
+            let mut device = open_device_for_reading(&device_path)
+                .map_err(|e| format!("Failed to open device for verification: {}", e))?;
 
             #[cfg(target_os = "linux")]
             {
+                use std::io::Seek;
+
+                // Only what was written is read back (everything, unless the burn was sparse)
+                let ranges = ranges.unwrap_or_else(|| std::iter::once(0..image_size).collect());
+                let verify_size: u64 = ranges.iter().map(|r| r.end - r.start).sum();
+                if verify_size < image_size {
+                    crate::debug::log(&format!("Verifying {} MB of written ranges", verify_size / 1_048_576));
+                }
+
                 let mut hasher = Sha256::new();
                 let mut buffer = vec![0u8; CHUNK_SIZE];
                 let mut total_read = 0u64;
 
-                while total_read < image_size {
-                    if _cancel_token.is_cancelled() {
-                        return Err("Verification cancelled".to_string());
-                    }
+                for range in ranges {
+                    device.seek(std::io::SeekFrom::Start(range.start))
+                        .map_err(|e| format!("Failed to seek device: {}", e))?;
+                    let mut position = range.start;
+
+                    while position < range.end {
+                        if _cancel_token.is_cancelled() {
+                            return Err("Verification cancelled".to_string());
+                        }
 
-                    let to_read = std::cmp::min(CHUNK_SIZE, (image_size - total_read) as usize);
-                    let bytes_read = device.read(&mut buffer[..to_read])
-                        .map_err(|e| format!("Failed to read device: {}", e))?;
+                        let to_read = std::cmp::min(CHUNK_SIZE as u64, range.end - position) as usize;
+                        let bytes_read = device.read(&mut buffer[..to_read])
+                            .map_err(|e| format!("Failed to read device: {}", e))?;
 
-                    if bytes_read == 0 {
-                        return Err(format!("Unexpected EOF: read {} bytes, expected {}", total_read, image_size));
-                    }
+                        if bytes_read == 0 {
+                            return Err(format!("Unexpected EOF: read {} bytes, expected {}", position, image_size));
+                        }
 
-                    hasher.update(&buffer[..bytes_read]);
-                    total_read += bytes_read as u64;
+                        hasher.update(&buffer[..bytes_read]);
+                        position += bytes_read as u64;
+                        total_read += bytes_read as u64;
 
-                    let _ = _progress_tx.send(BurnProgress::Verifying {
-                        verified: total_read,
-                        total: image_size,
-                    });
+                        let _ = _progress_tx.send(BurnProgress::Verifying {
+                            verified: total_read,
+                            total: verify_size,
+                        });
+                    }
                 }
 
                 let result = hasher.finalize();
@@ -1039,10 +1438,117 @@ async fn verify_image(
     if device_hash.is_empty() {
         crate::debug::log("Verification skipped (not implemented on this platform)");
         Ok(())
-    } else if image_hash != device_hash {
+    } else if written.sha256 != device_hash {
         Err("Verification failed: Hashes do not match!".to_string())
     } else {
         crate::debug::log("Verification passed: Hashes match");
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn test_burn_source() {
+        let dir = tempfile::tempdir().unwrap();
+        let data: Vec<u8> = (0..5 * 1024 * 1024u32).map(|i| (i / 4096) as u8).collect();
+        let path = dir.path().join("card.img.xz");
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 1);
+        xz.write_all(&data).unwrap();
+        std::fs::write(&path, xz.finish().unwrap()).unwrap();
+
+        // xz has no size in its header, so the total is extrapolated while reading
+        let mut source = BurnSource::open(&path, None).unwrap();
+        let mut buffer = vec![0u8; 1024 * 1024];
+        let mut read = 0;
+        while read < data.len() / 2 {
+            read += source.read(&mut buffer).unwrap();
+        }
+        assert!(source.estimated_total() >= read as u64);
+        std::io::copy(&mut source, &mut std::io::sink()).unwrap();
+        assert_eq!(source.estimated_total(), data.len() as u64);
+
+        let written = source.finish();
+        assert_eq!(written.size, data.len() as u64);
+        assert_eq!(written.sha256, format!("{:x}", Sha256::digest(&data)));
+
+        // A hint is trusted until it's exceeded
+        let source = BurnSource::open(&path, Some(123)).unwrap();
+        assert_eq!(source.estimated_total(), 123);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_sparse_writer() {
+        use crate::bmap::MappedRange;
+
+        let dir = tempfile::tempdir().unwrap();
+        let mapped = [0..8192, 20480..40960, 61440..65536];
+        // Data in the mapped ranges, zeros (free space) everywhere else
+        let image: Vec<u8> = (0..64 * 1024u32)
+            .map(|i| if mapped.iter().any(|r| r.contains(&(i as u64))) { (i % 253) as u8 + 1 } else { 0 })
+            .collect();
+        let range = |start: u64, end: u64, image: &[u8]| MappedRange {
+            start,
+            end,
+            sha256: Some(format!("{:x}", Sha256::digest(&image[start as usize..end as usize]))),
+        };
+        let bmap = Bmap {
+            image_size: image.len() as u64,
+            block_size: 4096,
+            ranges: mapped.iter().map(|r| range(r.start, r.end, &image)).collect(),
+        };
+
+        // A "device" full of stale data: unmapped ranges must be left alone
+        let device_path = dir.path().join("device");
+        std::fs::write(&device_path, vec![0xEEu8; image.len()]).unwrap();
+        let mut device = std::fs::File::options().write(true).open(&device_path).unwrap();
+
+        let mut writer = SparseWriter::new(Some(&bmap), false);
+        for (i, chunk) in image.chunks(10000).enumerate() {
+            writer.write_chunk(&mut device, (i * 10000) as u64, chunk).unwrap();
+        }
+        let mut result = WrittenImage { size: image.len() as u64, sha256: String::new(), ranges: None };
+        writer.finish(&device, &mut result).unwrap();
+        let ranges = result.ranges.unwrap();
+        assert_eq!(ranges, [0..8192, 20480..40960, 61440..65536]);
+
+        let written = std::fs::read(&device_path).unwrap();
+        let mut hasher = Sha256::new();
+        for range in &ranges {
+            assert_eq!(written[range.start as usize..range.end as usize], image[range.start as usize..range.end as usize]);
+            hasher.update(&written[range.start as usize..range.end as usize]);
+        }
+        assert_eq!(format!("{:x}", hasher.finalize()), result.sha256);
+        assert!(written[8192..20480].iter().all(|&b| b == 0xEE));
+
+        // Data that doesn't match the bmap's checksums is refused
+        let mut corrupt = image.clone();
+        corrupt[30000] ^= 0xFF;
+        let mut writer = SparseWriter::new(Some(&bmap), false);
+        let result = corrupt.chunks(10000).enumerate()
+            .try_for_each(|(i, chunk)| writer.write_chunk(&mut device, (i * 10000) as u64, chunk));
+        assert!(result.unwrap_err().contains("doesn't match its bmap"));
+
+        // So is data the bmap leaves out (a stale or tampered bmap)
+        for offset in [8192, 50000, 61439] {
+            let mut unmapped = image.clone();
+            unmapped[offset] = 1;
+            let mut writer = SparseWriter::new(Some(&bmap), false);
+            let result = unmapped.chunks(10000).enumerate()
+                .try_for_each(|(i, chunk)| writer.write_chunk(&mut device, (i * 10000) as u64, chunk));
+            assert!(result.unwrap_err().ends_with(&format!("outside the mapped blocks at offset {}", offset)));
+        }
+
+        // Without a bmap, zero chunks fall back to a normal write where BLKZEROOUT isn't available
+        let mut writer = SparseWriter::new(None, true);
+        writer.write_chunk(&mut device, 0, &[0u8; 4096]).unwrap();
+        let mut result = WrittenImage { size: 4096, sha256: String::new(), ranges: None };
+        writer.finish(&device, &mut result).unwrap();
+        assert!(result.ranges.is_none());
+        assert!(std::fs::read(&device_path).unwrap()[..4096].iter().all(|&b| b == 0));
+    }
+}
@@ -0,0 +1,192 @@
+// Copyright (C) 2026 SpruceOS Team
+// Licensed under GPL-3.0-or-later
+
+// Persistent download cache
+// Finished downloads are kept so flashing several cards with the same release
+// only downloads it once. Entries are content-addressed: by SHA-256 when the
+// release publishes one, otherwise by URL + asset name + size. The least
+// recently used entries are evicted once the cache grows past its size limit.
+
+use crate::config::{DOWNLOAD_CACHE_MAX_SIZE, TEMP_PREFIX};
+use crate::github::{download_state_path, is_sha256, remove_download, Asset};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[derive(Debug, Clone)]
+pub struct DownloadCache {
+    pub dir: PathBuf,
+    /// Total size the cache is trimmed to after each download (bytes)
+    pub max_size: u64,
+}
+
+impl DownloadCache {
+    /// Cache in the user's cache directory, or None if disabled in config.rs
+    pub fn from_config() -> Option<Self> {
+        (DOWNLOAD_CACHE_MAX_SIZE > 0).then(|| Self {
+            dir: crate::pipeline::get_cache_dir().join(format!("{}_downloads", TEMP_PREFIX)),
+            max_size: DOWNLOAD_CACHE_MAX_SIZE,
+        })
+    }
+
+    /// Where an asset is (or will be) stored: <key>_<asset name>
+    pub fn entry_path(&self, asset: &Asset) -> PathBuf {
+        self.dir.join(format!("{}_{}", &cache_key(asset)[..16], asset.name))
+    }
+
+    /// Path of a complete cached copy of the asset, if there is one
+    /// Partial downloads (with resume state) and size mismatches don't count.
+    pub fn lookup(&self, asset: &Asset) -> Option<PathBuf> {
+        let path = self.entry_path(asset);
+        let size = std::fs::metadata(&path).ok()?.len();
+        if size != asset.size || download_state_path(&path).exists() {
+            return None;
+        }
+
+        // Mark as recently used for eviction
+        if let Ok(file) = std::fs::File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(path)
+    }
+
+    /// Cached files (complete or partial) with size and last use, oldest first
+    fn entries(&self) -> Vec<(PathBuf, u64, SystemTime)> {
+        let Ok(read_dir) = std::fs::read_dir(&self.dir) else {
+            return Vec::new();
+        };
+
+        let mut entries: Vec<_> = read_dir
+            .flatten()
+            .filter(|entry| {
+                let name = entry.file_name().to_string_lossy().to_string();
+                !name.ends_with(".state") && !name.ends_with(".tmp")
+            })
+            .filter_map(|entry| {
+                let metadata = entry.metadata().ok().filter(|m| m.is_file())?;
+                let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                Some((entry.path(), metadata.len(), modified))
+            })
+            .collect();
+        entries.sort_by_key(|(_, _, modified)| *modified);
+        entries
+    }
+
+    /// Total size of the cache on disk (bytes)
+    pub fn size(&self) -> u64 {
+        self.entries().iter().map(|(_, size, _)| size).sum()
+    }
+
+    /// Remove least recently used entries until the cache fits in max_size
+    /// `keep` (the download just used) is never evicted. Returns the bytes freed.
+    pub fn evict(&self, keep: &Path) -> u64 {
+        let entries = self.entries();
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        let mut freed = 0;
+
+        for (path, size, _) in entries {
+            if total <= self.max_size {
+                break;
+            }
+            if path == keep {
+                continue;
+            }
+            crate::debug::log(&format!("Evicting cached download: {:?} ({} MB)", path, size / 1_048_576));
+            remove_download(&path);
+            total -= size;
+            freed += size;
+        }
+        freed
+    }
+
+    /// Delete everything in the cache. Returns the bytes freed.
+    pub fn clear(&self) -> Result<u64, String> {
+        let size = self.size();
+        if self.dir.exists() {
+            std::fs::remove_dir_all(&self.dir)
+                .map_err(|e| format!("Failed to clear download cache: {}", e))?;
+        }
+        crate::debug::log(&format!("Download cache cleared ({} MB)", size / 1_048_576));
+        Ok(size)
+    }
+}
+
+/// Content address of an asset: its SHA-256 if known, else a hash of where it comes from
+fn cache_key(asset: &Asset) -> String {
+    if let Some(sha256) = asset.sha256.as_deref().filter(|h| is_sha256(h)) {
+        return sha256.to_ascii_lowercase();
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(asset.browser_download_url.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(asset.name.as_bytes());
+    hasher.update(b"\n");
+    hasher.update(asset.size.to_string().as_bytes());
+    hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn asset(name: &str, size: u64, sha256: Option<&str>) -> Asset {
+        Asset {
+            name: name.to_string(),
+            size,
+            browser_download_url: format!("https://example.com/v1/{}", name),
+            display_name: None,
+            devices: None,
+            sha256: sha256.map(|s| s.to_string()),
+            uncompressed_size: None,
+            bmap_url: None,
+            bmap_sha256: None,
+        }
+    }
+
+    #[test]
+    fn test_cache_key() {
+        let a = asset("spruceOS.7z", 10, None);
+        let mut b = asset("spruceOS.7z", 10, None);
+        assert_eq!(cache_key(&a), cache_key(&b));
+        b.browser_download_url = "https://example.com/v2/spruceOS.7z".to_string();
+        assert_ne!(cache_key(&a), cache_key(&b));
+
+        let hash = "AB".repeat(32);
+        assert_eq!(cache_key(&asset("x.7z", 10, Some(&hash))), hash.to_ascii_lowercase());
+
+        // Anything else can't pick the file name
+        let cache = DownloadCache { dir: PathBuf::from("cache"), max_size: 0 };
+        for bad in ["../../../../etc/", "é", ""] {
+            let path = cache.entry_path(&asset("x.7z", 10, Some(bad)));
+            assert_eq!(path.parent(), Some(cache.dir.as_path()), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_lookup_and_evict() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = DownloadCache { dir: dir.path().to_path_buf(), max_size: 10 };
+        let old = asset("old.7z", 6, None);
+        let new = asset("new.7z", 6, None);
+
+        assert!(cache.lookup(&old).is_none());
+        std::fs::write(cache.entry_path(&old), [0u8; 6]).unwrap();
+        std::fs::File::options().write(true).open(cache.entry_path(&old)).unwrap()
+            .set_modified(SystemTime::UNIX_EPOCH).unwrap();
+        std::fs::write(cache.entry_path(&new), [0u8; 6]).unwrap();
+        assert!(cache.lookup(&new).is_some());
+
+        // A partial download isn't a hit
+        std::fs::write(download_state_path(&cache.entry_path(&new)), "{}").unwrap();
+        assert!(cache.lookup(&new).is_none());
+        std::fs::remove_file(download_state_path(&cache.entry_path(&new))).unwrap();
+
+        assert_eq!(cache.evict(&cache.entry_path(&new)), 6);
+        assert!(!cache.entry_path(&old).exists());
+        assert!(cache.entry_path(&new).exists());
+
+        assert_eq!(cache.clear().unwrap(), 6);
+        assert_eq!(cache.size(), 0);
+    }
+}
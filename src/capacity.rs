@@ -0,0 +1,305 @@
+// Copyright (C) 2026 SpruceOS Team
+// Licensed under GPL-3.0-or-later
+
+// Fake capacity detection
+// Counterfeit cards report a large size but only store the first few GB: writes past
+// the real end wrap around onto earlier blocks or are silently dropped. Like f3probe,
+// we write blocks tagged with their own offset at offsets spread over the whole card,
+// read them all back, and take the real capacity to end where the first block no
+// longer holds its own data. The original contents of every probed block are written
+// back afterwards, so the check is safe on a card that still holds data.
+
+use crate::burn::{device_size, open_device_for_writing, unmount_device, DEVICE_IO_ALIGNMENT};
+use crate::drives::DriveInfo;
+use std::io::{Read, Seek, SeekFrom, Write};
+use tokio::sync::mpsc::UnboundedSender;
+use tokio_util::sync::CancellationToken;
+
+/// Size of each probed block
+const PROBE_BLOCK_SIZE: usize = DEVICE_IO_ALIGNMENT;
+/// Number of blocks probed, evenly spread over the reported size
+const PROBE_COUNT: u64 = 64;
+const PROBE_MAGIC: &[u8; 16] = b"SPRUCE-CAPACITY\0";
+
+#[derive(Debug, Clone)]
+pub enum ProbeProgress {
+    Started { probes: u64 },
+    Writing { done: u64, total: u64 },
+    Reading { done: u64, total: u64 },
+    Restoring,
+    Completed,
+    Cancelled,
+    Error(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapacityReport {
+    /// Size the card claims to have
+    pub reported_bytes: u64,
+    /// Size up to the first block that read back the wrong data (reported_bytes if none did)
+    pub verified_bytes: u64,
+    pub probes: u64,
+    /// Blocks that read back someone else's data (or none of theirs)
+    pub failed_probes: u64,
+    /// Blocks that couldn't be read back at all, a sign of bad sectors rather than a fake card
+    pub unreadable_probes: u64,
+}
+
+impl CapacityReport {
+    /// Only blocks holding the wrong data count; read errors alone don't make a card fake
+    pub fn is_fake(&self) -> bool {
+        self.failed_probes > 0
+    }
+
+    /// One-line description for logs and the CLI
+    pub fn summary(&self) -> String {
+        if self.is_fake() {
+            format!(
+                "Card reports {:.1} GB but only about {:.1} GB can be used ({} of {} test blocks were lost). It is likely counterfeit",
+                gb(self.reported_bytes),
+                gb(self.verified_bytes),
+                self.failed_probes,
+                self.probes
+            )
+        } else if self.unreadable_probes > 0 {
+            format!(
+                "{} of {} test blocks could not be read back, the card may be failing (no sign of fake capacity)",
+                self.unreadable_probes, self.probes
+            )
+        } else {
+            format!("All {} test blocks verified, the card holds its reported {:.1} GB", self.probes, gb(self.reported_bytes))
+        }
+    }
+}
+
+fn gb(bytes: u64) -> f64 {
+    bytes as f64 / 1_000_000_000.0
+}
+
+/// Aligned offsets of the probed blocks, lowest first; the last one is the final block
+fn probe_offsets(device_size: u64) -> Vec<u64> {
+    let block = PROBE_BLOCK_SIZE as u64;
+    let blocks = device_size / block;
+    if blocks == 0 {
+        return Vec::new();
+    }
+    let mut offsets: Vec<u64> = (1..=PROBE_COUNT)
+        .map(|i| (blocks * i / PROBE_COUNT).saturating_sub(1) * block)
+        .collect();
+    offsets.dedup();
+    offsets
+}
+
+/// Test pattern for the block at `offset`: magic, offset, nonce, then pseudo-random data
+fn probe_pattern(offset: u64, nonce: u64, block: &mut [u8]) {
+    block[..16].copy_from_slice(PROBE_MAGIC);
+    block[16..24].copy_from_slice(&offset.to_le_bytes());
+    block[24..32].copy_from_slice(&nonce.to_le_bytes());
+
+    // Seeded per block so a block copied elsewhere is recognisably wrong
+    let mut state = (nonce ^ offset.rotate_left(17)) | 1;
+    for chunk in block[32..].chunks_mut(8) {
+        chunk.copy_from_slice(&xorshift64(&mut state).to_le_bytes()[..chunk.len()]);
+    }
+}
+
+/// Cheap pseudo-random numbers (xorshift64); `state` must not be zero
+pub(crate) fn xorshift64(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+/// Highest usable size given which probes (lowest offset first) read back their own data
+/// `results` is None for a block that couldn't be read, which doesn't end the verified area.
+fn verified_size(offsets: &[u64], results: &[Option<bool>], reported: u64) -> u64 {
+    match results.iter().position(|&result| result == Some(false)) {
+        None => reported,
+        Some(0) => 0,
+        // Everything up to the last good block is known to work
+        Some(first_bad) => offsets[first_bad - 1] + PROBE_BLOCK_SIZE as u64,
+    }
+}
+
+/// Probe a card for fake capacity
+/// The device is unmounted; its contents are unchanged afterwards (also when cancelled).
+pub async fn probe_capacity(
+    drive: &DriveInfo,
+    progress_tx: UnboundedSender<ProbeProgress>,
+    cancel_token: CancellationToken,
+) -> Result<CapacityReport, String> {
+    crate::debug::log_section("Capacity Check");
+    crate::debug::log(&format!("Device: {} (reports {} bytes)", drive.device_path, drive.size_bytes));
+
+    unmount_device(&drive.device_path).await?;
+
+    let result = tokio::task::spawn_blocking({
+        let device_path = drive.device_path.clone();
+        let fallback_size = drive.size_bytes;
+        let progress_tx = progress_tx.clone();
+        let cancel_token = cancel_token.clone();
+
+        move || -> Result<CapacityReport, String> {
+            let mut device = open_device_for_writing(&device_path)?;
+            let reported = device_size(&device.file).unwrap_or(fallback_size);
+            let offsets = probe_offsets(reported);
+            let total = offsets.len() as u64;
+            if total == 0 {
+                return Err(format!("Device is too small to check ({} bytes)", reported));
+            }
+            let _ = progress_tx.send(ProbeProgress::Started { probes: total });
+
+            // Unbuffered I/O needs an aligned buffer
+            let mut storage = vec![0u8; PROBE_BLOCK_SIZE * 2];
+            let start = storage.as_ptr().align_offset(DEVICE_IO_ALIGNMENT);
+            let buffer = &mut storage[start..start + PROBE_BLOCK_SIZE];
+            let file = &mut device.file;
+
+            let read_block = |file: &mut std::fs::File, offset: u64, buffer: &mut [u8]| -> Result<(), String> {
+                file.seek(SeekFrom::Start(offset))
+                    .and_then(|_| file.read_exact(buffer))
+                    .map_err(|e| format!("Failed to read device at offset {}: {}", offset, e))
+            };
+            let write_block = |file: &mut std::fs::File, offset: u64, buffer: &[u8]| -> Result<(), String> {
+                file.seek(SeekFrom::Start(offset))
+                    .and_then(|_| file.write_all(buffer))
+                    .map_err(|e| format!("Failed to write device at offset {}: {}", offset, e))
+            };
+
+            // Blocks that can't even be read are not restored (there is nothing to keep)
+            let originals: Vec<Option<Vec<u8>>> = offsets
+                .iter()
+                .map(|&offset| read_block(file, offset, buffer).ok().map(|_| buffer.to_vec()))
+                .collect();
+
+            let nonce = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos() as u64)
+                .unwrap_or(0x5EED);
+
+            // Highest offsets first: on a card that wraps around, their writes land on lower
+            // blocks, which are then written afterwards and keep their own pattern
+            let mut probe = || -> Result<Vec<Option<bool>>, String> {
+                for (done, &offset) in offsets.iter().rev().enumerate() {
+                    if cancel_token.is_cancelled() {
+                        return Err("Capacity check cancelled".to_string());
+                    }
+                    probe_pattern(offset, nonce, buffer);
+                    // Fake areas may also reject writes; the read-back marks the block as lost
+                    if let Err(e) = write_block(file, offset, buffer) {
+                        crate::debug::log(&e);
+                    }
+                    let _ = progress_tx.send(ProbeProgress::Writing { done: done as u64 + 1, total });
+                }
+                file.sync_all().map_err(|e| format!("Failed to flush device: {}", e))?;
+
+                let mut expected = vec![0u8; PROBE_BLOCK_SIZE];
+                let mut results = Vec::with_capacity(offsets.len());
+                for (done, &offset) in offsets.iter().enumerate() {
+                    if cancel_token.is_cancelled() {
+                        return Err("Capacity check cancelled".to_string());
+                    }
+                    probe_pattern(offset, nonce, &mut expected);
+                    // A block that can't be read is logged and reported apart from one with the wrong data
+                    results.push(match read_block(file, offset, buffer) {
+                        Ok(()) => Some(*buffer == expected[..]),
+                        Err(e) => {
+                            crate::debug::log(&e);
+                            None
+                        }
+                    });
+                    let _ = progress_tx.send(ProbeProgress::Reading { done: done as u64 + 1, total });
+                }
+                Ok(results)
+            };
+            let result = probe();
+
+            // Same order as the test writes, so the real blocks get their own data back last
+            let _ = progress_tx.send(ProbeProgress::Restoring);
+            for (&offset, original) in offsets.iter().zip(&originals).rev() {
+                let Some(original) = original else { continue };
+                buffer.copy_from_slice(original);
+                if let Err(e) = write_block(file, offset, buffer) {
+                    crate::debug::log(&format!("WARNING: {}", e));
+                }
+            }
+            let _ = file.sync_all();
+
+            let results = result?;
+            let report = CapacityReport {
+                reported_bytes: reported,
+                verified_bytes: verified_size(&offsets, &results, reported),
+                probes: total,
+                failed_probes: results.iter().filter(|&&result| result == Some(false)).count() as u64,
+                unreadable_probes: results.iter().filter(|result| result.is_none()).count() as u64,
+            };
+            crate::debug::log(&report.summary());
+            Ok(report)
+        }
+    })
+    .await
+    .map_err(|e| format!("Capacity check task failed: {}", e))?;
+
+    match &result {
+        Ok(_) => {
+            let _ = progress_tx.send(ProbeProgress::Completed);
+        }
+        Err(_) if cancel_token.is_cancelled() => {
+            let _ = progress_tx.send(ProbeProgress::Cancelled);
+        }
+        Err(e) => {
+            let _ = progress_tx.send(ProbeProgress::Error(e.clone()));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_probe_offsets() {
+        let size = 128_000_000_000u64;
+        let offsets = probe_offsets(size);
+        assert_eq!(offsets.len() as u64, PROBE_COUNT);
+        assert!(offsets.windows(2).all(|w| w[0] < w[1]));
+        assert!(offsets.iter().all(|o| o % PROBE_BLOCK_SIZE as u64 == 0));
+        assert_eq!(*offsets.last().unwrap(), (size / 4096 - 1) * 4096);
+        assert_eq!(probe_offsets(10_000).len(), 2);
+    }
+
+    #[test]
+    fn test_probe_pattern() {
+        let mut a = vec![0u8; PROBE_BLOCK_SIZE];
+        let mut b = vec![0u8; PROBE_BLOCK_SIZE];
+        probe_pattern(4096, 42, &mut a);
+        probe_pattern(8192, 42, &mut b);
+        assert_eq!(&a[..16], PROBE_MAGIC);
+        assert_ne!(a[32..], b[32..]);
+    }
+
+    #[test]
+    fn test_verified_size() {
+        let offsets = [0, 4096, 8192, 12288];
+        assert_eq!(verified_size(&offsets, &[Some(true); 4], 16384), 16384);
+        assert_eq!(verified_size(&offsets, &[Some(true), Some(true), Some(false), Some(false)], 16384), 8192);
+        assert_eq!(verified_size(&offsets, &[Some(false), Some(true), Some(true), Some(true)], 16384), 0);
+        // Unreadable blocks are bad sectors, not the end of the real capacity
+        assert_eq!(verified_size(&offsets, &[Some(true), None, Some(true), Some(true)], 16384), 16384);
+    }
+
+    #[test]
+    fn test_is_fake() {
+        let report = CapacityReport {
+            reported_bytes: 16384,
+            verified_bytes: 16384,
+            probes: 4,
+            failed_probes: 0,
+            unreadable_probes: 1,
+        };
+        assert!(!report.is_fake());
+        assert!(CapacityReport { failed_probes: 1, ..report }.is_fake());
+    }
+}
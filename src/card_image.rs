@@ -0,0 +1,329 @@
+// Copyright (C) 2026 SpruceOS Team
+// Licensed under GPL-3.0-or-later
+
+// Offline card images
+// Builds a ready-to-burn image of a freshly installed card without any hardware:
+// an image file gets the same MBR and FAT32 volume fat32.rs writes to a real card,
+// the extracted release is written into it with fat32_writer.rs, and the result is
+// compressed like a backup. The image burns with burn_image like any release image.
+
+use crate::backup::Encoder;
+use crate::compression::Compression;
+use crate::config::{Filesystem, TEMP_PREFIX};
+use crate::copy::CopyProgress;
+use crate::fat32::{FormatOptions, SECTOR_SIZE};
+use crate::fat32_writer::{find_partition, Fat32Writer, ROOT_DIR};
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+const CHUNK_SIZE: usize = 4 * 1024 * 1024; // 4MB chunks
+/// Smallest image created when the size is picked automatically
+const MIN_IMAGE_SIZE: u64 = 64 * 1024 * 1024;
+/// Files are counted as whole clusters of this size when picking a size (the largest FAT32 uses)
+const ESTIMATE_CLUSTER_SIZE: u64 = 32 * 1024;
+
+#[derive(Debug, Clone)]
+pub enum ImageProgress {
+    Formatting { percent: u8 },
+    Copying { copied_bytes: u64, total_bytes: u64 },
+    Compressing { done: u64, total: u64 },
+    Completed,
+    Cancelled,
+    #[allow(dead_code)]
+    Error(String),
+}
+
+/// Compression of an image file from its name (None for a plain .img)
+pub fn image_compression(path: &Path) -> Result<Option<Compression>, String> {
+    let name = path.file_name().map(|n| n.to_string_lossy().to_lowercase()).unwrap_or_default();
+    if name.ends_with(".img.gz") {
+        Ok(Some(Compression::Gzip))
+    } else if name.ends_with(".img.zst") {
+        Ok(Some(Compression::Zstd))
+    } else if name.ends_with(".img") {
+        Ok(None)
+    } else {
+        Err(format!("Image file must end in .img, .img.gz or .img.zst: {}", path.display()))
+    }
+}
+
+/// Parse an image size such as "8G", "3.2GB", "3900MiB" or a plain byte count
+/// K/M/G/T are decimal like card sizes (an "8 GB" card holds 8,000,000,000 bytes); KiB/MiB/GiB/TiB are binary.
+pub fn parse_size(text: &str) -> Result<u64, String> {
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let multiplier: u64 = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" => 1_000,
+        "M" | "MB" => 1_000_000,
+        "G" | "GB" => 1_000_000_000,
+        "T" | "TB" => 1_000_000_000_000,
+        "KI" | "KIB" => 1 << 10,
+        "MI" | "MIB" => 1 << 20,
+        "GI" | "GIB" => 1 << 30,
+        "TI" | "TIB" => 1 << 40,
+        _ => return Err(format!("Unknown size unit in '{}' (use K, M, G, T or KiB, MiB, GiB, TiB)", text)),
+    };
+    let value: f64 = number.parse().map_err(|_| format!("Invalid size '{}'", text))?;
+    let bytes = (value * multiplier as f64) as u64;
+    // Whole sectors only; a partial last sector could never be used
+    let bytes = bytes - bytes % SECTOR_SIZE as u64;
+    if bytes == 0 {
+        return Err(format!("Invalid size '{}'", text));
+    }
+    Ok(bytes)
+}
+
+/// Image size that comfortably holds a folder: every file and folder counted as whole
+/// clusters, plus 10%, the FATs' share and the alignment padding, rounded up to a whole MiB
+/// With a partition layout the other partitions are added at their size (a last one that
+/// takes the rest of the card gets MIN_IMAGE_SIZE), and a fixed-size install partition is used as is.
+pub fn fitting_image_size(source_dir: &Path, options: &FormatOptions) -> Result<u64, String> {
+    fn tree_bytes(dir: &Path) -> std::io::Result<u64> {
+        let mut bytes = ESTIMATE_CLUSTER_SIZE;
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            bytes += if path.is_dir() {
+                tree_bytes(&path)?
+            } else {
+                std::fs::metadata(&path)?.len().div_ceil(ESTIMATE_CLUSTER_SIZE) * ESTIMATE_CLUSTER_SIZE
+            };
+        }
+        Ok(bytes)
+    }
+
+    let data = tree_bytes(source_dir).map_err(|e| format!("Failed to scan source directory: {}", e))?;
+    let align = options.alignment as u64;
+    // Before the data area; the space before the first partition is added below
+    let install_bytes = data + data / 10 + align + 16 * 1024 * 1024;
+    let (_, install) = options.install_target()?;
+    let size = match install {
+        None => align + install_bytes,
+        Some(install) => options.partitions.iter().fold(align, |size, spec| {
+            size + match spec.size {
+                Some(bytes) => bytes.next_multiple_of(align),
+                None if std::ptr::eq(spec, install) => install_bytes,
+                None => MIN_IMAGE_SIZE,
+            }
+        }),
+    };
+    Ok(size.next_multiple_of(1024 * 1024).max(MIN_IMAGE_SIZE))
+}
+
+/// Build a card image of `image_bytes` holding the contents of `source_dir`
+/// The image is written as `<output>.partial` and renamed once complete (the
+/// uncompressed image of a .img.gz/.img.zst goes to a temporary file next to it).
+/// With a partition layout the files go into its install partition, and the layout's own
+/// labels replace `volume_label`. Returns the size of the finished file.
+pub async fn build_card_image(
+    source_dir: &Path,
+    output: &Path,
+    image_bytes: u64,
+    volume_label: &str,
+    options: &FormatOptions,
+    progress_tx: mpsc::UnboundedSender<ImageProgress>,
+    cancel_token: CancellationToken,
+) -> Result<u64, String> {
+    crate::debug::log_section("Build Card Image");
+    crate::debug::log(&format!("Source: {:?}", source_dir));
+    crate::debug::log(&format!("Image file: {:?} ({} bytes)", output, image_bytes));
+
+    let compression = image_compression(output)?;
+    // fat32_writer.rs only writes FAT32 volumes
+    let (partition, install) = options.install_target()?;
+    let filesystem = install.and_then(|spec| spec.filesystem).unwrap_or(options.filesystem);
+    if filesystem != Filesystem::Fat32 {
+        return Err(format!("Card images can only be built with FAT32, not {}", filesystem.name()));
+    }
+    let options = *options;
+    let output_dir = output
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    std::fs::create_dir_all(&output_dir).map_err(|e| format!("Failed to create image folder: {}", e))?;
+
+    let mut partial_name = output.as_os_str().to_os_string();
+    partial_name.push(".partial");
+    let partial_path = PathBuf::from(partial_name);
+
+    // Copy progress comes from the writer; forward it while the blocking task runs
+    let (copy_tx, mut copy_rx) = mpsc::unbounded_channel::<CopyProgress>();
+    let forwarder = tokio::spawn({
+        let progress_tx = progress_tx.clone();
+        async move {
+            while let Some(progress) = copy_rx.recv().await {
+                if let CopyProgress::Progress { copied_bytes, total_bytes, .. } = progress {
+                    let _ = progress_tx.send(ImageProgress::Copying { copied_bytes, total_bytes });
+                }
+            }
+        }
+    });
+
+    let result = tokio::task::spawn_blocking({
+        let source_dir = source_dir.to_path_buf();
+        let volume_label = volume_label.to_string();
+        let partial_path = partial_path.clone();
+        let progress_tx = progress_tx.clone();
+        let cancel_token = cancel_token.clone();
+
+        move || -> Result<u64, String> {
+            // A compressed image is built uncompressed first; the temporary file is removed when dropped
+            let raw_temp = match compression {
+                Some(_) => Some(
+                    tempfile::Builder::new()
+                        .prefix(TEMP_PREFIX)
+                        .suffix(".img")
+                        .tempfile_in(&output_dir)
+                        .map_err(|e| format!("Failed to create temporary image: {}", e))?,
+                ),
+                None => None,
+            };
+            let raw_path = raw_temp.as_ref().map_or(partial_path.as_path(), |temp| temp.path());
+            let mut raw = std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(raw_path)
+                .map_err(|e| format!("Failed to create image file: {}", e))?;
+            // Sparse where the filesystem supports it; only written areas take space
+            raw.set_len(image_bytes).map_err(|e| format!("Failed to size image file: {}", e))?;
+
+            crate::fat32::format_device(&mut raw, image_bytes, &options, &volume_label, &cancel_token, |percent| {
+                let _ = progress_tx.send(ImageProgress::Formatting { percent });
+            })?;
+
+            let volume_offset = find_partition(&mut raw, partition)?;
+            let mut writer = Fat32Writer::open(&mut raw, volume_offset)?;
+            writer.copy_tree(&source_dir, ROOT_DIR, &copy_tx, &cancel_token)?;
+            writer.finish()?;
+            drop(copy_tx);
+
+            let Some(compression) = compression else {
+                raw.sync_all().map_err(|e| format!("Failed to write image file: {}", e))?;
+                return Ok(image_bytes);
+            };
+
+            let file = std::fs::File::create(&partial_path)
+                .map_err(|e| format!("Failed to create image file: {}", e))?;
+            let mut encoder = Encoder::new(compression, file)?;
+            let mut raw = std::fs::File::open(raw_path).map_err(|e| format!("Failed to read image: {}", e))?;
+            let mut buffer = vec![0u8; CHUNK_SIZE];
+            let mut done = 0u64;
+            loop {
+                if cancel_token.is_cancelled() {
+                    crate::debug::log("Image build cancelled by user");
+                    return Err("Image build cancelled".to_string());
+                }
+                let read = raw.read(&mut buffer).map_err(|e| format!("Failed to read image: {}", e))?;
+                if read == 0 {
+                    break;
+                }
+                encoder.write_all(&buffer[..read]).map_err(|e| format!("Failed to write image file: {}", e))?;
+                done += read as u64;
+                let _ = progress_tx.send(ImageProgress::Compressing { done, total: image_bytes });
+            }
+            let mut file = encoder.finish().map_err(|e| format!("Failed to write image file: {}", e))?;
+            file.flush()
+                .and_then(|_| file.sync_all())
+                .map_err(|e| format!("Failed to write image file: {}", e))?;
+            file.metadata()
+                .map(|m| m.len())
+                .map_err(|e| format!("Failed to write image file: {}", e))
+        }
+    })
+    .await
+    .map_err(|e| format!("Image build task failed: {}", e))?;
+    let _ = forwarder.await;
+
+    let result = result.and_then(|size| {
+        std::fs::rename(&partial_path, output).map_err(|e| format!("Failed to save image file: {}", e))?;
+        crate::debug::log(&format!("Image saved: {} bytes", size));
+        Ok(size)
+    });
+
+    match &result {
+        Ok(_) => {
+            let _ = progress_tx.send(ImageProgress::Completed);
+        }
+        Err(_) if cancel_token.is_cancelled() => {
+            let _ = std::fs::remove_file(&partial_path);
+            let _ = progress_tx.send(ImageProgress::Cancelled);
+        }
+        Err(e) => {
+            let _ = std::fs::remove_file(&partial_path);
+            let _ = progress_tx.send(ImageProgress::Error(e.clone()));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fat32_writer::tests::read_file;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("8G").unwrap(), 8_000_000_000);
+        assert_eq!(parse_size("3.2 GB").unwrap(), 3_200_000_000);
+        assert_eq!(parse_size("64MiB").unwrap(), 64 * 1024 * 1024);
+        assert_eq!(parse_size("1000").unwrap(), 512);
+        assert!(parse_size("8X").is_err());
+        assert!(parse_size("").is_err());
+        assert!(parse_size("100").is_err());
+    }
+
+    #[test]
+    fn test_image_compression() {
+        assert_eq!(image_compression(Path::new("card.img.gz")).unwrap(), Some(Compression::Gzip));
+        assert_eq!(image_compression(Path::new("card.IMG.zst")).unwrap(), Some(Compression::Zstd));
+        assert_eq!(image_compression(Path::new("card.img")).unwrap(), None);
+        assert!(image_compression(Path::new("card.7z")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_build_card_image() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("release");
+        std::fs::create_dir_all(source.join("spruce/bin")).unwrap();
+        std::fs::write(source.join("spruce/bin/payload.bin"), b"SPRUCE-PAYLOAD-1234").unwrap();
+        std::fs::write(source.join("autorun.inf"), b"[autorun]").unwrap();
+
+        // Aligned to a 4 MiB erase block like a repository may ask for
+        let options = FormatOptions { alignment: 4 * 1024 * 1024, ..FormatOptions::default() };
+        let size = fitting_image_size(&source, &options).unwrap();
+        assert_eq!(size, MIN_IMAGE_SIZE);
+
+        let output = dir.path().join("card.img.gz");
+        let (tx, _rx) = mpsc::unbounded_channel();
+        build_card_image(&source, &output, size, "SPRUCEOS", &options, tx, CancellationToken::new())
+            .await
+            .unwrap();
+        let leftovers: Vec<_> = std::fs::read_dir(dir.path()).unwrap().map(|e| e.unwrap().file_name()).collect();
+        assert_eq!(leftovers.len(), 2, "{:?}", leftovers);
+
+        let mut image = Vec::new();
+        flate2::read::GzDecoder::new(std::fs::File::open(&output).unwrap())
+            .read_to_end(&mut image)
+            .unwrap();
+        assert_eq!(image.len() as u64, size);
+        assert_eq!(image[510..512], [0x55, 0xAA]);
+
+        let mut image = Cursor::new(image);
+        let offset = find_partition(&mut image, 1).unwrap();
+        assert_eq!(offset, 4 * 1024 * 1024);
+        // The volume has files in it now, so it can't be opened as a fresh one
+        assert!(Fat32Writer::open(&mut image, offset).is_err());
+        assert_eq!(
+            read_file(&mut image, offset, "spruce/bin/payload.bin"),
+            Some((19, b"SPRUCE-PAYLOAD-1234".to_vec()))
+        );
+        assert_eq!(read_file(&mut image, offset, "autorun.inf"), Some((9, b"[autorun]".to_vec())));
+    }
+}
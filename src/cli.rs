@@ -0,0 +1,1199 @@
+// Copyright (C) 2026 SpruceOS Team
+// Licensed under GPL-3.0-or-later
+
+// Headless command-line mode
+// Runs the same InstallPipeline as the GUI (see pipeline.rs) without creating a window
+//
+// Usage:
+//   spruceos-installer install --repo <name> --device <path> [--asset <name>] [--update] [--keep-data] [--yes]
+//   spruceos-installer install --file <path> --device <path> [--repo <name>] [--update] [--yes]
+//   spruceos-installer backup --device <path> --output <file.img.zst> [--used-only]
+//   spruceos-installer check-capacity --device <path>
+//   spruceos-installer benchmark --device <path> [--raw] [--yes]
+//   spruceos-installer build-image --file <archive> --output <file.img.gz> [--repo <name>] [--size <size>] [--label <name>]
+//   spruceos-installer list-drives
+//   spruceos-installer list-assets --repo <name> [--release <tag>]
+//   spruceos-installer list-releases --repo <name>
+//   spruceos-installer clear-cache
+//
+// Progress is printed to stdout, errors to stderr. See the EXIT_* constants
+// below for the exit codes returned to the calling shell.
+
+use crate::app::InstallerApp;
+use crate::backup::{backup_device, BackupExtent, BackupProgress, BackupTarget, UserDataBackup};
+use crate::benchmark::{benchmark_drive, BenchmarkMode, BenchmarkProgress};
+use crate::capacity::{probe_capacity, ProbeProgress};
+use crate::card_image::{build_card_image, fitting_image_size, image_compression, parse_size, ImageProgress};
+use crate::cache::DownloadCache;
+use crate::config::{Filesystem, RepoOption, APP_NAME, DEFAULT_REPO_INDEX, REPO_OPTIONS, TEMP_PREFIX, VOLUME_LABEL};
+use crate::drives::{get_removable_drives, DriveInfo};
+use crate::extract::{extract_archive, ExtractProgress};
+use crate::fat32::FormatOptions;
+use crate::github::{apply_bmaps, apply_checksums, get_checksums_from_release, get_latest_release, get_manifest_from_release, get_release_by_tag, list_releases, Asset, Release};
+use crate::pipeline::{is_raw_image, InstallEvent, InstallPipeline};
+use std::future::Future;
+use std::io::Write;
+use std::path::PathBuf;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+/// Installation finished successfully
+pub const EXIT_SUCCESS: i32 = 0;
+/// Installation failed (download, format, extract, copy or burn error)
+pub const EXIT_FAILURE: i32 = 1;
+/// Invalid command line (unknown option, repo, asset or device)
+pub const EXIT_USAGE: i32 = 2;
+/// User declined the confirmation prompt
+pub const EXIT_ABORTED: i32 = 3;
+/// Installation was cancelled with Ctrl+C
+pub const EXIT_CANCELLED: i32 = 130;
+
+/// Options of the subcommands (each accepts only its own, see allowed_options)
+#[derive(Debug, Default)]
+struct CommandArgs {
+    repo: Option<String>,
+    asset: Option<String>,
+    device: Option<String>,
+    file: Option<String>,
+    release: Option<String>,
+    update_mode: bool,
+    /// Back the card up to this file first (`install --backup`)
+    backup: Option<String>,
+    /// File to create (`backup`, `build-image`)
+    output: Option<String>,
+    backup_used_only: bool,
+    /// Save repo.backup_directories to the computer first (--save-data), and copy them back (--keep-data)
+    save_data: bool,
+    restore_data: bool,
+    check_capacity: bool,
+    /// Test the card's speed (--benchmark for `install`, --raw for `benchmark`)
+    benchmark: bool,
+    raw: bool,
+    /// Format with this instead of the repository's filesystem (`install --filesystem`)
+    filesystem: Option<Filesystem>,
+    /// Image size and volume label for `build-image`
+    size: Option<String>,
+    label: Option<String>,
+    assume_yes: bool,
+    verbose: bool,
+    /// Print the subcommand's usage instead of running it
+    help: bool,
+}
+
+/// Usage line of each subcommand (several for one with alternative forms)
+const USAGE: &[(&str, &str)] = &[
+    ("install", "--repo <name> --device <path> [--release <tag>] [--asset <name>] [--update] [--filesystem <fs>] [--backup <file> [--used-only]] [--save-data | --keep-data] [--check-capacity] [--benchmark] [--yes] [--verbose]"),
+    ("install", "--file <path> --device <path> [--repo <name>] [--update] [--filesystem <fs>] [--backup <file> [--used-only]] [--save-data | --keep-data] [--check-capacity] [--benchmark] [--yes] [--verbose]"),
+    ("backup", "--device <path> --output <file> [--used-only]"),
+    ("check-capacity", "--device <path>"),
+    ("benchmark", "--device <path> [--raw] [--yes]"),
+    ("build-image", "--file <archive> --output <file> [--repo <name>] [--size <size>] [--label <name>]"),
+    ("list-drives", ""),
+    ("list-assets", "--repo <name> [--release <tag>]"),
+    ("list-releases", "--repo <name>"),
+    ("clear-cache", ""),
+];
+
+/// Options and their descriptions, in the order --help lists them
+const OPTIONS: &[(&str, &str)] = &[
+    ("--repo <name>", "Repository to install from, or whose card layout build-image uses (see Repositories below)"),
+    ("--device <path>", "Target SD card (e.g. /dev/sdb, /dev/disk4, E:)"),
+    ("--release <tag>", "Install this release or pre-release instead of the latest"),
+    ("--asset <name>", "Release file to install (required if the release has several)"),
+    ("--file <path>", "Install a local archive (.7z, .zip, .tar.*) or disk image instead of downloading"),
+    ("--update", "Update an existing installation instead of formatting"),
+    ("--filesystem <fs>", "Format the card as fat32 or exfat instead of the repository's filesystem (not for disk images)"),
+    ("--backup <file>", "Back the card up to a .img.zst or .img.gz file before erasing it"),
+    ("--save-data", "Copy the repository's user data folders (saves, ROMs, ...) to this computer first"),
+    ("--keep-data", "Like --save-data, then copy them back onto the card after installing"),
+    ("--check-capacity", "Check the card for fake capacity before erasing it"),
+    ("--benchmark", "Test the card's speed before installing"),
+    ("--output <file>", "Backup file (backup) or .img/.img.gz/.img.zst card image (build-image) to create"),
+    ("--used-only", "Back up only up to the end of the last partition"),
+    ("--raw", "Benchmark the raw device (overwrites 64 MB of data) instead of a test file"),
+    ("--size <size>", "Card image size, e.g. 8G (decimal like card sizes) or 4GiB; default fits the files"),
+    ("--label <name>", "Volume label of the card image (default: the label installs use)"),
+    ("--yes", "Do not ask for confirmation before erasing the card"),
+    ("--verbose", "Echo the debug log to stdout"),
+];
+
+/// Entry point for command-line mode
+/// Returns None if no subcommand was given (the GUI should start instead),
+/// otherwise the process exit code
+pub fn run(args: &[String]) -> Option<i32> {
+    let command = args.first()?.as_str();
+
+    let code = match command {
+        "install" | "backup" | "check-capacity" | "benchmark" | "build-image" | "list-drives" | "list-assets" | "list-releases" | "clear-cache" | "help" | "--help" | "-h" => {
+            attach_console();
+            match command {
+                "install" => run_install(&args[1..]),
+                "backup" => run_backup(&args[1..]),
+                "check-capacity" => run_check_capacity(&args[1..]),
+                "benchmark" => run_benchmark(&args[1..]),
+                "build-image" => run_build_image(&args[1..]),
+                "list-drives" => run_list_drives(&args[1..]),
+                "list-assets" => run_list_assets(&args[1..]),
+                "list-releases" => run_list_releases(&args[1..]),
+                "clear-cache" => run_clear_cache(&args[1..]),
+                _ => {
+                    print_usage();
+                    EXIT_SUCCESS
+                }
+            }
+        }
+        _ => return None,
+    };
+
+    Some(code)
+}
+
+fn print_usage() {
+    println!("{} Installer", APP_NAME);
+    println!();
+    print_usage_lines(|_| true);
+    println!();
+    println!("Options:");
+    print_options(|_| true);
+    println!();
+    println!("Repositories: {}", repo_names());
+    println!();
+    println!("Exit codes:");
+    println!("  {}    success", EXIT_SUCCESS);
+    println!("  {}    installation failed", EXIT_FAILURE);
+    println!("  {}    invalid arguments", EXIT_USAGE);
+    println!("  {}    confirmation declined", EXIT_ABORTED);
+    println!("  {}  cancelled (Ctrl+C)", EXIT_CANCELLED);
+}
+
+/// Usage of one subcommand (`<command> --help`)
+fn print_command_usage(command: &str) {
+    print_usage_lines(|c| c == command);
+    let allowed = allowed_options(command);
+    if !allowed.is_empty() {
+        println!();
+        println!("Options:");
+        print_options(|option| allowed.contains(&option));
+    }
+    if allowed.contains(&"--repo") {
+        println!();
+        println!("Repositories: {}", repo_names());
+    }
+}
+
+fn print_usage_lines(show: impl Fn(&str) -> bool) {
+    println!("Usage:");
+    for (command, usage) in USAGE.iter().filter(|(command, _)| show(command)) {
+        let line = format!("{} {} {}", env!("CARGO_PKG_NAME"), command, usage);
+        println!("  {}", line.trim_end());
+    }
+}
+
+fn print_options(show: impl Fn(&str) -> bool) {
+    for (option, description) in OPTIONS {
+        let name = option.split_whitespace().next().unwrap_or(option);
+        if show(name) {
+            println!("  {:<18}{}", option, description);
+        }
+    }
+}
+
+fn repo_names() -> String {
+    REPO_OPTIONS.iter().map(|r| r.name).collect::<Vec<_>>().join(", ")
+}
+
+/// Windows release builds use the GUI subsystem, so stdout is not connected to
+/// the terminal unless we attach to the parent console explicitly
+fn attach_console() {
+    #[cfg(target_os = "windows")]
+    unsafe {
+        use windows::Win32::System::Console::{AttachConsole, ATTACH_PARENT_PROCESS};
+        let _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+/// Long options a subcommand accepts; anything else is rejected rather than ignored
+fn allowed_options(command: &str) -> &'static [&'static str] {
+    match command {
+        "install" => &["--repo", "--asset", "--device", "--file", "--release", "--update", "--filesystem", "--backup", "--used-only", "--save-data", "--keep-data", "--check-capacity", "--benchmark", "--yes", "--verbose"],
+        "backup" => &["--device", "--output", "--used-only", "--verbose"],
+        "check-capacity" => &["--device", "--verbose"],
+        "benchmark" => &["--device", "--raw", "--yes", "--verbose"],
+        "build-image" => &["--repo", "--file", "--output", "--size", "--label", "--verbose"],
+        "list-assets" => &["--repo", "--release"],
+        "list-releases" => &["--repo"],
+        _ => &[],
+    }
+}
+
+fn parse_args(command: &str, args: &[String]) -> Result<CommandArgs, String> {
+    let mut parsed = CommandArgs::default();
+    let mut iter = args.iter();
+    let allowed = allowed_options(command);
+
+    while let Some(arg) = iter.next() {
+        let option = match arg.as_str() {
+            "-r" => "--repo",
+            "-a" => "--asset",
+            "-d" => "--device",
+            "-f" => "--file",
+            "-o" => "--output",
+            "-y" => "--yes",
+            "-v" => "--verbose",
+            "-h" => "--help",
+            other => other,
+        };
+        // Every subcommand has a --help
+        if option == "--help" {
+            parsed.help = true;
+            return Ok(parsed);
+        }
+        if !allowed.contains(&option) {
+            return Err(if USAGE.iter().any(|(c, _)| allowed_options(c).contains(&option)) {
+                format!("{} does not apply to {}", arg, command)
+            } else {
+                format!("Unknown option: {}", arg)
+            });
+        }
+
+        match option {
+            "--repo" => parsed.repo = Some(expect_value(&mut iter, arg)?),
+            "--asset" => parsed.asset = Some(expect_value(&mut iter, arg)?),
+            "--device" => parsed.device = Some(expect_value(&mut iter, arg)?),
+            "--file" => parsed.file = Some(expect_value(&mut iter, arg)?),
+            "--release" => parsed.release = Some(expect_value(&mut iter, arg)?),
+            "--update" => parsed.update_mode = true,
+            "--filesystem" => {
+                let name = expect_value(&mut iter, arg)?;
+                let filesystem = Filesystem::from_name(&name)
+                    .ok_or_else(|| format!("Unknown filesystem: {} (expected fat32 or exfat)", name))?;
+                parsed.filesystem = Some(filesystem);
+            }
+            "--backup" => parsed.backup = Some(expect_value(&mut iter, arg)?),
+            "--output" => parsed.output = Some(expect_value(&mut iter, arg)?),
+            "--used-only" => parsed.backup_used_only = true,
+            "--save-data" => parsed.save_data = true,
+            "--check-capacity" => parsed.check_capacity = true,
+            "--benchmark" => parsed.benchmark = true,
+            "--raw" => parsed.raw = true,
+            "--size" => parsed.size = Some(expect_value(&mut iter, arg)?),
+            "--label" => parsed.label = Some(expect_value(&mut iter, arg)?),
+            "--keep-data" => {
+                parsed.save_data = true;
+                parsed.restore_data = true;
+            }
+            "--yes" => parsed.assume_yes = true,
+            "--verbose" => parsed.verbose = true,
+            other => return Err(format!("Unknown option: {}", other)),
+        }
+    }
+
+    // A local file is installed as is, so there is no release or asset to pick
+    if command == "install" && parsed.file.is_some() {
+        if parsed.release.is_some() {
+            return Err("--file cannot be combined with --release".to_string());
+        }
+        if parsed.asset.is_some() {
+            return Err("--file cannot be combined with --asset".to_string());
+        }
+    }
+
+    // --used-only only shapes the backup taken before erasing the card
+    if command == "install" && parsed.backup_used_only && parsed.backup.is_none() {
+        return Err("--used-only requires --backup".to_string());
+    }
+
+    // Disk images are burned as they are, so there is nothing to format
+    if command == "install" && parsed.filesystem.is_some() && parsed.file.as_deref().is_some_and(is_raw_image) {
+        return Err("--filesystem cannot be combined with a disk image".to_string());
+    }
+
+    // Update mode keeps the card's filesystem
+    if command == "install" && parsed.update_mode && parsed.filesystem.is_some() {
+        return Err("--filesystem cannot be combined with --update".to_string());
+    }
+
+    Ok(parsed)
+}
+
+/// Parse a subcommand's options and apply --verbose
+/// Errors and --help are printed here; the exit code is returned
+fn parse_command(command: &str, args: &[String]) -> Result<CommandArgs, i32> {
+    match parse_args(command, args) {
+        Ok(parsed) if parsed.help => {
+            print_command_usage(command);
+            Err(EXIT_SUCCESS)
+        }
+        Ok(parsed) => {
+            crate::debug::set_console_echo(parsed.verbose);
+            Ok(parsed)
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            eprintln!("Run with --help for usage.");
+            Err(EXIT_USAGE)
+        }
+    }
+}
+
+fn expect_value(iter: &mut std::slice::Iter<'_, String>, option: &str) -> Result<String, String> {
+    iter.next()
+        .cloned()
+        .ok_or_else(|| format!("Missing value for {}", option))
+}
+
+fn find_repo(name: &str) -> Result<&'static RepoOption, String> {
+    REPO_OPTIONS
+        .iter()
+        .find(|r| r.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| format!("Unknown repository '{}'. Available: {}", name, repo_names()))
+}
+
+/// Only removable drives reported by drive detection can be targeted,
+/// so a typo can never point the installer at a system disk
+fn find_drive(device: &str) -> Result<DriveInfo, String> {
+    match_drive(&get_removable_drives(), device)
+}
+
+/// Pick `device` (device path or name) out of the detected drives
+fn match_drive(drives: &[DriveInfo], device: &str) -> Result<DriveInfo, String> {
+    drives
+        .iter()
+        .find(|d| d.device_path == device || d.name == device)
+        .cloned()
+        .ok_or_else(|| {
+            let available: Vec<String> = drives.iter().map(|d| d.device_path.clone()).collect();
+            if available.is_empty() {
+                format!("Device '{}' not found: no removable drives detected", device)
+            } else {
+                format!("Device '{}' is not a removable drive. Available: {}", device, available.join(", "))
+            }
+        })
+}
+
+/// Shared start of the subcommands that work on a card: check privileges (if
+/// `needs_root`, only enforced on Linux), find the --device drive and create the runtime
+/// Errors are printed here; the exit code is returned
+fn target_drive(
+    parsed: &CommandArgs,
+    #[allow(unused_variables)] needs_root: bool,
+) -> Result<(DriveInfo, tokio::runtime::Runtime), i32> {
+    let Some(device) = parsed.device.as_deref() else {
+        eprintln!("Error: --device is required");
+        eprintln!("Run with --help for usage.");
+        return Err(EXIT_USAGE);
+    };
+
+    #[cfg(all(unix, not(target_os = "macos")))]
+    if needs_root && unsafe { libc::geteuid() } != 0 {
+        eprintln!("Error: accessing SD cards requires root privileges. Run with sudo.");
+        return Err(EXIT_FAILURE);
+    }
+
+    let drive = match find_drive(device) {
+        Ok(drive) => drive,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return Err(EXIT_USAGE);
+        }
+    };
+
+    let runtime = match build_runtime() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return Err(EXIT_FAILURE);
+        }
+    };
+
+    Ok((drive, runtime))
+}
+
+fn build_runtime() -> Result<tokio::runtime::Runtime, String> {
+    tokio::runtime::Runtime::new().map_err(|e| format!("Failed to create Tokio runtime: {}", e))
+}
+
+/// Token that is cancelled when the user presses Ctrl+C
+fn cancel_on_ctrl_c(runtime: &tokio::runtime::Runtime) -> CancellationToken {
+    let cancel_token = CancellationToken::new();
+    let ctrl_c_token = cancel_token.clone();
+    runtime.spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            eprintln!();
+            eprintln!("Cancelling...");
+            ctrl_c_token.cancel();
+        }
+    });
+    cancel_token
+}
+
+/// Run a card operation with a Ctrl+C cancel token while `print` reports its progress
+/// Failures are printed here; the exit code is returned
+fn run_with_progress<P, T, Printer, Task>(
+    runtime: &tokio::runtime::Runtime,
+    print: impl FnOnce(mpsc::UnboundedReceiver<P>) -> Printer,
+    task: impl FnOnce(mpsc::UnboundedSender<P>, CancellationToken) -> Task,
+) -> Result<T, i32>
+where
+    P: Send + 'static,
+    Printer: Future<Output = ()> + Send + 'static,
+    Task: Future<Output = Result<T, String>>,
+{
+    run_step(runtime, &cancel_on_ctrl_c(runtime), print, task)
+}
+
+/// `run_with_progress` for one of several steps that share a cancel token
+fn run_step<P, T, Printer, Task>(
+    runtime: &tokio::runtime::Runtime,
+    cancel_token: &CancellationToken,
+    print: impl FnOnce(mpsc::UnboundedReceiver<P>) -> Printer,
+    task: impl FnOnce(mpsc::UnboundedSender<P>, CancellationToken) -> Task,
+) -> Result<T, i32>
+where
+    P: Send + 'static,
+    Printer: Future<Output = ()> + Send + 'static,
+    Task: Future<Output = Result<T, String>>,
+{
+    let (progress_tx, progress_rx) = mpsc::unbounded_channel::<P>();
+    let printer = runtime.spawn(print(progress_rx));
+    let result = runtime.block_on(task(progress_tx, cancel_token.clone()));
+    let _ = runtime.block_on(printer);
+
+    result.map_err(|e| {
+        if cancel_token.is_cancelled() {
+            println!("{}", e);
+            EXIT_CANCELLED
+        } else {
+            eprintln!("Error: {}", e);
+            eprintln!("Debug log: {}", crate::debug::get_log_path().display());
+            EXIT_FAILURE
+        }
+    })
+}
+
+/// Fetch a release (the latest unless a tag is given) and the assets the GUI would offer for it
+async fn fetch_assets(repo: &RepoOption, tag: Option<&str>) -> Result<(Release, Vec<Asset>), String> {
+    let release = match tag {
+        Some(tag) => get_release_by_tag(repo.url, tag).await?,
+        None => get_latest_release(repo.url).await?,
+    };
+
+    let assets = match get_manifest_from_release(&release, repo.require_signature).await? {
+        Some(manifest) => {
+            crate::debug::log("Using external assets from manifest.json");
+            let manifest_assets: Vec<Asset> = manifest.assets.into_iter().map(|ma| ma.into()).collect();
+            InstallerApp::filter_assets(manifest_assets, repo.allowed_extensions)
+        }
+        None => InstallerApp::filter_assets(release.assets.clone(), repo.allowed_extensions),
+    };
+
+    let mut assets = assets;
+    let checksums = get_checksums_from_release(&release, repo.require_signature).await?;
+    apply_checksums(&mut assets, &checksums);
+    apply_bmaps(&mut assets, &release, &checksums);
+    assets.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok((release, assets))
+}
+
+fn run_list_drives(args: &[String]) -> i32 {
+    if let Err(code) = parse_command("list-drives", args) {
+        return code;
+    }
+
+    let drives = get_removable_drives();
+    if drives.is_empty() {
+        println!("No removable drives found");
+        return EXIT_SUCCESS;
+    }
+
+    for drive in drives {
+        println!("{}\t{}", drive.device_path, drive.display_name());
+    }
+    EXIT_SUCCESS
+}
+
+fn run_clear_cache(args: &[String]) -> i32 {
+    if let Err(code) = parse_command("clear-cache", args) {
+        return code;
+    }
+
+    let Some(cache) = DownloadCache::from_config() else {
+        println!("Download cache is disabled");
+        return EXIT_SUCCESS;
+    };
+
+    match cache.clear() {
+        Ok(freed) => {
+            println!("Cleared download cache ({} MB freed)", freed / 1_048_576);
+            EXIT_SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+fn run_list_assets(args: &[String]) -> i32 {
+    let parsed = match parse_command("list-assets", args) {
+        Ok(parsed) => parsed,
+        Err(code) => return code,
+    };
+
+    let Some(repo_name) = parsed.repo else {
+        eprintln!("Error: --repo is required");
+        return EXIT_USAGE;
+    };
+
+    let repo = match find_repo(&repo_name) {
+        Ok(repo) => repo,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return EXIT_USAGE;
+        }
+    };
+
+    let runtime = match build_runtime() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return EXIT_FAILURE;
+        }
+    };
+
+    match runtime.block_on(fetch_assets(repo, parsed.release.as_deref())) {
+        Ok((release, assets)) => {
+            println!("{} {}", repo.name, release.tag_name);
+            for asset in assets {
+                println!("{}\t{:.1} MB", asset.name, asset.size as f64 / 1_048_576.0);
+            }
+            EXIT_SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error fetching release: {}", e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+fn run_list_releases(args: &[String]) -> i32 {
+    let parsed = match parse_command("list-releases", args) {
+        Ok(parsed) => parsed,
+        Err(code) => return code,
+    };
+
+    let Some(repo_name) = parsed.repo else {
+        eprintln!("Error: --repo is required");
+        return EXIT_USAGE;
+    };
+
+    let repo = match find_repo(&repo_name) {
+        Ok(repo) => repo,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return EXIT_USAGE;
+        }
+    };
+
+    let runtime = match build_runtime() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return EXIT_FAILURE;
+        }
+    };
+
+    match runtime.block_on(list_releases(repo.url)) {
+        Ok(releases) => {
+            for release in releases {
+                println!("{}", release.summary());
+            }
+            EXIT_SUCCESS
+        }
+        Err(e) => {
+            eprintln!("Error fetching releases: {}", e);
+            EXIT_FAILURE
+        }
+    }
+}
+
+fn run_install(args: &[String]) -> i32 {
+    let parsed = match parse_command("install", args) {
+        Ok(parsed) => parsed,
+        Err(code) => return code,
+    };
+
+    // A local file doesn't need a repository, but update mode still uses its directory list
+    let repo = match (parsed.repo.as_deref(), &parsed.file) {
+        (Some(repo_name), _) => match find_repo(repo_name) {
+            Ok(repo) => repo,
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return EXIT_USAGE;
+            }
+        },
+        (None, Some(_)) => &REPO_OPTIONS[DEFAULT_REPO_INDEX],
+        (None, None) => {
+            eprintln!("Error: --repo or --file is required");
+            eprintln!("Run with --help for usage.");
+            return EXIT_USAGE;
+        }
+    };
+
+    if parsed.update_mode && !repo.supports_update_mode {
+        eprintln!("Error: repository '{}' does not support update mode", repo.name);
+        return EXIT_USAGE;
+    }
+
+    let (drive, runtime) = match target_drive(&parsed, true) {
+        Ok(target) => target,
+        Err(code) => return code,
+    };
+
+    let mut pipeline = match &parsed.file {
+        Some(path) => match InstallPipeline::from_local_file(drive, PathBuf::from(path), repo, parsed.update_mode) {
+            Ok(pipeline) => {
+                println!("Source:  {}", path);
+                crate::debug::log(&format!("Local file: {}", path));
+                pipeline
+            }
+            Err(e) => {
+                eprintln!("Error: {}", e);
+                return EXIT_USAGE;
+            }
+        },
+        None => {
+            let (release, asset) = match select_release_asset(&runtime, repo, parsed.release.as_deref(), parsed.asset.as_deref()) {
+                Ok(selection) => selection,
+                Err(code) => return code,
+            };
+            if parsed.filesystem.is_some() && is_raw_image(&asset.name) {
+                eprintln!("Error: --filesystem cannot be combined with a disk image ({})", asset.name);
+                return EXIT_USAGE;
+            }
+            println!("Release: {} ({})", release.tag_name, asset.name);
+            crate::debug::log(&format!("Release: {}", release.tag_name));
+
+            let update_mode = parsed.update_mode && !is_raw_image(&asset.name);
+            InstallPipeline::new(drive, asset, repo, update_mode)
+        }
+    };
+    let update_mode = pipeline.update_mode;
+
+    println!("Target:  {}", pipeline.drive.display_name());
+    if let Some(path) = &parsed.backup {
+        if update_mode {
+            println!("Backup:  skipped, update mode keeps the card's contents");
+        } else {
+            let target = match backup_target(path, parsed.backup_used_only) {
+                Ok(target) => target,
+                Err(e) => {
+                    eprintln!("Error: {}", e);
+                    return EXIT_USAGE;
+                }
+            };
+            println!("Backup:  {}", target.path.display());
+            pipeline.backup = Some(target);
+        }
+    }
+    if let Some(filesystem) = parsed.filesystem {
+        pipeline.filesystem = filesystem;
+    }
+    pipeline.probe_capacity = parsed.check_capacity && !update_mode;
+    pipeline.benchmark = parsed.benchmark;
+    if parsed.save_data && !update_mode {
+        if repo.backup_directories.is_empty() {
+            println!("Data:    nothing to save, {} has no user data folders", repo.name);
+        } else {
+            let user_data = UserDataBackup::new(parsed.restore_data);
+            println!("Data:    {} saved to {}", repo.backup_directories.join(", "), user_data.root.display());
+            pipeline.user_data = Some(user_data);
+        }
+    }
+
+    if !parsed.assume_yes && !confirm(&pipeline.drive, update_mode, repo) {
+        println!("Aborted.");
+        return EXIT_ABORTED;
+    }
+
+    // Cancel cleanly on Ctrl+C so partial downloads and temp folders are removed
+    let cancel_token = cancel_on_ctrl_c(&runtime);
+
+    crate::debug::log_section("Installation Started (CLI)");
+    crate::debug::log(&format!("Drive: {} ({})", pipeline.drive.name, pipeline.drive.device_path));
+    crate::debug::log(&format!("Repository: {} ({})", repo.name, repo.url));
+
+    let (event_tx, event_rx) = mpsc::unbounded_channel::<InstallEvent>();
+    let printer = runtime.spawn(print_events(event_rx));
+    let result = runtime.block_on(pipeline.run(event_tx, cancel_token.clone()));
+    let _ = runtime.block_on(printer);
+
+    match result {
+        Ok(()) => EXIT_SUCCESS,
+        Err(_) if cancel_token.is_cancelled() => EXIT_CANCELLED,
+        Err(_) => {
+            eprintln!("Debug log: {}", crate::debug::get_log_path().display());
+            EXIT_FAILURE
+        }
+    }
+}
+
+fn backup_target(path: &str, used_only: bool) -> Result<BackupTarget, String> {
+    let path = PathBuf::from(path);
+    crate::backup::backup_compression(&path)?;
+    let extent = if used_only { BackupExtent::UsedPartitions } else { BackupExtent::WholeDevice };
+    Ok(BackupTarget { path, extent })
+}
+
+fn run_backup(args: &[String]) -> i32 {
+    let parsed = match parse_command("backup", args) {
+        Ok(parsed) => parsed,
+        Err(code) => return code,
+    };
+
+    let Some(output) = parsed.output.as_deref() else {
+        eprintln!("Error: --output is required");
+        eprintln!("Run with --help for usage.");
+        return EXIT_USAGE;
+    };
+
+    let target = match backup_target(output, parsed.backup_used_only) {
+        Ok(target) => target,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return EXIT_USAGE;
+        }
+    };
+
+    let (drive, runtime) = match target_drive(&parsed, true) {
+        Ok(target) => target,
+        Err(code) => return code,
+    };
+
+    println!("Source:  {}", drive.display_name());
+    println!("Backup:  {}", target.path.display());
+
+    let result = run_with_progress(&runtime, print_backup_progress, |progress_tx, cancel_token| {
+        backup_device(&drive, &target, progress_tx, cancel_token)
+    });
+    match result {
+        Ok(read) => {
+            println!("Backup complete: {} MB read, saved to {}", read / 1_048_576, target.path.display());
+            EXIT_SUCCESS
+        }
+        Err(code) => code,
+    }
+}
+
+fn run_check_capacity(args: &[String]) -> i32 {
+    let parsed = match parse_command("check-capacity", args) {
+        Ok(parsed) => parsed,
+        Err(code) => return code,
+    };
+
+    let (drive, runtime) = match target_drive(&parsed, true) {
+        Ok(target) => target,
+        Err(code) => return code,
+    };
+
+    println!("Checking {} (test blocks are written and the original data put back)...", drive.display_name());
+    let result = run_with_progress(&runtime, print_probe_progress, |progress_tx, cancel_token| {
+        probe_capacity(&drive, progress_tx, cancel_token)
+    });
+    match result {
+        Ok(report) if report.is_fake() => {
+            eprintln!("WARNING: {}", report.summary());
+            EXIT_FAILURE
+        }
+        Ok(report) => {
+            println!("{}", report.summary());
+            EXIT_SUCCESS
+        }
+        Err(code) => code,
+    }
+}
+
+fn run_benchmark(args: &[String]) -> i32 {
+    let parsed = match parse_command("benchmark", args) {
+        Ok(parsed) => parsed,
+        Err(code) => return code,
+    };
+
+    // The default test file goes through the mounted card, which needs no root
+    let (drive, runtime) = match target_drive(&parsed, parsed.raw) {
+        Ok(target) => target,
+        Err(code) => return code,
+    };
+
+    let mode = if parsed.raw { BenchmarkMode::Raw } else { BenchmarkMode::Filesystem };
+    if mode == BenchmarkMode::Raw && !parsed.assume_yes {
+        println!("WARNING: This will overwrite 64 MB in the middle of {}", drive.display_name());
+        if !prompt_continue() {
+            println!("Aborted.");
+            return EXIT_ABORTED;
+        }
+    }
+
+    println!("Testing the speed of {}...", drive.display_name());
+    let result = run_with_progress(&runtime, print_benchmark_progress, |progress_tx, cancel_token| {
+        benchmark_drive(&drive, mode, progress_tx, cancel_token)
+    });
+    match result {
+        Ok(result) => {
+            for line in result.summary() {
+                println!("{}", line);
+            }
+            EXIT_SUCCESS
+        }
+        Err(code) => code,
+    }
+}
+
+fn run_build_image(args: &[String]) -> i32 {
+    let parsed = match parse_command("build-image", args) {
+        Ok(parsed) => parsed,
+        Err(code) => return code,
+    };
+
+    let (Some(archive), Some(output)) = (parsed.file.as_deref(), parsed.output.as_deref()) else {
+        eprintln!("Error: --file and --output are required");
+        eprintln!("Run with --help for usage.");
+        return EXIT_USAGE;
+    };
+    let archive = PathBuf::from(archive);
+    let output = PathBuf::from(output);
+    if !archive.is_file() {
+        eprintln!("Error: File not found: {}", archive.display());
+        return EXIT_USAGE;
+    }
+    if is_raw_image(&archive.to_string_lossy()) {
+        eprintln!("Error: {} is already a disk image; build-image needs an archive", archive.display());
+        return EXIT_USAGE;
+    }
+    let size = match (image_compression(&output), parsed.size.as_deref().map(parse_size).transpose()) {
+        (Ok(_), Ok(size)) => size,
+        (Err(e), _) | (_, Err(e)) => {
+            eprintln!("Error: {}", e);
+            return EXIT_USAGE;
+        }
+    };
+    let label = parsed.label.as_deref().unwrap_or(VOLUME_LABEL);
+    // Cluster size and alignment of the repository's cards (the defaults without --repo)
+    let options = match parsed.repo.as_deref().map(find_repo).transpose() {
+        Ok(repo) => repo.map(FormatOptions::for_repo).unwrap_or_default(),
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return EXIT_USAGE;
+        }
+    };
+
+    let runtime = match build_runtime() {
+        Ok(runtime) => runtime,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            return EXIT_FAILURE;
+        }
+    };
+    let cancel_token = cancel_on_ctrl_c(&runtime);
+
+    let extract_dir = match tempfile::Builder::new().prefix(TEMP_PREFIX).tempdir() {
+        Ok(dir) => dir,
+        Err(e) => {
+            eprintln!("Error: Failed to create temporary folder: {}", e);
+            return EXIT_FAILURE;
+        }
+    };
+    let extract_path = extract_dir.path();
+
+    println!("Extracting {}...", archive.display());
+    if let Err(code) = run_step(&runtime, &cancel_token, print_extract_progress, |progress_tx, cancel_token| {
+        extract_archive(&archive, extract_path, progress_tx, cancel_token)
+    }) {
+        return code;
+    }
+
+    let result = run_step(&runtime, &cancel_token, print_image_progress, |progress_tx, cancel_token| async {
+        let size = size.map_or_else(|| fitting_image_size(extract_path, &options), Ok)?;
+        println!("Building a {} MB image with label {}...", size / 1_000_000, label);
+        build_card_image(extract_path, &output, size, label, &options, progress_tx, cancel_token).await
+    });
+    match result {
+        Ok(file_size) => {
+            println!("Image complete: {} ({} MB)", output.display(), file_size / 1_000_000);
+            EXIT_SUCCESS
+        }
+        Err(code) => code,
+    }
+}
+
+/// Fetch the release and pick the asset given with --asset (or the only sensible one)
+/// Errors are printed here; the exit code is returned
+fn select_release_asset(
+    runtime: &tokio::runtime::Runtime,
+    repo: &RepoOption,
+    tag: Option<&str>,
+    asset_name: Option<&str>,
+) -> Result<(Release, Asset), i32> {
+    println!("Fetching release info for {}...", repo.name);
+    let (release, assets) = match runtime.block_on(fetch_assets(repo, tag)) {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("Error fetching release: {}", e);
+            return Err(EXIT_FAILURE);
+        }
+    };
+
+    if assets.is_empty() {
+        eprintln!("Error: no compatible files found in release {}", release.tag_name);
+        return Err(EXIT_FAILURE);
+    }
+
+    let asset = match asset_name {
+        Some(name) => match assets.iter().find(|a| a.name == name) {
+            Some(asset) => asset.clone(),
+            None => {
+                eprintln!("Error: asset '{}' not found in release {}", name, release.tag_name);
+                eprintln!("Available: {}", assets.iter().map(|a| a.name.as_str()).collect::<Vec<_>>().join(", "));
+                return Err(EXIT_USAGE);
+            }
+        },
+        None => match InstallerApp::should_auto_select(&assets) {
+            (true, Some(idx)) => assets[idx].clone(),
+            _ => {
+                eprintln!("Error: release {} has several files, choose one with --asset:", release.tag_name);
+                for asset in &assets {
+                    eprintln!("  {}", asset.name);
+                }
+                return Err(EXIT_USAGE);
+            }
+        },
+    };
+
+    Ok((release, asset))
+}
+
+fn confirm(drive: &DriveInfo, update_mode: bool, repo: &RepoOption) -> bool {
+    if update_mode {
+        println!("The following directories will be deleted: {}", repo.update_directories.join(", "));
+    } else {
+        println!("WARNING: This will DELETE ALL DATA on {}", drive.display_name());
+    }
+    prompt_continue()
+}
+
+fn prompt_continue() -> bool {
+    print!("Continue? [y/N] ");
+    let _ = std::io::stdout().flush();
+
+    let mut answer = String::new();
+    if std::io::stdin().read_line(&mut answer).is_err() {
+        return false;
+    }
+    matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Print pipeline events, limiting progress output to one line per whole percent
+async fn print_events(mut rx: mpsc::UnboundedReceiver<InstallEvent>) {
+    let mut last_status = String::new();
+    let mut last_percent = None;
+
+    while let Some(event) = rx.recv().await {
+        match event {
+            InstallEvent::Stage(_) => last_percent = None,
+            InstallEvent::Log(msg) => println!("{}", msg),
+            InstallEvent::Status(msg) => {
+                if msg != last_status {
+                    println!("{}", msg);
+                    last_status = msg;
+                }
+            }
+            InstallEvent::Progress { current, total, message } => {
+                let percent = (current * 100).checked_div(total).unwrap_or(0);
+                if last_percent != Some(percent) {
+                    println!("{}", message);
+                    last_percent = Some(percent);
+                    last_status = message;
+                }
+            }
+            InstallEvent::Failed(e) => eprintln!("Error: {}", e),
+            InstallEvent::Completed | InstallEvent::Cancelled => {}
+        }
+    }
+}
+
+/// Print backup progress, one line per whole percent
+async fn print_backup_progress(mut rx: mpsc::UnboundedReceiver<BackupProgress>) {
+    let mut last_percent = None;
+    while let Some(progress) = rx.recv().await {
+        if let BackupProgress::Reading { read, total } = progress {
+            let percent = (read * 100).checked_div(total).unwrap_or(0);
+            if last_percent != Some(percent) {
+                println!("Backing up... {}% ({}/{} MB)", percent, read / 1_048_576, total / 1_048_576);
+                last_percent = Some(percent);
+            }
+        }
+    }
+}
+
+/// Print the milestones of a capacity check
+async fn print_probe_progress(mut rx: mpsc::UnboundedReceiver<ProbeProgress>) {
+    while let Some(progress) = rx.recv().await {
+        match progress {
+            ProbeProgress::Writing { done, total } if done == total => println!("Wrote {} test blocks", total),
+            ProbeProgress::Reading { done, total } if done == total => println!("Read back {} test blocks", total),
+            ProbeProgress::Restoring => println!("Restoring original data..."),
+            _ => {}
+        }
+    }
+}
+
+/// Print the name of each benchmark test as it starts
+async fn print_benchmark_progress(mut rx: mpsc::UnboundedReceiver<BenchmarkProgress>) {
+    let mut current = None;
+    while let Some(progress) = rx.recv().await {
+        if let BenchmarkProgress::Running { test, .. } = progress {
+            if current != Some(test) {
+                println!("{}...", test.label());
+                current = Some(test);
+            }
+        }
+    }
+}
+
+/// Print extraction progress every 10%
+async fn print_extract_progress(mut rx: mpsc::UnboundedReceiver<ExtractProgress>) {
+    let mut last_percent = None;
+    while let Some(progress) = rx.recv().await {
+        let percent = match progress {
+            ExtractProgress::Progress { percent } => percent as u64,
+            ExtractProgress::Writing { extracted, total, .. } => (extracted * 100).checked_div(total).unwrap_or(0),
+            _ => continue,
+        };
+        if last_percent != Some(percent / 10) {
+            println!("Extracting... {}%", percent);
+            last_percent = Some(percent / 10);
+        }
+    }
+}
+
+/// Print each step of building a card image every 10%
+async fn print_image_progress(mut rx: mpsc::UnboundedReceiver<ImageProgress>) {
+    let mut last = None;
+    while let Some(progress) = rx.recv().await {
+        let (step, percent) = match progress {
+            ImageProgress::Formatting { percent } => ("Formatting", percent as u64),
+            ImageProgress::Copying { copied_bytes, total_bytes } => {
+                ("Copying", (copied_bytes * 100).checked_div(total_bytes).unwrap_or(100))
+            }
+            ImageProgress::Compressing { done, total } => ("Compressing", (done * 100).checked_div(total).unwrap_or(100)),
+            _ => continue,
+        };
+        if last != Some((step, percent / 10)) {
+            println!("{}... {}%", step, percent);
+            last = Some((step, percent / 10));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|a| a.to_string()).collect()
+    }
+
+    fn drive(name: &str, device_path: &str) -> DriveInfo {
+        DriveInfo {
+            name: name.to_string(),
+            device_path: device_path.to_string(),
+            mount_path: None,
+            label: String::new(),
+            size_bytes: 0,
+        }
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let parsed = parse_args("install", &args(&["-r", "Stable", "-d", "/dev/sdb", "--update", "-y"])).unwrap();
+        assert_eq!(parsed.repo.as_deref(), Some("Stable"));
+        assert_eq!(parsed.device.as_deref(), Some("/dev/sdb"));
+        assert!(parsed.update_mode && parsed.assume_yes && !parsed.help);
+
+        // Every subcommand takes --help, even without other options
+        assert!(parse_args("install", &args(&["--repo", "Stable", "--help"])).unwrap().help);
+        assert!(parse_args("list-drives", &args(&["-h"])).unwrap().help);
+
+        let parsed = parse_args("backup", &args(&["-d", "/dev/sdb", "-o", "card.img.zst", "--used-only"])).unwrap();
+        assert_eq!(parsed.output.as_deref(), Some("card.img.zst"));
+        assert!(parsed.backup.is_none() && parsed.backup_used_only);
+
+        let parsed = parse_args("install", &args(&["-r", "Stable", "-d", "/dev/sdb", "--keep-data"])).unwrap();
+        assert!(parsed.save_data && parsed.restore_data);
+
+        let parsed = parse_args("install", &args(&["-r", "Stable", "-d", "/dev/sdb", "--filesystem", "exFAT"])).unwrap();
+        assert_eq!(parsed.filesystem, Some(Filesystem::ExFat));
+
+        let parsed = parse_args("build-image", &args(&["-f", "spruce.7z", "-o", "spruce.img.zst", "--size", "8G"])).unwrap();
+        assert_eq!((parsed.output.as_deref(), parsed.size.as_deref()), (Some("spruce.img.zst"), Some("8G")));
+    }
+
+    #[test]
+    fn test_install_usage_lists_options() {
+        for option in allowed_options("install") {
+            assert!(USAGE.iter().any(|(c, usage)| *c == "install" && usage.contains(option)), "{}", option);
+        }
+    }
+
+    #[test]
+    fn test_parse_args_errors() {
+        assert_eq!(parse_args("install", &args(&["--device"])).unwrap_err(), "Missing value for --device");
+        assert_eq!(parse_args("install", &args(&["--force"])).unwrap_err(), "Unknown option: --force");
+        // Options of other subcommands
+        assert_eq!(
+            parse_args("list-assets", &args(&["--repo", "Stable", "--device", "/dev/sdb"])).unwrap_err(),
+            "--device does not apply to list-assets"
+        );
+        assert!(parse_args("list-drives", &args(&["--verbose"])).is_err());
+        assert_eq!(
+            parse_args("install", &args(&["-d", "/dev/sdb", "--output", "card.img.zst"])).unwrap_err(),
+            "--output does not apply to install"
+        );
+        assert!(parse_args("backup", &args(&["-d", "/dev/sdb", "-o", "card.img.zst", "--yes"])).is_err());
+        // A local file has no release or asset to pick
+        assert_eq!(
+            parse_args("install", &args(&["--file", "spruce.7z", "--release", "v4.0.0"])).unwrap_err(),
+            "--file cannot be combined with --release"
+        );
+        assert_eq!(
+            parse_args("install", &args(&["--file", "spruce.7z", "-a", "spruce.7z"])).unwrap_err(),
+            "--file cannot be combined with --asset"
+        );
+        assert!(parse_args("list-releases", &args(&["--repo", "Stable", "--release", "v4.0.0"])).is_err());
+        assert_eq!(
+            parse_args("install", &args(&["-r", "Stable", "--filesystem", "ntfs"])).unwrap_err(),
+            "Unknown filesystem: ntfs (expected fat32 or exfat)"
+        );
+        assert!(parse_args("install", &args(&["-r", "Stable", "--update", "--filesystem", "exfat"])).is_err());
+        assert_eq!(
+            parse_args("install", &args(&["-f", "card.img.gz", "--filesystem", "exfat"])).unwrap_err(),
+            "--filesystem cannot be combined with a disk image"
+        );
+        assert_eq!(
+            parse_args("install", &args(&["-r", "Stable", "-d", "/dev/sdb", "--used-only"])).unwrap_err(),
+            "--used-only requires --backup"
+        );
+    }
+
+    #[test]
+    fn test_match_drive() {
+        let drives = [drive("sdb", "/dev/sdb"), drive("E:", "E:")];
+        assert_eq!(match_drive(&drives, "/dev/sdb").unwrap().name, "sdb");
+        assert_eq!(match_drive(&drives, "sdb").unwrap().device_path, "/dev/sdb");
+        assert_eq!(match_drive(&drives, "E:").unwrap().name, "E:");
+
+        let error = match_drive(&drives, "/dev/sda").unwrap_err();
+        assert!(error.contains("not a removable drive") && error.contains("/dev/sdb, E:"), "{}", error);
+        assert!(match_drive(&[], "/dev/sdb").unwrap_err().contains("no removable drives detected"));
+    }
+}
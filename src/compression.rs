@@ -0,0 +1,231 @@
+// Copyright (C) 2026 SpruceOS Team
+// Licensed under GPL-3.0-or-later
+
+// Compressed disk image support
+// The compression of an image is detected from its magic bytes rather than its
+// extension, so a mislabelled .img.gz or an .img.xz renamed to .img is still
+// decompressed instead of being written to the card as-is.
+
+use std::cell::Cell;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::rc::Rc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Xz,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    /// Identify the compression from the first bytes of a file
+    pub fn from_magic(header: &[u8]) -> Self {
+        if header.starts_with(&[0x1F, 0x8B]) {
+            Compression::Gzip
+        } else if header.starts_with(&[0xFD, b'7', b'z', b'X', b'Z', 0x00]) {
+            Compression::Xz
+        } else if header.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+            Compression::Zstd
+        } else if header.starts_with(b"BZh") {
+            Compression::Bzip2
+        } else {
+            Compression::None
+        }
+    }
+
+    /// Read the magic bytes of a file and identify its compression
+    pub fn detect(path: &Path) -> Result<Self, String> {
+        let mut file = std::fs::File::open(path)
+            .map_err(|e| format!("Failed to open image file: {}", e))?;
+        let mut header = [0u8; 6];
+        let len = read_up_to(&mut file, &mut header)?;
+        Ok(Self::from_magic(&header[..len]))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Compression::None => "uncompressed",
+            Compression::Gzip => "gzip",
+            Compression::Xz => "xz",
+            Compression::Zstd => "zstd",
+            Compression::Bzip2 => "bzip2",
+        }
+    }
+
+    /// Wrap a reader of compressed data in the matching decoder
+    /// Multi-member/multi-stream files (e.g. from pigz or pxz) are read to the end.
+    pub fn decoder<'a, R: Read + 'a>(&self, reader: R) -> Result<Box<dyn Read + 'a>, String> {
+        Ok(match self {
+            Compression::None => Box::new(reader),
+            Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(reader)),
+            Compression::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(reader)),
+            Compression::Zstd => Box::new(
+                zstd::stream::read::Decoder::new(reader)
+                    .map_err(|e| format!("Failed to initialise zstd decoder: {}", e))?,
+            ),
+            Compression::Bzip2 => Box::new(bzip2::read::MultiBzDecoder::new(reader)),
+        })
+    }
+}
+
+/// Counts the bytes read through it (the compressed position in a stream)
+pub struct CountingReader<R> {
+    inner: R,
+    count: Rc<Cell<u64>>,
+}
+
+impl<R> CountingReader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, count: Rc::new(Cell::new(0)) }
+    }
+
+    /// Shared handle to the count, still readable once the reader is moved into a decoder
+    pub fn counter(&self) -> Rc<Cell<u64>> {
+        self.count.clone()
+    }
+}
+
+impl<R: Read> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count.set(self.count.get() + n as u64);
+        Ok(n)
+    }
+}
+
+/// A disk image opened for reading, decompressed on the fly
+pub struct ImageReader {
+    reader: Box<dyn Read>,
+    compression: Compression,
+    compressed_read: Rc<Cell<u64>>,
+    compressed_size: u64,
+}
+
+impl ImageReader {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let compression = Compression::detect(path)?;
+        let file = std::fs::File::open(path)
+            .map_err(|e| format!("Failed to open image file: {}", e))?;
+        let compressed_size = file.metadata()
+            .map_err(|e| format!("Failed to get image size: {}", e))?
+            .len();
+
+        let counting = CountingReader::new(BufReader::with_capacity(1024 * 1024, file));
+        let compressed_read = counting.counter();
+        let reader = compression.decoder(counting)?;
+        Ok(Self { reader, compression, compressed_read, compressed_size })
+    }
+
+    pub fn compression(&self) -> Compression {
+        self.compression
+    }
+
+    /// Size of the image file itself
+    pub fn compressed_size(&self) -> u64 {
+        self.compressed_size
+    }
+
+    /// How far into the image file the decoder has read
+    pub fn compressed_read(&self) -> u64 {
+        self.compressed_read.get()
+    }
+}
+
+impl Read for ImageReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+/// Decompressed size of an image, as far as it can be known without decompressing it
+/// gzip records the size modulo 4 GiB (ISIZE) and zstd only in the first frame's
+/// header, so for those formats this is an estimate; xz and bzip2 give None.
+pub fn uncompressed_size_hint(path: &Path) -> Result<Option<u64>, String> {
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| format!("Failed to open image file: {}", e))?;
+    let file_size = file.metadata()
+        .map_err(|e| format!("Failed to get image size: {}", e))?
+        .len();
+
+    let mut header = [0u8; 18];
+    let header_len = read_up_to(&mut file, &mut header)?;
+    let header = &header[..header_len];
+
+    Ok(match Compression::from_magic(header) {
+        Compression::None => Some(file_size),
+        Compression::Gzip if file_size >= 18 => {
+            let mut isize = [0u8; 4];
+            file.seek(SeekFrom::End(-4))
+                .and_then(|_| file.read_exact(&mut isize))
+                .map_err(|e| format!("Failed to read image file: {}", e))?;
+            let isize = u32::from_le_bytes(isize) as u64;
+            // A wrapped ISIZE smaller than the compressed data is useless
+            (isize >= file_size).then_some(isize)
+        }
+        Compression::Zstd => zstd::zstd_safe::get_frame_content_size(header).ok().flatten(),
+        _ => None,
+    })
+}
+
+fn read_up_to(file: &mut std::fs::File, buf: &mut [u8]) -> Result<usize, String> {
+    let mut len = 0;
+    while len < buf.len() {
+        match file.read(&mut buf[len..]) {
+            Ok(0) => break,
+            Ok(n) => len += n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(format!("Failed to read image file: {}", e)),
+        }
+    }
+    Ok(len)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn test_image_reader() {
+        let dir = tempfile::tempdir().unwrap();
+        let data: Vec<u8> = (0..3 * 1024 * 1024u32).map(|i| (i % 251) as u8).collect();
+
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(&data).unwrap();
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 1);
+        xz.write_all(&data).unwrap();
+        let mut bz = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::fast());
+        bz.write_all(&data).unwrap();
+
+        let images = [
+            ("card.img", data.clone(), Compression::None),
+            ("card.img.gz", gz.finish().unwrap(), Compression::Gzip),
+            // Mislabelled on purpose: detection goes by content, not extension
+            ("xz-inside.img.gz", xz.finish().unwrap(), Compression::Xz),
+            ("card.img.zst", zstd::bulk::compress(&data, 1).unwrap(), Compression::Zstd),
+            ("card.img.bz2", bz.finish().unwrap(), Compression::Bzip2),
+        ];
+
+        for (name, contents, expected) in images {
+            let path = dir.path().join(name);
+            std::fs::write(&path, contents).unwrap();
+            let mut reader = ImageReader::open(&path).unwrap();
+            assert_eq!(reader.compression(), expected, "{}", name);
+            let mut out = Vec::new();
+            reader.read_to_end(&mut out).unwrap();
+            assert!(out == data, "{}", name);
+            assert_eq!(reader.compressed_read(), reader.compressed_size(), "{}", name);
+
+            let hint = uncompressed_size_hint(&path).unwrap();
+            match expected {
+                Compression::None | Compression::Gzip | Compression::Zstd => {
+                    assert_eq!(hint, Some(data.len() as u64), "{}", name)
+                }
+                _ => assert_eq!(hint, None, "{}", name),
+            }
+        }
+    }
+}
@@ -72,6 +72,47 @@ pub const USER_AGENT: &str = env!("CARGO_PKG_NAME");
 /// Prefix for temporary folders and files
 pub const TEMP_PREFIX: &str = env!("CARGO_PKG_NAME");
 
+// ----------------------------------------------------------------------------
+// DOWNLOAD CACHE
+// ----------------------------------------------------------------------------
+
+/// Maximum size of the download cache in bytes (0 = disable caching)
+/// Downloaded releases are kept so installing the same release to several cards
+/// downloads it only once. The least recently used files are removed beyond this size.
+pub const DOWNLOAD_CACHE_MAX_SIZE: u64 = 8 * 1024 * 1024 * 1024; // 8 GB
+
+// ----------------------------------------------------------------------------
+// RELEASE SIGNING
+// ----------------------------------------------------------------------------
+
+/// Minisign public key used to verify release signatures (base64 key line of minisign.pub)
+/// Example: Some("RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3")
+///
+/// Sign `manifest.json` and/or `SHA256SUMS` with `minisign -Sm <file>` and upload the
+/// resulting `.minisig` files to the release. Assets are then covered by their signed checksums.
+/// Set to None to disable signature checks (signatures present in a release are ignored).
+pub const SIGNING_PUBLIC_KEY: Option<&str> = None;
+
+// ----------------------------------------------------------------------------
+// SPARSE BURNING (Linux)
+// ----------------------------------------------------------------------------
+
+/// Discard (TRIM) the blocks a .bmap marks as unused after burning an image
+/// Gives the card's controller back the free space; the blocks hold no data either way.
+pub const BURN_DISCARD_UNMAPPED: bool = true;
+
+/// For images without a .bmap, zero all-zero chunks with BLKZEROOUT instead of writing them
+/// Much faster on cards that support it; falls back to a normal write otherwise.
+pub const BURN_SKIP_ZERO_CHUNKS: bool = true;
+
+// ----------------------------------------------------------------------------
+// COPY VERIFICATION
+// ----------------------------------------------------------------------------
+
+/// Read back every file copied to the card in archive mode and compare it with the original
+/// Catches failing and fake-capacity cards at the cost of reading the install once more.
+pub const VERIFY_COPIED_FILES: bool = true;
+
 // ----------------------------------------------------------------------------
 // REPOSITORY OPTIONS
 // ----------------------------------------------------------------------------
@@ -88,6 +129,44 @@ pub struct AssetDisplayMapping {
     pub devices: &'static str,
 }
 
+/// Filesystem a fresh archive install formats the card with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Filesystem {
+    /// Read by every handheld firmware; single files are limited to 4 GB
+    Fat32,
+    /// No 4 GB file limit, but only for firmwares that can read it (e.g. newer TrimUI builds)
+    ExFat,
+}
+
+impl Filesystem {
+    pub const ALL: [Filesystem; 2] = [Filesystem::Fat32, Filesystem::ExFat];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Filesystem::Fat32 => "FAT32",
+            Filesystem::ExFat => "exFAT",
+        }
+    }
+
+    /// Look a filesystem up by its name, ignoring case ("fat32", "exfat")
+    pub fn from_name(name: &str) -> Option<Filesystem> {
+        Self::ALL.into_iter().find(|fs| fs.name().eq_ignore_ascii_case(name))
+    }
+}
+
+/// One partition of a card layout (see `RepoOption::partitions`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PartitionSpec {
+    /// Volume label, and the name `RepoOption::install_partition` refers to
+    pub label: &'static str,
+    /// Size in bytes, rounded up to the partition alignment; None takes the rest of the card (last partition only)
+    pub size: Option<u64>,
+    /// MBR partition type; None uses the filesystem's (0x0C for FAT32, 0x07 for exFAT)
+    pub partition_type: Option<u8>,
+    /// None leaves a raw region, e.g. for a bootloader; without a partition_type it stays out of the partition table
+    pub filesystem: Option<Filesystem>,
+}
+
 /// Repository configuration for download sources
 ///
 /// Each repository entry contains:
@@ -101,11 +180,18 @@ pub struct AssetDisplayMapping {
 /// - `update_directories`: Directories to delete when updating (e.g., &["Retroarch", "spruce"])
 ///                         Paths are relative to SD card root
 ///                         NOTE: Only used when update mode is enabled
+/// - `backup_directories`: User data folders (relative to SD card root) offered for backup before a fresh install wipes the card
 /// - `allowed_extensions`: Optional filter to only show assets with these extensions
 ///                         Use this to filter out update packages or show only specific formats
 ///                         Set to None to show all assets
 /// - `asset_display_mappings`: Optional mappings to show user-friendly device names
 ///                             instead of technical filenames in the selection UI
+/// - `require_signature`: Refuse to install anything not covered by a signature made with SIGNING_PUBLIC_KEY
+/// - `filesystem`: Filesystem a fresh archive install formats the card with (ExFat only if every supported device reads it)
+/// - `cluster_size`: Cluster size in bytes for bootloaders that need a specific one (None = picked from the card size)
+/// - `partition_alignment`: Partition and data area alignment in bytes, e.g. the 4 MiB erase block (None = 1 MiB)
+/// - `partitions`: Card layout for fresh archive installs, in disk order (empty = one `filesystem` partition over the whole card)
+/// - `install_partition`: Label of the layout partition archives are extracted to (None = the first formatted one)
 ///
 /// Example (archive-based repository):
 /// ```
@@ -115,8 +201,15 @@ pub struct AssetDisplayMapping {
 ///     info: "Stable releases with update support.\nSupported devices: Device X, Y, Z",
 ///     supports_update_mode: true,  // Archives can be updated
 ///     update_directories: &["Retroarch", "spruce"],
+///     backup_directories: &["Saves", "Roms", "BIOS"],
 ///     allowed_extensions: Some(&[".7z", ".zip"]),  // Only show archives
 ///     asset_display_mappings: None,
+///     require_signature: false,
+///     filesystem: Filesystem::Fat32,
+///     cluster_size: None,
+///     partition_alignment: None,
+///     partitions: &[],
+///     install_partition: None,
 /// }
 /// ```
 ///
@@ -128,8 +221,15 @@ pub struct AssetDisplayMapping {
 ///     info: "Raw disk images for GKD Pixel 2.\nFresh install only - wipes all data.",
 ///     supports_update_mode: false,  // Raw images always do full burns
 ///     update_directories: &[],  // Not used for raw images
+///     backup_directories: &[],  // Nothing to restore into after a burn
 ///     allowed_extensions: Some(&[".img.gz", ".img"]),  // Only raw images
 ///     asset_display_mappings: None,
+///     require_signature: false,
+///     filesystem: Filesystem::Fat32,
+///     cluster_size: None,
+///     partition_alignment: None,
+///     partitions: &[],
+///     install_partition: None,
 /// }
 /// ```
 pub struct RepoOption {
@@ -138,8 +238,15 @@ pub struct RepoOption {
     pub info: &'static str,
     pub supports_update_mode: bool,
     pub update_directories: &'static [&'static str],
+    pub backup_directories: &'static [&'static str],
     pub allowed_extensions: Option<&'static [&'static str]>,
     pub asset_display_mappings: Option<&'static [AssetDisplayMapping]>,
+    pub require_signature: bool,
+    pub filesystem: Filesystem,
+    pub cluster_size: Option<u32>,
+    pub partition_alignment: Option<u32>,
+    pub partitions: &'static [PartitionSpec],
+    pub install_partition: Option<&'static str>,
 }
 
 pub const REPO_OPTIONS: &[RepoOption] = &[
@@ -149,8 +256,15 @@ pub const REPO_OPTIONS: &[RepoOption] = &[
         info: "Stable releases of spruceOS.\nSupported devices: Miyoo A30",
         supports_update_mode: true,  // Archive-based (.7z)
         update_directories: &["Retroarch", "spruce"],
+        backup_directories: &["Saves", "Roms", "BIOS"],
         allowed_extensions: Some(&[".7z"]),  // Only show 7z archives
         asset_display_mappings: None,
+        require_signature: false,
+        filesystem: Filesystem::Fat32,
+        cluster_size: None,
+        partition_alignment: None,
+        partitions: &[],
+        install_partition: None,
     },
     RepoOption {
         name: "Nightlies",
@@ -158,8 +272,15 @@ pub const REPO_OPTIONS: &[RepoOption] = &[
         info: "Nightly development builds.\n⚠️ Warning: May be unstable! \nSupported devices:\nMiyoo A30, Miyoo Flip, Miyoo Mini Flip, TrimUI Smart Pro, TrimUI Smart Pro S, TrimUI Brick",
         supports_update_mode: true,  // Supports archives
         update_directories: &["Retroarch", "spruce"],
+        backup_directories: &["Saves", "Roms", "BIOS"],
         allowed_extensions: None,  // Show all assets
         asset_display_mappings: None,
+        require_signature: false,
+        filesystem: Filesystem::Fat32,
+        cluster_size: None,
+        partition_alignment: None,
+        partitions: &[],
+        install_partition: None,
     },
     RepoOption {
         name: "SprigUI",
@@ -167,8 +288,15 @@ pub const REPO_OPTIONS: &[RepoOption] = &[
         info: "SpruceOS for the Miyoo Mini Flip.",
         supports_update_mode: true,  // Archive-based (.7z)
         update_directories: &["Retroarch", "spruce"],
+        backup_directories: &["Saves", "Roms", "BIOS"],
         allowed_extensions: Some(&[".7z"]),  // Only show 7z archives
         asset_display_mappings: None,
+        require_signature: false,
+        filesystem: Filesystem::Fat32,
+        cluster_size: None,
+        partition_alignment: None,
+        partitions: &[],
+        install_partition: None,
     },
     RepoOption {
         name: "TwigUI",
@@ -176,8 +304,15 @@ pub const REPO_OPTIONS: &[RepoOption] = &[
         info: "SpruceOS for the GKD Pixel 2.",
         supports_update_mode: false,  // Raw disk images only (.img.gz)
         update_directories: &["Retroarch", "spruce"],
+        backup_directories: &[],  // Nothing to restore into after a burn
         allowed_extensions: Some(&[".img.gz"]),  // Only show .img.gz files
         asset_display_mappings: None,
+        require_signature: false,
+        filesystem: Filesystem::Fat32,
+        cluster_size: None,
+        partition_alignment: None,
+        partitions: &[],
+        install_partition: None,
     },
 ];
 
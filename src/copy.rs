@@ -1,6 +1,8 @@
 // Copyright (C) 2026 SpruceOS Team
 // Licensed under GPL-3.0-or-later
 
+use sha2::{Digest, Sha256};
+use std::io::Read;
 use std::path::{Path, PathBuf};
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
@@ -16,6 +18,24 @@ pub enum CopyProgress {
     Error(String),
 }
 
+#[derive(Debug, Clone)]
+pub enum VerifyProgress {
+    /// Writing cached data out to the card before reading it back
+    Flushing,
+    Started { total_bytes: u64, total_files: u64 },
+    Progress { verified_bytes: u64, total_bytes: u64, current_file: String },
+    /// A file on the card differs from the original
+    Mismatch { file: String, reason: String },
+    Completed,
+    Cancelled,
+    Error(String),
+}
+
+/// Read buffer size for verification (a multiple of VERIFY_ALIGNMENT)
+const VERIFY_CHUNK_SIZE: usize = 1024 * 1024;
+/// Buffer alignment required for unbuffered reads on Windows (covers 4K-sector cards)
+const VERIFY_ALIGNMENT: usize = 4096;
+
 /// Recursively collect all files in a directory (including hidden files)
 fn collect_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
     let mut files = Vec::new();
@@ -141,3 +161,207 @@ pub async fn copy_directory_with_progress(
 
     Ok(())
 }
+
+/// Read back every file copied from source_dir and compare its SHA-256 with the original
+/// The card is flushed first and its files are read around the OS cache where the platform
+/// allows it, so a card that drops or corrupts writes is caught. Every mismatch is
+/// reported as it is found; the error lists how many files failed.
+pub async fn verify_copied_files(
+    source_dir: &Path,
+    dest_dir: &Path,
+    progress_tx: mpsc::UnboundedSender<VerifyProgress>,
+    cancel_token: CancellationToken,
+) -> Result<(), String> {
+    crate::debug::log_section("Verify Copied Files");
+    crate::debug::log(&format!("Source: {:?}", source_dir));
+    crate::debug::log(&format!("Card: {:?}", dest_dir));
+
+    let source_dir = source_dir.to_path_buf();
+    let dest_dir = dest_dir.to_path_buf();
+    let tx = progress_tx.clone();
+    let token = cancel_token.clone();
+
+    let result = tokio::task::spawn_blocking(move || -> Result<(), String> {
+        let _ = tx.send(VerifyProgress::Flushing);
+        flush_filesystems();
+
+        let files = collect_files(&source_dir)
+            .map_err(|e| format!("Failed to scan source directory: {}", e))?;
+        let total_files = files.len() as u64;
+        let total_bytes = calculate_total_size(&files);
+        let _ = tx.send(VerifyProgress::Started { total_bytes, total_files });
+
+        let mut storage = vec![0u8; VERIFY_CHUNK_SIZE + VERIFY_ALIGNMENT];
+        let offset = storage.as_ptr().align_offset(VERIFY_ALIGNMENT);
+        let buffer = &mut storage[offset..offset + VERIFY_CHUNK_SIZE];
+
+        let mut verified_bytes = 0u64;
+        let mut mismatches = Vec::new();
+        for file_path in &files {
+            if token.is_cancelled() {
+                crate::debug::log("Verification cancelled by user");
+                return Err("Verification cancelled".to_string());
+            }
+
+            let relative_path = file_path.strip_prefix(&source_dir)
+                .map_err(|e| format!("Failed to get relative path: {}", e))?;
+            let file_name = relative_path.to_string_lossy().to_string();
+            let _ = tx.send(VerifyProgress::Progress {
+                verified_bytes,
+                total_bytes,
+                current_file: file_name.clone(),
+            });
+
+            let (size, expected) = hash_file(std::fs::File::open(file_path), buffer)
+                .map_err(|e| format!("Failed to read {:?}: {}", file_path, e))?;
+
+            let reason = match hash_file(open_uncached(&dest_dir.join(relative_path)), buffer) {
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Some("missing on the card".to_string()),
+                Err(e) => Some(format!("read error: {}", e)),
+                Ok((card_size, _)) if card_size != size => Some(format!("{} bytes on the card, expected {}", card_size, size)),
+                Ok((_, actual)) if actual != expected => Some("contents differ".to_string()),
+                Ok(_) => None,
+            };
+            if let Some(reason) = reason {
+                crate::debug::log(&format!("MISMATCH: {} ({})", file_name, reason));
+                let _ = tx.send(VerifyProgress::Mismatch { file: file_name.clone(), reason });
+                mismatches.push(file_name);
+            }
+
+            verified_bytes += size;
+        }
+
+        let _ = tx.send(VerifyProgress::Progress {
+            verified_bytes: total_bytes,
+            total_bytes,
+            current_file: String::new(),
+        });
+
+        if !mismatches.is_empty() {
+            return Err(format!(
+                "{} of {} files on the card don't match the originals ({}{}). The card may be damaged or counterfeit",
+                mismatches.len(),
+                total_files,
+                mismatches.iter().take(3).cloned().collect::<Vec<_>>().join(", "),
+                if mismatches.len() > 3 { ", ..." } else { "" }
+            ));
+        }
+
+        crate::debug::log(&format!("Verified {} files, {} bytes", total_files, total_bytes));
+        Ok(())
+    })
+    .await
+    .map_err(|e| format!("Verification task failed: {}", e))?;
+
+    match &result {
+        Ok(()) => {
+            let _ = progress_tx.send(VerifyProgress::Completed);
+        }
+        Err(_) if cancel_token.is_cancelled() => {
+            let _ = progress_tx.send(VerifyProgress::Cancelled);
+        }
+        Err(e) => {
+            let _ = progress_tx.send(VerifyProgress::Error(e.clone()));
+        }
+    }
+    result
+}
+
+/// Size and SHA-256 of a file's contents
+fn hash_file(file: std::io::Result<std::fs::File>, buffer: &mut [u8]) -> std::io::Result<(u64, [u8; 32])> {
+    let mut file = file?;
+    let mut hasher = Sha256::new();
+    let mut size = 0u64;
+    loop {
+        let n = match file.read(buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        };
+        hasher.update(&buffer[..n]);
+        size += n as u64;
+    }
+    Ok((size, hasher.finalize().into()))
+}
+
+/// Write all cached file data out to the disks
+/// On Windows, unbuffered reads already see the data as written to the card.
+fn flush_filesystems() {
+    #[cfg(unix)]
+    unsafe {
+        libc::sync();
+    }
+}
+
+/// Open a file on the card so reads come from the card rather than the OS cache
+fn open_uncached(path: &Path) -> std::io::Result<std::fs::File> {
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::fs::OpenOptionsExt;
+        use windows::Win32::Storage::FileSystem::FILE_FLAG_NO_BUFFERING;
+        // Needs sector-aligned buffers and read sizes (VERIFY_ALIGNMENT)
+        std::fs::OpenOptions::new()
+            .read(true)
+            .custom_flags(FILE_FLAG_NO_BUFFERING.0)
+            .open(path)
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    {
+        let file = std::fs::File::open(path)?;
+
+        // The pages are clean after sync, so they can be dropped and re-read from the card
+        #[cfg(target_os = "linux")]
+        unsafe {
+            use std::os::unix::io::AsRawFd;
+            libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
+        }
+
+        #[cfg(target_os = "macos")]
+        unsafe {
+            use std::os::unix::io::AsRawFd;
+            libc::fcntl(file.as_raw_fd(), libc::F_NOCACHE, 1);
+        }
+
+        Ok(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_verify_copied_files() {
+        let source = tempfile::tempdir().unwrap();
+        let card = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(source.path().join("spruce/bin")).unwrap();
+        std::fs::write(source.path().join("spruce/bin/app"), vec![7u8; 3 * VERIFY_CHUNK_SIZE / 2]).unwrap();
+        std::fs::write(source.path().join("readme.txt"), b"hello").unwrap();
+
+        let (tx, _rx) = mpsc::unbounded_channel();
+        copy_directory_with_progress(source.path(), card.path(), tx, CancellationToken::new()).await.unwrap();
+
+        let (tx, _rx) = mpsc::unbounded_channel();
+        verify_copied_files(source.path(), card.path(), tx, CancellationToken::new()).await.unwrap();
+
+        // A flipped byte and a missing file are both reported
+        std::fs::write(card.path().join("readme.txt"), b"hellO").unwrap();
+        std::fs::remove_file(card.path().join("spruce/bin/app")).unwrap();
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let err = verify_copied_files(source.path(), card.path(), tx, CancellationToken::new()).await.unwrap_err();
+        assert!(err.starts_with("2 of 2 files"), "{}", err);
+
+        let mut mismatches = Vec::new();
+        while let Ok(progress) = rx.try_recv() {
+            if let VerifyProgress::Mismatch { file, reason } = progress {
+                mismatches.push((file, reason));
+            }
+        }
+        mismatches.sort();
+        assert_eq!(mismatches.len(), 2);
+        assert_eq!(mismatches[0].1, "contents differ");
+        assert_eq!(mismatches[1].1, "missing on the card");
+    }
+}
@@ -16,6 +16,7 @@ lazy_static::lazy_static! {
 pub struct DebugLog {
     path: PathBuf,
     enabled: bool,
+    echo_to_console: bool,
 }
 
 impl DebugLog {
@@ -64,16 +65,19 @@ impl DebugLog {
         Self {
             path: final_path,
             enabled: true,
+            echo_to_console: true,
         }
     }
 }
 
 /// Log a debug message
 pub fn log(message: &str) {
-    // Also print to stdout for VS Code debug console visibility
-    println!("[DEBUG] {}", message);
-
     if let Ok(debug_log) = DEBUG_LOG.lock() {
+        // Also print to stdout for VS Code debug console visibility
+        if debug_log.echo_to_console {
+            println!("[DEBUG] {}", message);
+        }
+
         if debug_log.enabled {
             if let Ok(mut f) = std::fs::OpenOptions::new()
                 .create(true)
@@ -90,6 +94,15 @@ pub fn log(message: &str) {
     }
 }
 
+/// Enable or disable echoing debug messages to stdout
+/// The headless CLI turns this off so its own progress output stays readable;
+/// messages are still written to the log file either way
+pub fn set_console_echo(enabled: bool) {
+    if let Ok(mut debug_log) = DEBUG_LOG.lock() {
+        debug_log.echo_to_console = enabled;
+    }
+}
+
 /// Log a section header
 pub fn log_section(section: &str) {
     log(&format!("\n=== {} ===", section));
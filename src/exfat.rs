@@ -0,0 +1,448 @@
+// Copyright (C) 2026 SpruceOS Team
+// Licensed under GPL-3.0-or-later
+
+// Native exFAT formatter
+// For repositories whose firmware reads exFAT (see RepoOption::filesystem), which has
+// no 4 GB file size limit. Writes the boot regions, FAT, allocation bitmap, up-case
+// table and root directory of an empty volume to anything seekable; fat32.rs writes
+// the partition table around it and shares its aligned writer.
+
+use crate::fat32::{AlignedWriter, FormatOptions, SECTOR_SIZE};
+use std::io::{Seek, Write};
+use tokio_util::sync::CancellationToken;
+
+/// MBR partition type of exFAT (shared with NTFS)
+pub const PARTITION_TYPE_EXFAT: u8 = 0x07;
+/// Main and backup boot regions are 12 sectors each; the FAT starts after both
+const BOOT_REGION_SECTORS: u64 = 12;
+/// Writes are aligned to and sized in multiples of this, as unbuffered device I/O requires
+const IO_ALIGNMENT: u64 = 4096;
+const MAX_CLUSTERS: u64 = 0xFFFF_FFF5;
+const MAX_CLUSTER_SIZE: u32 = 32 * 1024 * 1024;
+const FIRST_CLUSTER: u32 = 2;
+const END_OF_CHAIN: u32 = 0xFFFF_FFFF;
+/// Longest volume label (UTF-16 code units)
+const MAX_LABEL_LEN: usize = 11;
+
+const ENTRY_ALLOCATION_BITMAP: u8 = 0x81;
+const ENTRY_UPCASE_TABLE: u8 = 0x82;
+const ENTRY_VOLUME_LABEL: u8 = 0x83;
+
+#[derive(Debug)]
+struct ExfatParams {
+    /// Sectors before the volume (its partition's start sector)
+    partition_offset: u64,
+    volume_length: u64,
+    fat_offset: u32,
+    fat_length: u32,
+    cluster_heap_offset: u32,
+    cluster_count: u32,
+    sectors_per_cluster_shift: u8,
+}
+
+impl ExfatParams {
+    fn bytes_per_cluster(&self) -> u64 {
+        (SECTOR_SIZE as u64) << self.sectors_per_cluster_shift
+    }
+
+    /// Byte offset of a cluster from the start of the volume
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        self.cluster_heap_offset as u64 * SECTOR_SIZE as u64 + (cluster - FIRST_CLUSTER) as u64 * self.bytes_per_cluster()
+    }
+}
+
+fn calculate_params(total_sectors: u64, partition_offset: u64, options: &FormatOptions) -> Result<ExfatParams, String> {
+    let total_bytes = total_sectors * SECTOR_SIZE as u64;
+    let align = options.alignment_sectors()?;
+
+    // Cluster size based on volume size (Microsoft defaults) unless the repository sets one
+    let cluster_bytes: u64 = if let Some(size) = options.cluster_size {
+        if !size.is_power_of_two() || !(IO_ALIGNMENT as u32..=MAX_CLUSTER_SIZE).contains(&size) {
+            return Err(format!("exFAT cluster size must be a power of two between 4 KB and 32 MB ({} bytes requested)", size));
+        }
+        size as u64
+    } else if total_bytes <= 256 * 1024 * 1024 {
+        4 * 1024 // 4KB - up to 256MB
+    } else if total_bytes <= 32u64 * 1024 * 1024 * 1024 {
+        32 * 1024 // 32KB - up to 32GB
+    } else {
+        128 * 1024 // 128KB - above 32GB
+    };
+    let spc = cluster_bytes / SECTOR_SIZE as u64;
+    let sectors_per_cluster_shift = spc.trailing_zeros() as u8;
+
+    // The FAT starts on a cluster boundary; the cluster heap on the alignment boundary of the card
+    // (or the cluster size if that is larger; both are powers of two), keeping clusters aligned
+    let fat_offset = (BOOT_REGION_SECTORS * 2).div_ceil(spc) * spc;
+    let heap_align = align.max(spc);
+    let mut cluster_count = total_sectors.saturating_sub(fat_offset) / spc;
+    let (fat_length, cluster_heap_offset) = loop {
+        let fat_length = ((cluster_count + 2) * 4).div_ceil(SECTOR_SIZE as u64).div_ceil(8) * 8;
+        let heap_offset = (partition_offset + fat_offset + fat_length).next_multiple_of(heap_align) - partition_offset;
+        let fitting = total_sectors.saturating_sub(heap_offset) / spc;
+        if fitting >= cluster_count {
+            break (fat_length, heap_offset);
+        }
+        cluster_count = fitting;
+    };
+
+    // Bitmap, up-case table and root directory need a few clusters
+    if cluster_count < 16 {
+        return Err(format!("Volume of {} bytes is too small for exFAT", total_bytes));
+    }
+    if cluster_count > MAX_CLUSTERS {
+        return Err(format!("Volume of {} bytes has too many clusters for exFAT", total_bytes));
+    }
+
+    Ok(ExfatParams {
+        partition_offset,
+        volume_length: total_sectors,
+        fat_offset: fat_offset as u32,
+        fat_length: fat_length as u32,
+        cluster_heap_offset: cluster_heap_offset as u32,
+        cluster_count: cluster_count as u32,
+        sectors_per_cluster_shift,
+    })
+}
+
+fn create_boot_sector(params: &ExfatParams, root_cluster: u32, percent_in_use: u8) -> [u8; 512] {
+    let mut boot = [0u8; 512];
+
+    // Jump instruction and file system name; bytes 11-63 must be zero
+    boot[0..3].copy_from_slice(&[0xEB, 0x76, 0x90]);
+    boot[3..11].copy_from_slice(b"EXFAT   ");
+
+    boot[64..72].copy_from_slice(&params.partition_offset.to_le_bytes());
+    boot[72..80].copy_from_slice(&params.volume_length.to_le_bytes());
+    boot[80..84].copy_from_slice(&params.fat_offset.to_le_bytes());
+    boot[84..88].copy_from_slice(&params.fat_length.to_le_bytes());
+    boot[88..92].copy_from_slice(&params.cluster_heap_offset.to_le_bytes());
+    boot[92..96].copy_from_slice(&params.cluster_count.to_le_bytes());
+    boot[96..100].copy_from_slice(&root_cluster.to_le_bytes());
+
+    // Volume serial number (mixed with the partition start, so the volumes of one card differ)
+    let serial: u32 = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs() as u32)
+        .unwrap_or(0x12345678)
+        ^ params.partition_offset as u32;
+    boot[100..104].copy_from_slice(&serial.to_le_bytes());
+
+    // File system revision 1.00, volume flags clear
+    boot[104..106].copy_from_slice(&0x0100u16.to_le_bytes());
+    boot[108] = SECTOR_SIZE.trailing_zeros() as u8;
+    boot[109] = params.sectors_per_cluster_shift;
+    boot[110] = 1; // Number of FATs
+    boot[111] = 0x80; // Drive select
+    boot[112] = percent_in_use;
+
+    // Boot code: halt
+    boot[120..510].fill(0xF4);
+
+    // Boot signature
+    boot[510] = 0x55;
+    boot[511] = 0xAA;
+
+    boot
+}
+
+/// Main (or backup) boot region: boot sector, 8 extended boot sectors, OEM parameters,
+/// a reserved sector and the checksum sector
+fn create_boot_region(boot_sector: &[u8; 512]) -> Vec<u8> {
+    let sector = SECTOR_SIZE as usize;
+    let mut region = vec![0u8; BOOT_REGION_SECTORS as usize * sector];
+    region[..sector].copy_from_slice(boot_sector);
+    for i in 1..=8 {
+        region[i * sector + 508..(i + 1) * sector].copy_from_slice(&0xAA55_0000u32.to_le_bytes());
+    }
+
+    let checksum = boot_checksum(&region[..11 * sector]);
+    for entry in region[11 * sector..].chunks_mut(4) {
+        entry.copy_from_slice(&checksum.to_le_bytes());
+    }
+    region
+}
+
+/// Checksum of the first 11 boot region sectors, skipping VolumeFlags and PercentInUse
+fn boot_checksum(sectors: &[u8]) -> u32 {
+    sectors
+        .iter()
+        .enumerate()
+        .filter(|(i, _)| !matches!(i, 106 | 107 | 112))
+        .fold(0u32, |sum, (_, &byte)| sum.rotate_right(1).wrapping_add(byte as u32))
+}
+
+/// Checksum of the up-case table as stored (directory entry field)
+fn table_checksum(data: &[u8]) -> u32 {
+    data.iter().fold(0u32, |sum, &byte| sum.rotate_right(1).wrapping_add(byte as u32))
+}
+
+/// Compressed up-case table: runs of characters that map to themselves are stored
+/// as 0xFFFF followed by the run length, everything else as its uppercase form
+fn create_upcase_table() -> Vec<u8> {
+    let upcase = |unit: u32| -> u32 {
+        let Some(c) = char::from_u32(unit) else { return unit };
+        let mut upper = c.to_uppercase();
+        match (upper.next(), upper.next()) {
+            (Some(u), None) if (u as u32) <= 0xFFFF => u as u32,
+            _ => unit,
+        }
+    };
+
+    let mut table: Vec<u16> = Vec::new();
+    let mut unit = 0u32;
+    while unit <= 0xFFFF {
+        let mapped = upcase(unit);
+        if mapped != unit {
+            table.push(mapped as u16);
+            unit += 1;
+            continue;
+        }
+        let start = unit;
+        while unit <= 0xFFFF && upcase(unit) == unit {
+            unit += 1;
+        }
+        table.push(0xFFFF);
+        table.push((unit - start) as u16);
+    }
+    table.iter().flat_map(|u| u.to_le_bytes()).collect()
+}
+
+/// Volume label as stored in its directory entry: up to 11 UTF-16 code units
+fn label_units(volume_label: &str) -> Vec<u16> {
+    volume_label
+        .trim()
+        .encode_utf16()
+        .filter(|&u| u >= 0x20 && !"\"*/:<>?\\|".encode_utf16().any(|bad| bad == u))
+        .take(MAX_LABEL_LEN)
+        .collect()
+}
+
+/// Write an empty exFAT volume of `total_sectors` starting at `start_sector` of `device`
+/// The partition table is left alone. `on_progress` receives the percentage written.
+pub fn format_volume<D: Write + Seek>(
+    device: &mut D,
+    start_sector: u64,
+    total_sectors: u64,
+    volume_label: &str,
+    options: &FormatOptions,
+    cancel_token: &CancellationToken,
+    mut on_progress: impl FnMut(u8),
+) -> Result<(), String> {
+    let params = calculate_params(total_sectors, start_sector, options)?;
+    crate::debug::log(&format!("exFAT parameters: {:?}", params));
+
+    let sector = SECTOR_SIZE as u64;
+    let cluster_bytes = params.bytes_per_cluster();
+    let volume_offset = start_sector * sector;
+
+    // The allocation bitmap, up-case table and root directory fill the first clusters
+    let upcase = create_upcase_table();
+    let bitmap_bytes = (params.cluster_count as u64).div_ceil(8);
+    let bitmap_clusters = bitmap_bytes.div_ceil(cluster_bytes) as u32;
+    let upcase_clusters = (upcase.len() as u64).div_ceil(cluster_bytes) as u32;
+    let bitmap_cluster = FIRST_CLUSTER;
+    let upcase_cluster = bitmap_cluster + bitmap_clusters;
+    let root_cluster = upcase_cluster + upcase_clusters;
+    let used_clusters = bitmap_clusters + upcase_clusters + 1;
+
+    // Only the start of the FAT has entries; the rest is zeroed on the card rather than
+    // held in memory (the FAT of a large card runs to hundreds of MB)
+    let fat_bytes = params.fat_length as u64 * sector;
+    let first_chunk = IO_ALIGNMENT.max(fat_used_bytes(used_clusters).div_ceil(IO_ALIGNMENT) * IO_ALIGNMENT).min(fat_bytes);
+    let mut fat = vec![0u8; first_chunk as usize];
+    let mut set_fat = |cluster: u32, value: u32| {
+        fat[cluster as usize * 4..cluster as usize * 4 + 4].copy_from_slice(&value.to_le_bytes());
+    };
+    set_fat(0, 0xFFFF_FFF8);
+    set_fat(1, END_OF_CHAIN);
+    for (first, count) in [(bitmap_cluster, bitmap_clusters), (upcase_cluster, upcase_clusters), (root_cluster, 1)] {
+        for cluster in first..first + count {
+            set_fat(cluster, if cluster + 1 < first + count { cluster + 1 } else { END_OF_CHAIN });
+        }
+    }
+
+    let mut bitmap = vec![0u8; (bitmap_clusters as u64 * cluster_bytes) as usize];
+    for i in 0..used_clusters as usize {
+        bitmap[i / 8] |= 1 << (i % 8);
+    }
+
+    let mut root = vec![0u8; cluster_bytes as usize];
+    let mut entries = root.chunks_mut(32);
+    let label = label_units(volume_label);
+    if !label.is_empty() {
+        let entry = entries.next().unwrap();
+        entry[0] = ENTRY_VOLUME_LABEL;
+        entry[1] = label.len() as u8;
+        for (i, unit) in label.iter().enumerate() {
+            entry[2 + i * 2..4 + i * 2].copy_from_slice(&unit.to_le_bytes());
+        }
+    }
+    let entry = entries.next().unwrap();
+    entry[0] = ENTRY_ALLOCATION_BITMAP;
+    entry[20..24].copy_from_slice(&bitmap_cluster.to_le_bytes());
+    entry[24..32].copy_from_slice(&bitmap_bytes.to_le_bytes());
+    let entry = entries.next().unwrap();
+    entry[0] = ENTRY_UPCASE_TABLE;
+    entry[4..8].copy_from_slice(&table_checksum(&upcase).to_le_bytes());
+    entry[20..24].copy_from_slice(&upcase_cluster.to_le_bytes());
+    entry[24..32].copy_from_slice(&(upcase.len() as u64).to_le_bytes());
+
+    let mut upcase_data = upcase;
+    upcase_data.resize((upcase_clusters as u64 * cluster_bytes) as usize, 0);
+
+    let mut writer = AlignedWriter::new(device);
+
+    // Clear the boot regions first, so an interrupted format never looks like a valid filesystem
+    let boot_bytes = params.fat_offset as u64 * sector;
+    writer.zero(volume_offset, boot_bytes, cancel_token, |_| {})
+        .map_err(|e| format!("Failed to clear boot region: {}", e))?;
+
+    let fat_start = volume_offset + params.fat_offset as u64 * sector;
+    writer.write_at(fat_start, &fat)
+        .map_err(|e| format!("Failed to write FAT: {}", e))?;
+    // The rest of the FAT is cleared so no stale entries from an earlier filesystem survive
+    writer.zero(fat_start + first_chunk, fat_bytes - first_chunk, cancel_token, |done| {
+        on_progress(((first_chunk + done) * 95 / fat_bytes) as u8)
+    })
+    .map_err(|e| format!("Failed to clear FAT: {}", e))?;
+
+    writer.write_at(volume_offset + params.cluster_offset(bitmap_cluster), &bitmap)
+        .map_err(|e| format!("Failed to write allocation bitmap: {}", e))?;
+    writer.write_at(volume_offset + params.cluster_offset(upcase_cluster), &upcase_data)
+        .map_err(|e| format!("Failed to write up-case table: {}", e))?;
+    writer.write_at(volume_offset + params.cluster_offset(root_cluster), &root)
+        .map_err(|e| format!("Failed to write root directory: {}", e))?;
+
+    // Boot regions last, backup first
+    let percent_in_use = (used_clusters as u64 * 100 / params.cluster_count as u64) as u8;
+    let region = create_boot_region(&create_boot_sector(&params, root_cluster, percent_in_use));
+    let mut boot_area = vec![0u8; boot_bytes as usize];
+    boot_area[region.len()..region.len() * 2].copy_from_slice(&region);
+    writer.write_at(volume_offset, &boot_area)
+        .map_err(|e| format!("Failed to write backup boot region: {}", e))?;
+    boot_area[..region.len()].copy_from_slice(&region);
+    writer.write_at(volume_offset, &boot_area)
+        .map_err(|e| format!("Failed to write boot region: {}", e))?;
+
+    drop(writer);
+    device.flush().map_err(|e| format!("Failed to flush device: {}", e))?;
+    on_progress(100);
+    Ok(())
+}
+
+/// Bytes of the FAT holding entries up to the last used cluster
+fn fat_used_bytes(used_clusters: u32) -> u64 {
+    (FIRST_CLUSTER + used_clusters) as u64 * 4
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Read, SeekFrom};
+
+    #[test]
+    fn test_calculate_params() {
+        let sectors = 64 * 1024 * 1024 * 1024 / 512;
+        let params = calculate_params(sectors, 2048, &FormatOptions::default()).unwrap();
+        assert_eq!(params.bytes_per_cluster(), 128 * 1024);
+        assert!(params.fat_offset as u64 >= BOOT_REGION_SECTORS * 2);
+        assert!(params.fat_length as u64 * 512 >= (params.cluster_count as u64 + 2) * 4);
+        assert!(params.cluster_heap_offset >= params.fat_offset + params.fat_length);
+        assert!(params.cluster_heap_offset as u64 + params.cluster_count as u64 * 256 <= sectors);
+        assert!(params.cluster_heap_offset.is_multiple_of(256));
+
+        assert_eq!(calculate_params(128 * 1024 * 1024 / 512, 2048, &FormatOptions::default()).unwrap().bytes_per_cluster(), 4096);
+        assert!(calculate_params(64, 2048, &FormatOptions::default()).is_err());
+
+        // Repository overrides: 64K clusters, heap on a 4 MiB erase block
+        let options = FormatOptions { cluster_size: Some(65536), alignment: 4 * 1024 * 1024, ..Default::default() };
+        let params = calculate_params(sectors, 8192, &options).unwrap();
+        assert_eq!(params.bytes_per_cluster(), 65536);
+        assert!((8192 + params.cluster_heap_offset as u64).is_multiple_of(8192));
+        let options = FormatOptions { cluster_size: Some(1000), ..Default::default() };
+        assert!(calculate_params(sectors, 2048, &options).is_err());
+    }
+
+    #[test]
+    fn test_boot_checksum_skips_flags() {
+        let params = calculate_params(1 << 21, 2048, &FormatOptions::default()).unwrap();
+        let a = create_boot_region(&create_boot_sector(&params, 5, 0));
+        let b = create_boot_region(&create_boot_sector(&params, 5, 42));
+        assert_eq!(a[11 * 512..], b[11 * 512..]);
+        assert_eq!(&a[2 * 512 - 4..2 * 512], &[0x00, 0x00, 0x55, 0xAA]);
+    }
+
+    #[test]
+    fn test_upcase_table() {
+        let table = create_upcase_table();
+        let units: Vec<u16> = table.chunks(2).map(|c| u16::from_le_bytes([c[0], c[1]])).collect();
+        // Expand it again and check a few mappings
+        let mut expanded = Vec::new();
+        let mut iter = units.iter();
+        while let Some(&unit) = iter.next() {
+            if unit == 0xFFFF {
+                let count = *iter.next().unwrap();
+                let start = expanded.len() as u16;
+                expanded.extend((0..count).map(|i| start + i));
+            } else {
+                expanded.push(unit);
+            }
+        }
+        assert_eq!(expanded.len(), 0x10000);
+        assert_eq!(expanded['a' as usize], 'A' as u16);
+        assert_eq!(expanded['Z' as usize], 'Z' as u16);
+        assert_eq!(expanded['é' as usize], 'É' as u16);
+    }
+
+    #[test]
+    fn test_label_units() {
+        assert_eq!(String::from_utf16(&label_units("SpruceOS")).unwrap(), "SpruceOS");
+        assert_eq!(label_units("A very long label").len(), MAX_LABEL_LEN);
+        assert_eq!(String::from_utf16(&label_units("a/b:c")).unwrap(), "abc");
+    }
+
+    #[test]
+    fn test_format_volume() {
+        let start = 2048u64;
+        let sectors = 300 * 1024 * 1024 / 512;
+        let mut image = Cursor::new(vec![0xAAu8; ((start + sectors) * 512) as usize]);
+        format_volume(&mut image, start, sectors, "SPRUCEOS", &FormatOptions::default(), &CancellationToken::new(), |_| {}).unwrap();
+
+        let mut region = vec![0u8; 24 * 512];
+        image.seek(SeekFrom::Start(start * 512)).unwrap();
+        image.read_exact(&mut region).unwrap();
+        assert_eq!(&region[3..11], b"EXFAT   ");
+        assert!(region[11..64].iter().all(|&b| b == 0));
+        assert_eq!(region[..12 * 512], region[12 * 512..]);
+        let checksum = boot_checksum(&region[..11 * 512]);
+        assert_eq!(region[11 * 512..11 * 512 + 4], checksum.to_le_bytes());
+
+        let boot: [u8; 512] = region[..512].try_into().unwrap();
+        let field = |at: usize| u32::from_le_bytes(boot[at..at + 4].try_into().unwrap()) as u64;
+        assert_eq!(u64::from_le_bytes(boot[64..72].try_into().unwrap()), start);
+        let (fat_offset, heap_offset, root_cluster) = (field(80), field(88), field(96));
+        let cluster_bytes = 512u64 << boot[109];
+
+        let read_u32 = |image: &mut Cursor<Vec<u8>>, at: u64| {
+            let mut bytes = [0u8; 4];
+            image.seek(SeekFrom::Start(at)).unwrap();
+            image.read_exact(&mut bytes).unwrap();
+            u32::from_le_bytes(bytes)
+        };
+        let fat = (start + fat_offset) * 512;
+        assert_eq!(read_u32(&mut image, fat), 0xFFFF_FFF8);
+        assert_eq!(read_u32(&mut image, fat + root_cluster * 4), END_OF_CHAIN);
+        // Old data in the FAT was cleared
+        assert_eq!(read_u32(&mut image, fat + (root_cluster + 1) * 4), 0);
+        assert_eq!(read_u32(&mut image, fat + field(84) * 512 - 4), 0);
+
+        let mut root = vec![0u8; 96];
+        image.seek(SeekFrom::Start((start + heap_offset) * 512 + (root_cluster - 2) * cluster_bytes)).unwrap();
+        image.read_exact(&mut root).unwrap();
+        assert_eq!(root[0], ENTRY_VOLUME_LABEL);
+        assert_eq!(root[1], 8);
+        assert_eq!(root[32], ENTRY_ALLOCATION_BITMAP);
+        assert_eq!(root[64], ENTRY_UPCASE_TABLE);
+    }
+}
@@ -1,7 +1,9 @@
 // Copyright (C) 2026 SpruceOS Team
 // Licensed under GPL-3.0-or-later
 
+use crate::compression::{Compression, CountingReader};
 use crate::config::TEMP_PREFIX;
+use std::io::{Read, Write};
 use std::path::Path;
 use std::process::Stdio;
 use tokio::io::AsyncReadExt;
@@ -40,21 +42,329 @@ pub enum ExtractProgress {
     Started,
     Extracting,
     Progress { percent: u8 },
+    /// Byte-level progress from in-process extraction
+    /// (for tarballs, which are streamed, extracted/total count bytes of the archive file)
+    Writing { file: String, extracted: u64, total: u64 },
     Completed,
     Cancelled,
     Error(String),
 }
 
-pub async fn extract_7z(
+/// Archive formats that can be extracted onto the card
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    SevenZip,
+    Zip,
+    Tar,
+    TarGz,
+    TarXz,
+    TarZst,
+}
+
+impl ArchiveFormat {
+    /// Detect the format from the file name
+    pub fn from_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        let format = if name.ends_with(".7z") {
+            ArchiveFormat::SevenZip
+        } else if name.ends_with(".zip") {
+            ArchiveFormat::Zip
+        } else if name.ends_with(".tar") {
+            ArchiveFormat::Tar
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            ArchiveFormat::TarGz
+        } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+            ArchiveFormat::TarXz
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            ArchiveFormat::TarZst
+        } else {
+            return None;
+        };
+        Some(format)
+    }
+}
+
+/// Progress is reported at most once per this many extracted bytes (and on every new file)
+const PROGRESS_INTERVAL: u64 = 1024 * 1024;
+
+/// Why in-process extraction stopped
+enum NativeError {
+    /// The archive uses something sevenz-rust can't decode - retry with the 7z binary
+    Unsupported(String),
+    Failed(String),
+}
+
+/// Throttled ExtractProgress::Writing reports
+struct ProgressReporter {
+    tx: mpsc::UnboundedSender<ExtractProgress>,
+    total: u64,
+    last_reported: u64,
+}
+
+impl ProgressReporter {
+    fn new(tx: mpsc::UnboundedSender<ExtractProgress>, total: u64) -> Self {
+        Self { tx, total, last_reported: 0 }
+    }
+
+    fn report(&mut self, file: &str, extracted: u64, force: bool) {
+        if force || extracted - self.last_reported >= PROGRESS_INTERVAL {
+            self.last_reported = extracted;
+            let _ = self.tx.send(ExtractProgress::Writing {
+                file: file.to_string(),
+                extracted,
+                total: self.total,
+            });
+        }
+    }
+}
+
+/// Copy one archive member to disk, checking for cancellation between buffers
+/// `on_write` is called with the number of bytes written after every buffer
+fn write_entry(
+    data: &mut dyn Read,
+    dest_path: &Path,
+    buffer: &mut [u8],
+    cancel_token: &CancellationToken,
+    mut on_write: impl FnMut(u64),
+) -> std::io::Result<std::fs::File> {
+    if let Some(parent) = dest_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::io::BufWriter::new(std::fs::File::create(dest_path)?);
+
+    loop {
+        if cancel_token.is_cancelled() {
+            return Err(std::io::Error::other("cancelled"));
+        }
+        let n = data.read(buffer)?;
+        if n == 0 {
+            break;
+        }
+        file.write_all(&buffer[..n])?;
+        on_write(n as u64);
+    }
+
+    file.into_inner().map_err(|e| e.into_error())
+}
+
+/// Extract a .7z in-process with sevenz-rust, reporting per-file byte progress
+async fn extract_7z_native(
     archive_path: &Path,
     dest_dir: &Path,
     progress_tx: mpsc::UnboundedSender<ExtractProgress>,
     cancel_token: CancellationToken,
+) -> Result<(), NativeError> {
+    let archive_path = archive_path.to_path_buf();
+    let dest_dir = dest_dir.to_path_buf();
+
+    tokio::task::spawn_blocking(move || {
+        use sevenz_rust::{Error, Password, SevenZReader};
+
+        let mut reader = SevenZReader::open(&archive_path, Password::empty())
+            .map_err(|e| match e {
+                Error::PasswordRequired => NativeError::Failed("Archive is password protected".to_string()),
+                e => NativeError::Failed(format!("Failed to open archive: {}", e)),
+            })?;
+
+        let total: u64 = reader.archive().files.iter().map(|f| f.size()).sum();
+        crate::debug::log(&format!(
+            "Archive contains {} entries ({} MB uncompressed)",
+            reader.archive().files.len(),
+            total / 1_048_576
+        ));
+
+        std::fs::create_dir_all(&dest_dir)
+            .map_err(|e| NativeError::Failed(format!("Failed to create destination directory: {}", e)))?;
+        let _ = progress_tx.send(ExtractProgress::Extracting);
+
+        let mut progress = ProgressReporter::new(progress_tx, total);
+        let mut extracted = 0u64;
+        let mut buffer = vec![0u8; 256 * 1024];
+
+        let result = reader.for_each_entries(|entry, data| {
+            if entry.is_anti_item() {
+                return Ok(true);
+            }
+
+            let dest_path = safe_entry_path(&dest_dir, entry.name())
+                .ok_or_else(|| Error::other(format!("Unsafe path in archive: {}", entry.name())))?;
+
+            if entry.is_directory() {
+                std::fs::create_dir_all(&dest_path)?;
+                return Ok(true);
+            }
+
+            progress.report(entry.name(), extracted, true);
+            let file = write_entry(data, &dest_path, &mut buffer, &cancel_token, |n| {
+                extracted += n;
+                progress.report(entry.name(), extracted, false);
+            })?;
+            if entry.has_last_modified_date {
+                let _ = file.set_modified(std::time::SystemTime::from(entry.last_modified_date()));
+            }
+            Ok(true)
+        });
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(_) if cancel_token.is_cancelled() => Err(NativeError::Failed("Extraction cancelled".to_string())),
+            Err(e @ (Error::UnsupportedCompressionMethod(_) | Error::Unsupported(_) | Error::ExternalUnsupported)) => {
+                Err(NativeError::Unsupported(e.to_string()))
+            }
+            Err(Error::ChecksumVerificationFailed) => {
+                Err(NativeError::Failed("Archive is corrupt (checksum mismatch)".to_string()))
+            }
+            Err(e) => Err(NativeError::Failed(format!("Extraction failed: {}", e))),
+        }
+    })
+    .await
+    .map_err(|e| NativeError::Failed(format!("Extraction task failed: {}", e)))?
+}
+
+/// Extract a .zip archive (stored or deflated members)
+fn extract_zip_blocking(
+    archive_path: &Path,
+    dest_dir: &Path,
+    progress_tx: mpsc::UnboundedSender<ExtractProgress>,
+    cancel_token: &CancellationToken,
 ) -> Result<(), String> {
-    crate::debug::log_section("7z Extraction");
-    crate::debug::log(&format!("Archive: {:?}", archive_path));
-    crate::debug::log(&format!("Destination: {:?}", dest_dir));
+    let file = std::fs::File::open(archive_path)
+        .map_err(|e| format!("Failed to open archive: {}", e))?;
+    let mut archive = zip::ZipArchive::new(std::io::BufReader::new(file))
+        .map_err(|e| format!("Failed to read zip archive: {}", e))?;
+
+    let total: u64 = (0..archive.len())
+        .filter_map(|i| archive.by_index_raw(i).ok().map(|f| f.size()))
+        .sum();
+    crate::debug::log(&format!("Archive contains {} entries ({} MB uncompressed)", archive.len(), total / 1_048_576));
+    let _ = progress_tx.send(ExtractProgress::Extracting);
+
+    let mut progress = ProgressReporter::new(progress_tx, total);
+    let mut extracted = 0u64;
+    let mut buffer = vec![0u8; 256 * 1024];
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)
+            .map_err(|e| format!("Failed to read zip entry {}: {}", i, e))?;
+        let name = entry.name().to_string();
+
+        // enclosed_name rejects absolute paths and `..`
+        let dest_path = entry.enclosed_name()
+            .map(|relative| dest_dir.join(relative))
+            .ok_or_else(|| format!("Unsafe path in archive: {}", name))?;
+
+        if entry.is_dir() {
+            std::fs::create_dir_all(&dest_path)
+                .map_err(|e| format!("Failed to create directory {}: {}", name, e))?;
+            continue;
+        }
+
+        progress.report(&name, extracted, true);
+        write_entry(&mut entry, &dest_path, &mut buffer, cancel_token, |n| {
+            extracted += n;
+            progress.report(&name, extracted, false);
+        })
+        .map_err(|e| entry_error(&name, e, cancel_token))?;
+    }
+
+    Ok(())
+}
 
+/// Extract a (possibly compressed) tarball
+/// Only regular files and directories are extracted - links can't exist on a FAT32 card
+fn extract_tar_blocking(
+    archive_path: &Path,
+    format: ArchiveFormat,
+    dest_dir: &Path,
+    progress_tx: mpsc::UnboundedSender<ExtractProgress>,
+    cancel_token: &CancellationToken,
+) -> Result<(), String> {
+    let file = std::fs::File::open(archive_path)
+        .map_err(|e| format!("Failed to open archive: {}", e))?;
+    let total = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+    // Tarballs are streamed, so progress follows how much of the archive file has been read
+    let counting = CountingReader::new(std::io::BufReader::new(file));
+    let position = counting.counter();
+
+    let decoder = match format {
+        ArchiveFormat::TarGz => Compression::Gzip,
+        ArchiveFormat::TarXz => Compression::Xz,
+        ArchiveFormat::TarZst => Compression::Zstd,
+        _ => Compression::None,
+    }
+    .decoder(counting)?;
+
+    let _ = progress_tx.send(ExtractProgress::Extracting);
+    let mut progress = ProgressReporter::new(progress_tx, total);
+    let mut buffer = vec![0u8; 256 * 1024];
+    let mut archive = tar::Archive::new(decoder);
+
+    let entries = archive.entries()
+        .map_err(|e| format!("Failed to read tar archive: {}", e))?;
+    for entry in entries {
+        if cancel_token.is_cancelled() {
+            return Err("Extraction cancelled".to_string());
+        }
+
+        let mut entry = entry.map_err(|e| format!("Failed to read tar entry: {}", e))?;
+        let name = entry.path()
+            .map(|p| p.to_string_lossy().to_string())
+            .map_err(|e| format!("Invalid path in archive: {}", e))?;
+        let dest_path = safe_entry_path(dest_dir, &name)
+            .ok_or_else(|| format!("Unsafe path in archive: {}", name))?;
+
+        match entry.header().entry_type() {
+            tar::EntryType::Directory => {
+                std::fs::create_dir_all(&dest_path)
+                    .map_err(|e| format!("Failed to create directory {}: {}", name, e))?;
+            }
+            tar::EntryType::Regular | tar::EntryType::Continuous => {
+                progress.report(&name, position.get(), true);
+                let file = write_entry(&mut entry, &dest_path, &mut buffer, cancel_token, |_| {
+                    progress.report(&name, position.get(), false);
+                })
+                .map_err(|e| entry_error(&name, e, cancel_token))?;
+                if let Ok(mtime) = entry.header().mtime() {
+                    let _ = file.set_modified(std::time::UNIX_EPOCH + std::time::Duration::from_secs(mtime));
+                }
+            }
+            other => crate::debug::log(&format!("Skipping {} (unsupported entry type {:?})", name, other)),
+        }
+    }
+
+    Ok(())
+}
+
+fn entry_error(name: &str, e: std::io::Error, cancel_token: &CancellationToken) -> String {
+    if cancel_token.is_cancelled() {
+        "Extraction cancelled".to_string()
+    } else {
+        format!("Failed to extract {}: {}", name, e)
+    }
+}
+
+/// Join an archive entry name onto the destination, refusing absolute paths and `..`
+fn safe_entry_path(dest_dir: &Path, name: &str) -> Option<std::path::PathBuf> {
+    let mut path = dest_dir.to_path_buf();
+    for component in name.split(['/', '\\']) {
+        match component {
+            "" | "." => continue,
+            ".." => return None,
+            c if c.contains(':') => return None,
+            c => path.push(c),
+        }
+    }
+    (path != dest_dir).then_some(path)
+}
+
+/// Extract a .7z with the embedded 7-Zip binary (fallback for codecs sevenz-rust lacks)
+async fn extract_7z_external(
+    archive_path: &Path,
+    dest_dir: &Path,
+    progress_tx: mpsc::UnboundedSender<ExtractProgress>,
+    cancel_token: CancellationToken,
+) -> Result<(), String> {
     // Check for cancellation before starting
     if cancel_token.is_cancelled() {
         let _ = progress_tx.send(ExtractProgress::Cancelled);
@@ -381,11 +691,207 @@ fn parse_last_percentage(text: &str) -> Option<u8> {
 }
 
 /// Main entry point for extraction with cancellation support
+/// Extracts in-process; the embedded 7z binary is only used for codecs sevenz-rust doesn't support
 pub async fn extract_7z_with_progress(
     archive_path: &Path,
     dest_dir: &Path,
     progress_tx: mpsc::UnboundedSender<ExtractProgress>,
     cancel_token: CancellationToken,
 ) -> Result<(), String> {
-    extract_7z(archive_path, dest_dir, progress_tx, cancel_token).await
+    crate::debug::log_section("7z Extraction");
+    crate::debug::log(&format!("Archive: {:?}", archive_path));
+    crate::debug::log(&format!("Destination: {:?}", dest_dir));
+
+    if cancel_token.is_cancelled() {
+        let _ = progress_tx.send(ExtractProgress::Cancelled);
+        return Err("Extraction cancelled".to_string());
+    }
+    let _ = progress_tx.send(ExtractProgress::Started);
+
+    match extract_7z_native(archive_path, dest_dir, progress_tx.clone(), cancel_token.clone()).await {
+        Ok(()) => {
+            crate::debug::log("7z extraction completed successfully");
+            let _ = progress_tx.send(ExtractProgress::Completed);
+            Ok(())
+        }
+        Err(NativeError::Unsupported(reason)) => {
+            crate::debug::log(&format!("In-process extraction not possible ({}), falling back to 7z binary", reason));
+            extract_7z_external(archive_path, dest_dir, progress_tx, cancel_token).await
+        }
+        Err(NativeError::Failed(e)) => {
+            crate::debug::log(&format!("ERROR: {}", e));
+            let _ = progress_tx.send(if cancel_token.is_cancelled() {
+                ExtractProgress::Cancelled
+            } else {
+                ExtractProgress::Error(e.clone())
+            });
+            Err(e)
+        }
+    }
+}
+
+/// Extract any supported archive (see ArchiveFormat), picking the format from the file name
+pub async fn extract_archive(
+    archive_path: &Path,
+    dest_dir: &Path,
+    progress_tx: mpsc::UnboundedSender<ExtractProgress>,
+    cancel_token: CancellationToken,
+) -> Result<(), String> {
+    let name = archive_path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+    let format = ArchiveFormat::from_name(&name)
+        .ok_or_else(|| format!("Unsupported archive format: {}", name))?;
+
+    if format == ArchiveFormat::SevenZip {
+        return extract_7z_with_progress(archive_path, dest_dir, progress_tx, cancel_token).await;
+    }
+
+    crate::debug::log_section("Archive Extraction");
+    crate::debug::log(&format!("Archive: {:?} ({:?})", archive_path, format));
+    crate::debug::log(&format!("Destination: {:?}", dest_dir));
+
+    if cancel_token.is_cancelled() {
+        let _ = progress_tx.send(ExtractProgress::Cancelled);
+        return Err("Extraction cancelled".to_string());
+    }
+    let _ = progress_tx.send(ExtractProgress::Started);
+
+    let archive = archive_path.to_path_buf();
+    let dest = dest_dir.to_path_buf();
+    let tx = progress_tx.clone();
+    let token = cancel_token.clone();
+    let result = tokio::task::spawn_blocking(move || {
+        std::fs::create_dir_all(&dest)
+            .map_err(|e| format!("Failed to create destination directory: {}", e))?;
+        match format {
+            ArchiveFormat::Zip => extract_zip_blocking(&archive, &dest, tx, &token),
+            _ => extract_tar_blocking(&archive, format, &dest, tx, &token),
+        }
+    })
+    .await
+    .map_err(|e| format!("Extraction task failed: {}", e))?;
+
+    match &result {
+        Ok(()) => {
+            crate::debug::log("Extraction completed successfully");
+            let _ = progress_tx.send(ExtractProgress::Completed);
+        }
+        Err(_) if cancel_token.is_cancelled() => {
+            let _ = progress_tx.send(ExtractProgress::Cancelled);
+            return Err("Extraction cancelled".to_string());
+        }
+        Err(e) => {
+            crate::debug::log(&format!("ERROR: {}", e));
+            let _ = progress_tx.send(ExtractProgress::Error(e.clone()));
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_safe_entry_path() {
+        let dest = Path::new("/tmp/extract");
+        assert_eq!(safe_entry_path(dest, "spruce/bin/app"), Some(dest.join("spruce").join("bin").join("app")));
+        assert_eq!(safe_entry_path(dest, "Retroarch\\ra.cfg"), Some(dest.join("Retroarch").join("ra.cfg")));
+        assert_eq!(safe_entry_path(dest, "../etc/passwd"), None);
+        assert_eq!(safe_entry_path(dest, "C:/Windows"), None);
+        assert_eq!(safe_entry_path(dest, "/"), None);
+    }
+
+    #[test]
+    fn test_archive_format() {
+        assert_eq!(ArchiveFormat::from_name("spruceOS.7z"), Some(ArchiveFormat::SevenZip));
+        assert_eq!(ArchiveFormat::from_name("NextUI.ZIP"), Some(ArchiveFormat::Zip));
+        assert_eq!(ArchiveFormat::from_name("os.tar.gz"), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::from_name("os.tgz"), Some(ArchiveFormat::TarGz));
+        assert_eq!(ArchiveFormat::from_name("os.tar.xz"), Some(ArchiveFormat::TarXz));
+        assert_eq!(ArchiveFormat::from_name("os.tar.zst"), Some(ArchiveFormat::TarZst));
+        assert_eq!(ArchiveFormat::from_name("card.img.gz"), None);
+    }
+
+    #[tokio::test]
+    async fn test_extract_zip_and_tarballs() {
+        let dir = tempfile::tempdir().unwrap();
+        let content = vec![b'x'; 2 * 1024 * 1024];
+
+        // zip
+        let zip_path = dir.path().join("os.zip");
+        {
+            let mut writer = zip::ZipWriter::new(std::fs::File::create(&zip_path).unwrap());
+            writer.add_directory("spruce/", zip::write::SimpleFileOptions::default()).unwrap();
+            writer.start_file("spruce/app.txt", zip::write::SimpleFileOptions::default()).unwrap();
+            writer.write_all(&content).unwrap();
+            writer.finish().unwrap();
+        }
+
+        // tar.gz / tar.xz / tar.zst with the same contents
+        let mut tar_data = Vec::new();
+        {
+            let mut builder = tar::Builder::new(&mut tar_data);
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, "spruce/app.txt", content.as_slice()).unwrap();
+            builder.finish().unwrap();
+        }
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::fast());
+        gz.write_all(&tar_data).unwrap();
+        std::fs::write(dir.path().join("os.tar.gz"), gz.finish().unwrap()).unwrap();
+        let mut xz = xz2::write::XzEncoder::new(Vec::new(), 1);
+        xz.write_all(&tar_data).unwrap();
+        std::fs::write(dir.path().join("os.tar.xz"), xz.finish().unwrap()).unwrap();
+        std::fs::write(dir.path().join("os.tar.zst"), zstd::encode_all(tar_data.as_slice(), 1).unwrap()).unwrap();
+
+        for name in ["os.zip", "os.tar.gz", "os.tar.xz", "os.tar.zst"] {
+            let dest = dir.path().join(format!("{}_out", name));
+            let (tx, mut rx) = mpsc::unbounded_channel();
+            extract_archive(&dir.path().join(name), &dest, tx, CancellationToken::new()).await.unwrap();
+            assert_eq!(std::fs::read(dest.join("spruce").join("app.txt")).unwrap(), content, "{}", name);
+
+            let mut completed = false;
+            while let Ok(progress) = rx.try_recv() {
+                completed |= matches!(progress, ExtractProgress::Completed);
+            }
+            assert!(completed, "{}", name);
+        }
+
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let token = CancellationToken::new();
+        token.cancel();
+        let err = extract_archive(&dir.path().join("os.zip"), &dir.path().join("x"), tx, token).await.unwrap_err();
+        assert!(err.ends_with("cancelled"));
+    }
+
+    #[tokio::test]
+    async fn test_extract_7z_native() {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source");
+        std::fs::create_dir_all(source.join("spruce")).unwrap();
+        std::fs::write(source.join("spruce").join("app.txt"), vec![b'x'; 3 * 1024 * 1024]).unwrap();
+        std::fs::write(source.join("empty.txt"), b"").unwrap();
+        let archive = dir.path().join("test.7z");
+        sevenz_rust::compress_to_path(&source, &archive).unwrap();
+
+        let dest = dir.path().join("dest");
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        assert!(extract_7z_with_progress(&archive, &dest, tx, CancellationToken::new()).await.is_ok());
+        assert_eq!(std::fs::read(dest.join("spruce").join("app.txt")).unwrap().len(), 3 * 1024 * 1024);
+        assert!(dest.join("empty.txt").exists());
+
+        let mut saw_bytes = false;
+        while let Ok(progress) = rx.try_recv() {
+            saw_bytes |= matches!(progress, ExtractProgress::Writing { extracted, .. } if extracted > 0);
+        }
+        assert!(saw_bytes);
+
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let token = CancellationToken::new();
+        token.cancel();
+        let err = extract_7z_with_progress(&archive, &dest, tx, token).await.unwrap_err();
+        assert!(err.ends_with("cancelled"));
+    }
 }
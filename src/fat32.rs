@@ -2,37 +2,217 @@
 // Licensed under GPL-3.0-or-later
 
 // Custom FAT32 formatter that works for drives > 32GB
-// Windows artificially limits FAT32 to 32GB, but the filesystem supports up to 2TB
-// This writes directly to the physical disk to avoid issues with unmounted volumes
+// Windows artificially limits FAT32 to 32GB, but the filesystem supports up to 2TB.
+// The formatter writes the volume structures itself (boot sectors, FSInfo, FATs and
+// root directory) to anything seekable: a physical disk on Windows, a block device on
+// Linux (no parted/mkfs.vfat needed) or an image file in tests.
 
+use crate::config::{Filesystem, PartitionSpec, RepoOption};
+use std::io::{Seek, SeekFrom, Write};
+use tokio_util::sync::CancellationToken;
+
+#[cfg(windows)]
 use tokio::sync::mpsc;
 
+#[cfg(windows)]
 use crate::format::FormatProgress;
 
-#[cfg(windows)]
-const SECTOR_SIZE: u32 = 512;
-#[cfg(windows)]
+pub const SECTOR_SIZE: u32 = 512;
 const RESERVED_SECTORS: u16 = 32;
-#[cfg(windows)]
 const NUM_FATS: u8 = 2;
-#[cfg(windows)]
-const PARTITION_START_SECTOR: u64 = 2048; // Standard 1MB alignment
+pub const PARTITION_START_SECTOR: u64 = 2048; // Standard 1MB alignment
+/// Partition and data area alignment unless a repository sets partition_alignment
+pub const DEFAULT_ALIGNMENT: u32 = PARTITION_START_SECTOR as u32 * SECTOR_SIZE;
+/// Largest alignment; the padded reserved area must still fit its 16-bit sector count
+const MAX_ALIGNMENT: u32 = 16 * 1024 * 1024;
+/// Largest FAT32 cluster size most systems can read
+const MAX_CLUSTER_SIZE: u32 = 64 * 1024;
+/// Writes are aligned to and sized in multiples of this, as unbuffered device I/O requires
+const IO_ALIGNMENT: u64 = 4096;
+const IO_CHUNK_SIZE: usize = 1024 * 1024;
+/// MBR partition type of FAT32 addressed by LBA
+pub const PARTITION_TYPE_FAT32_LBA: u8 = 0x0C;
+/// Cluster count limits of FAT32 (fewer clusters is FAT16 by definition)
+const MIN_CLUSTERS: u64 = 65_525;
+const MAX_CLUSTERS: u64 = 0x0FFF_FFF5;
+
+/// How format_device lays out a card (from the RepoOption fields of the same names)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FormatOptions {
+    pub filesystem: Filesystem,
+    /// Bytes per cluster; None picks it from the volume size
+    pub cluster_size: Option<u32>,
+    /// The partition and the volume's data area start on multiples of this many bytes
+    pub alignment: u32,
+    /// Partitions in disk order; empty gives one `filesystem` partition over the whole device
+    pub partitions: &'static [PartitionSpec],
+    /// Label of the partition installs go to (None = the first formatted one)
+    pub install_partition: Option<&'static str>,
+}
+
+impl Default for FormatOptions {
+    fn default() -> Self {
+        Self {
+            filesystem: Filesystem::Fat32,
+            cluster_size: None,
+            alignment: DEFAULT_ALIGNMENT,
+            partitions: &[],
+            install_partition: None,
+        }
+    }
+}
+
+impl FormatOptions {
+    pub fn for_repo(repo: &RepoOption) -> Self {
+        Self {
+            filesystem: repo.filesystem,
+            cluster_size: repo.cluster_size,
+            alignment: repo.partition_alignment.unwrap_or(DEFAULT_ALIGNMENT),
+            partitions: repo.partitions,
+            install_partition: repo.install_partition,
+        }
+    }
+
+    /// Whether the layout differs from what diskutil creates (macOS then formats with fat32.rs)
+    #[cfg(target_os = "macos")]
+    pub fn is_custom_layout(&self) -> bool {
+        self.cluster_size.is_some() || self.alignment != DEFAULT_ALIGNMENT || !self.partitions.is_empty()
+    }
+
+    /// Alignment in sectors, once it is checked to be usable
+    pub(crate) fn alignment_sectors(&self) -> Result<u64, String> {
+        if !self.alignment.is_power_of_two() || (self.alignment as u64) < IO_ALIGNMENT || self.alignment > MAX_ALIGNMENT {
+            return Err(format!(
+                "Partition alignment must be a power of two between 4 KB and 16 MB ({} bytes requested)",
+                self.alignment
+            ));
+        }
+        Ok((self.alignment / SECTOR_SIZE) as u64)
+    }
+
+    /// The partition installs go to: its number in the partition table (1-4), and its spec
+    /// when there is a layout (without one it is the single partition, number 1)
+    pub fn install_target(&self) -> Result<(u32, Option<&'static PartitionSpec>), String> {
+        if self.partitions.is_empty() {
+            return Ok((1, None));
+        }
+        let mut number = 0;
+        for spec in self.partitions {
+            if spec.filesystem.is_some() || spec.partition_type.is_some() {
+                number += 1;
+            }
+            let wanted = match self.install_partition {
+                Some(label) => spec.label == label,
+                None => spec.filesystem.is_some(),
+            };
+            if wanted {
+                if spec.filesystem.is_none() {
+                    return Err(format!("Install partition '{}' has no filesystem", spec.label));
+                }
+                return Ok((number, Some(spec)));
+            }
+        }
+        Err(match self.install_partition {
+            Some(label) => format!("Partition layout has no partition labelled '{}'", label),
+            None => "Partition layout has no partition with a filesystem".to_string(),
+        })
+    }
+}
+
+/// A partition of the layout as placed on a particular device
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PlannedPartition<'a> {
+    start_sector: u64,
+    sectors: u64,
+    /// None for a raw region kept out of the partition table
+    partition_type: Option<u8>,
+    filesystem: Option<Filesystem>,
+    label: &'a str,
+}
+
+/// Place the partitions of `options` on a device of `device_sectors`, each starting on the alignment
+/// Without a layout this is one partition labelled `volume_label` from the first aligned sector to the end.
+fn plan_partitions<'a>(device_sectors: u64, volume_label: &'a str, options: &FormatOptions) -> Result<Vec<PlannedPartition<'a>>, String> {
+    let align = options.alignment_sectors()?;
+    let default_layout = [PartitionSpec {
+        label: "",
+        size: None,
+        partition_type: None,
+        filesystem: Some(options.filesystem),
+    }];
+    let specs: &[PartitionSpec] = if options.partitions.is_empty() { &default_layout } else { options.partitions };
+    options.install_target()?;
+
+    let mut planned = Vec::with_capacity(specs.len());
+    let mut next_sector = align;
+    for (i, spec) in specs.iter().enumerate() {
+        let sectors = match spec.size {
+            Some(bytes) if bytes > 0 => bytes.div_ceil(SECTOR_SIZE as u64).next_multiple_of(align),
+            Some(_) => return Err(format!("Partition '{}' has a size of 0", spec.label)),
+            None if i + 1 == specs.len() => device_sectors.saturating_sub(next_sector),
+            None => return Err(format!("Only the last partition can take the rest of the card, not '{}'", spec.label)),
+        };
+        if sectors == 0 || next_sector + sectors > device_sectors {
+            return Err(format!(
+                "Partition layout needs more than the {} bytes of the card (at '{}')",
+                device_sectors * SECTOR_SIZE as u64,
+                spec.label
+            ));
+        }
+        planned.push(PlannedPartition {
+            start_sector: next_sector,
+            sectors,
+            partition_type: spec.partition_type.or(match spec.filesystem {
+                Some(Filesystem::Fat32) => Some(PARTITION_TYPE_FAT32_LBA),
+                Some(Filesystem::ExFat) => Some(crate::exfat::PARTITION_TYPE_EXFAT),
+                None => None,
+            }),
+            filesystem: spec.filesystem,
+            label: if options.partitions.is_empty() { volume_label } else { spec.label },
+        });
+        next_sector += sectors;
+    }
+    Ok(planned)
+}
 
-#[cfg(windows)]
 #[derive(Debug)]
 struct Fat32Params {
     sectors_per_cluster: u8,
-    total_sectors: u64,
+    /// Boot sectors plus the padding that aligns the data area
+    reserved_sectors: u16,
+    total_sectors: u32,
+    /// Sectors before the volume (its partition's start sector)
+    hidden_sectors: u32,
     fat_size_sectors: u32,
     root_cluster: u32,
+    cluster_count: u32,
 }
 
-#[cfg(windows)]
-fn calculate_params(total_bytes: u64) -> Fat32Params {
-    let total_sectors = total_bytes / SECTOR_SIZE as u64;
+impl Fat32Params {
+    fn fat_start_sector(&self) -> u64 {
+        self.reserved_sectors as u64
+    }
 
-    // Choose cluster size based on volume size (Microsoft recommendations)
-    let sectors_per_cluster: u8 = if total_bytes <= 64 * 1024 * 1024 {
+    fn data_start_sector(&self) -> u64 {
+        self.fat_start_sector() + NUM_FATS as u64 * self.fat_size_sectors as u64
+    }
+}
+
+fn calculate_params(total_sectors: u64, hidden_sectors: u64, options: &FormatOptions) -> Result<Fat32Params, String> {
+    let total_bytes = total_sectors * SECTOR_SIZE as u64;
+    let total_32 = u32::try_from(total_sectors)
+        .map_err(|_| format!("Volume of {} bytes is too large for FAT32 (2 TB maximum)", total_bytes))?;
+    let hidden_32 = u32::try_from(hidden_sectors)
+        .map_err(|_| format!("Volume starting at sector {} is beyond what FAT32 can address (2 TB maximum)", hidden_sectors))?;
+    let align = options.alignment_sectors()?;
+
+    // Choose cluster size based on volume size (Microsoft recommendations) unless the repository sets one
+    let mut sectors_per_cluster: u8 = if let Some(size) = options.cluster_size {
+        if !size.is_power_of_two() || !(SECTOR_SIZE..=MAX_CLUSTER_SIZE).contains(&size) {
+            return Err(format!("FAT32 cluster size must be a power of two between 512 bytes and 64 KB ({} bytes requested)", size));
+        }
+        (size / SECTOR_SIZE) as u8
+    } else if total_bytes <= 64 * 1024 * 1024 {
         1 // 512 bytes - up to 64MB
     } else if total_bytes <= 128 * 1024 * 1024 {
         2 // 1KB - up to 128MB
@@ -48,20 +228,61 @@ fn calculate_params(total_bytes: u64) -> Fat32Params {
         64 // 32KB - above 32GB
     };
 
-    // Calculate FAT size
-    let data_sectors = total_sectors.saturating_sub(RESERVED_SECTORS as u64);
-    let cluster_count = data_sectors / sectors_per_cluster as u64;
-    let fat_size_sectors = ((cluster_count + 2) * 4 + SECTOR_SIZE as u64 - 1) / SECTOR_SIZE as u64;
+    loop {
+        // FAT size from the FAT specification, which accounts for the FATs taking
+        // space from the data area; rounded up so everything after it stays 4K aligned
+        let spc = sectors_per_cluster as u64;
+        let fat_divisor = (256 * spc + NUM_FATS as u64) / 2;
+        let fat_size = total_sectors.saturating_sub(RESERVED_SECTORS as u64).div_ceil(fat_divisor);
+        let io_sectors = IO_ALIGNMENT / SECTOR_SIZE as u64;
+        let fat_size_sectors = fat_size.div_ceil(io_sectors) * io_sectors;
+
+        // Pad the reserved area so the data area starts aligned on the card (counting the sectors before the volume)
+        let data_start = hidden_sectors + RESERVED_SECTORS as u64 + NUM_FATS as u64 * fat_size_sectors;
+        let reserved_sectors = RESERVED_SECTORS as u64 + (data_start.next_multiple_of(align) - data_start);
+
+        let data_sectors = total_sectors
+            .saturating_sub(reserved_sectors + NUM_FATS as u64 * fat_size_sectors);
+        let cluster_count = data_sectors / spc;
+
+        // Just above a size boundary the alignment padding can leave too few clusters
+        // for FAT32; smaller clusters fix that unless the repository asked for a size
+        if cluster_count < MIN_CLUSTERS && options.cluster_size.is_none() && sectors_per_cluster > 1 {
+            sectors_per_cluster /= 2;
+            continue;
+        }
+        if !(MIN_CLUSTERS..=MAX_CLUSTERS).contains(&cluster_count) {
+            return Err(format!(
+                "{} byte clusters give {} clusters on a volume of {} bytes; FAT32 needs between {} and {}",
+                spc * SECTOR_SIZE as u64, cluster_count, total_bytes, MIN_CLUSTERS, MAX_CLUSTERS
+            ));
+        }
 
-    Fat32Params {
-        sectors_per_cluster,
-        total_sectors,
-        fat_size_sectors: fat_size_sectors as u32,
-        root_cluster: 2,
+        return Ok(Fat32Params {
+            sectors_per_cluster,
+            reserved_sectors: reserved_sectors as u16,
+            total_sectors: total_32,
+            hidden_sectors: hidden_32,
+            fat_size_sectors: fat_size_sectors as u32,
+            root_cluster: 2,
+            cluster_count: cluster_count as u32,
+        });
     }
 }
 
-#[cfg(windows)]
+/// Volume label as stored on disk: uppercase, 11 bytes, space-padded
+fn label_bytes(volume_label: &str) -> [u8; 11] {
+    let mut label_bytes = [0x20u8; 11];
+    let label_src: Vec<u8> = volume_label
+        .bytes()
+        .map(|b| b.to_ascii_uppercase())
+        .filter(|b| b.is_ascii_graphic() || *b == b' ')
+        .collect();
+    let copy_len = label_src.len().min(11);
+    label_bytes[..copy_len].copy_from_slice(&label_src[..copy_len]);
+    label_bytes
+}
+
 fn create_boot_sector(params: &Fat32Params, volume_label: &str) -> [u8; 512] {
     let mut boot = [0u8; 512];
 
@@ -81,8 +302,7 @@ fn create_boot_sector(params: &Fat32Params, volume_label: &str) -> [u8; 512] {
     boot[13] = params.sectors_per_cluster;
 
     // Reserved sectors
-    boot[14] = (RESERVED_SECTORS & 0xFF) as u8;
-    boot[15] = ((RESERVED_SECTORS >> 8) & 0xFF) as u8;
+    boot[14..16].copy_from_slice(&params.reserved_sectors.to_le_bytes());
 
     // Number of FATs
     boot[16] = NUM_FATS;
@@ -111,15 +331,10 @@ fn create_boot_sector(params: &Fat32Params, volume_label: &str) -> [u8; 512] {
     boot[27] = 0;
 
     // Hidden sectors (sectors before partition = partition start)
-    boot[28..32].copy_from_slice(&(PARTITION_START_SECTOR as u32).to_le_bytes());
+    boot[28..32].copy_from_slice(&params.hidden_sectors.to_le_bytes());
 
     // Total sectors 32-bit
-    let total_32 = if params.total_sectors > u32::MAX as u64 {
-        u32::MAX
-    } else {
-        params.total_sectors as u32
-    };
-    boot[32..36].copy_from_slice(&total_32.to_le_bytes());
+    boot[32..36].copy_from_slice(&params.total_sectors.to_le_bytes());
 
     // FAT32 specific fields
     // FAT size 32-bit
@@ -153,19 +368,16 @@ fn create_boot_sector(params: &Fat32Params, volume_label: &str) -> [u8; 512] {
     // Extended boot signature
     boot[66] = 0x29;
 
-    // Volume serial number
+    // Volume serial number (mixed with the partition start, so the volumes of one card differ)
     let serial: u32 = std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .map(|d| d.as_secs() as u32)
-        .unwrap_or(0x12345678);
+        .unwrap_or(0x12345678)
+        ^ params.hidden_sectors;
     boot[67..71].copy_from_slice(&serial.to_le_bytes());
 
     // Volume label (11 bytes, space-padded)
-    let mut label_bytes = [0x20u8; 11];
-    let label_src = volume_label.as_bytes();
-    let copy_len = label_src.len().min(11);
-    label_bytes[..copy_len].copy_from_slice(&label_src[..copy_len]);
-    boot[71..82].copy_from_slice(&label_bytes);
+    boot[71..82].copy_from_slice(&label_bytes(volume_label));
 
     // File system type
     boot[82..90].copy_from_slice(b"FAT32   ");
@@ -177,8 +389,7 @@ fn create_boot_sector(params: &Fat32Params, volume_label: &str) -> [u8; 512] {
     boot
 }
 
-#[cfg(windows)]
-fn create_fsinfo_sector() -> [u8; 512] {
+fn create_fsinfo_sector(params: &Fat32Params) -> [u8; 512] {
     let mut fsinfo = [0u8; 512];
 
     // FSInfo signature
@@ -187,11 +398,11 @@ fn create_fsinfo_sector() -> [u8; 512] {
     // Second signature
     fsinfo[484..488].copy_from_slice(&0x61417272u32.to_le_bytes());
 
-    // Free cluster count (unknown)
-    fsinfo[488..492].copy_from_slice(&0xFFFFFFFFu32.to_le_bytes());
+    // Free cluster count (all but the root directory)
+    fsinfo[488..492].copy_from_slice(&(params.cluster_count - 1).to_le_bytes());
 
-    // Next free cluster (unknown)
-    fsinfo[492..496].copy_from_slice(&0xFFFFFFFFu32.to_le_bytes());
+    // Next free cluster (the one after the root directory)
+    fsinfo[492..496].copy_from_slice(&(params.root_cluster + 1).to_le_bytes());
 
     // Trail signature
     fsinfo[508..512].copy_from_slice(&0xAA550000u32.to_le_bytes());
@@ -199,7 +410,6 @@ fn create_fsinfo_sector() -> [u8; 512] {
     fsinfo
 }
 
-#[cfg(windows)]
 fn create_fat_sector_with_entries() -> [u8; 512] {
     let mut fat = [0u8; 512];
 
@@ -215,151 +425,520 @@ fn create_fat_sector_with_entries() -> [u8; 512] {
     fat
 }
 
-/// Format using physical disk access (works even when volume isn't mounted)
-#[cfg(windows)]
-pub async fn format_fat32_large(
-    disk_number: u32,
-    volume_label: &str,
-    total_bytes: u64,
-    progress_tx: mpsc::UnboundedSender<FormatProgress>,
-) -> Result<(), String> {
-    use windows::Win32::Foundation::{HANDLE, CloseHandle, GENERIC_READ, GENERIC_WRITE};
-    use windows::Win32::Storage::FileSystem::{
-        CreateFileW, SetFilePointerEx, WriteFile, FILE_BEGIN,
-        FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING,
-        FILE_FLAG_NO_BUFFERING, FILE_FLAG_WRITE_THROUGH,
-    };
-    use windows::core::PCWSTR;
-
-    let _ = progress_tx.send(FormatProgress::Formatting);
+/// Writes through a buffer aligned for unbuffered device I/O
+pub(crate) struct AlignedWriter<'a, D: Write + Seek> {
+    device: &'a mut D,
+    storage: Vec<u8>,
+    start: usize,
+}
 
-    // Calculate partition size (total disk minus the 1MB alignment at start)
-    let partition_size = total_bytes.saturating_sub(PARTITION_START_SECTOR * SECTOR_SIZE as u64);
-    let params = calculate_params(partition_size);
+impl<'a, D: Write + Seek> AlignedWriter<'a, D> {
+    pub(crate) fn new(device: &'a mut D) -> Self {
+        let storage = vec![0u8; IO_CHUNK_SIZE + IO_ALIGNMENT as usize];
+        let start = storage.as_ptr().align_offset(IO_ALIGNMENT as usize);
+        Self { device, storage, start }
+    }
 
-    // Open the physical disk for raw access with proper flags
-    let disk_path: Vec<u16> = format!("\\\\.\\PhysicalDrive{}", disk_number)
-        .encode_utf16()
-        .chain(Some(0))
-        .collect();
+    /// Write `data` at `offset`; both must be multiples of IO_ALIGNMENT
+    pub(crate) fn write_at(&mut self, offset: u64, data: &[u8]) -> Result<(), String> {
+        self.device
+            .seek(SeekFrom::Start(offset))
+            .map_err(|e| format!("Seek failed: {}", e))?;
+        for chunk in data.chunks(IO_CHUNK_SIZE) {
+            let buffer = &mut self.storage[self.start..self.start + chunk.len()];
+            buffer.copy_from_slice(chunk);
+            self.device
+                .write_all(buffer)
+                .map_err(|e| format!("Write failed at offset {}: {}", offset, e))?;
+        }
+        Ok(())
+    }
 
-    let handle = unsafe {
-        CreateFileW(
-            PCWSTR(disk_path.as_ptr()),
-            (GENERIC_READ.0 | GENERIC_WRITE.0).into(),
-            FILE_SHARE_READ | FILE_SHARE_WRITE,
-            None,
-            OPEN_EXISTING,
-            FILE_FLAG_NO_BUFFERING | FILE_FLAG_WRITE_THROUGH,
-            None,
-        )
-    }.map_err(|e| format!("Failed to open disk {}: {}", disk_number, e))?;
-
-    // Helper to write a sector at a specific position
-    let write_sector = |h: HANDLE, offset: u64, data: &[u8; 512]| -> Result<(), String> {
-        let mut new_pos = 0i64;
-        unsafe {
-            SetFilePointerEx(h, offset as i64, Some(&mut new_pos), FILE_BEGIN)
-                .map_err(|e| format!("Seek failed: {}", e))?;
-
-            let mut written = 0u32;
-            WriteFile(h, Some(data), Some(&mut written), None)
-                .map_err(|e| format!("Write failed: {}", e))?;
-
-            if written != 512 {
-                return Err(format!("Incomplete write: {} bytes", written));
+    /// Zero `len` bytes at `offset`, reporting the bytes done so far after each chunk
+    pub(crate) fn zero(
+        &mut self,
+        offset: u64,
+        len: u64,
+        cancel_token: &CancellationToken,
+        mut on_chunk: impl FnMut(u64),
+    ) -> Result<(), String> {
+        self.device
+            .seek(SeekFrom::Start(offset))
+            .map_err(|e| format!("Seek failed: {}", e))?;
+        self.storage.fill(0);
+        let mut done = 0u64;
+        while done < len {
+            if cancel_token.is_cancelled() {
+                return Err("Format cancelled".to_string());
             }
+            let n = (len - done).min(IO_CHUNK_SIZE as u64) as usize;
+            self.device
+                .write_all(&self.storage[self.start..self.start + n])
+                .map_err(|e| format!("Write failed at offset {}: {}", offset + done, e))?;
+            done += n as u64;
+            on_chunk(done);
         }
         Ok(())
-    };
+    }
+}
+
+/// Write an empty FAT32 volume of `total_sectors` starting at `start_sector` of `device`
+/// The partition table is left alone. `on_progress` receives the percentage written.
+pub fn format_volume<D: Write + Seek>(
+    device: &mut D,
+    start_sector: u64,
+    total_sectors: u64,
+    volume_label: &str,
+    options: &FormatOptions,
+    cancel_token: &CancellationToken,
+    mut on_progress: impl FnMut(u8),
+) -> Result<(), String> {
+    let params = calculate_params(total_sectors, start_sector, options)?;
+    crate::debug::log(&format!("FAT32 parameters: {:?}", params));
 
-    // Calculate the byte offset where the partition starts
-    let partition_offset = PARTITION_START_SECTOR * SECTOR_SIZE as u64;
+    let sector = SECTOR_SIZE as u64;
+    let volume_offset = start_sector * sector;
+    let mut writer = AlignedWriter::new(device);
 
-    // Write boot sector at partition start
+    // Reserved area: boot sector, FSInfo, their backups at sectors 6 and 7, zeros elsewhere
     let boot_sector = create_boot_sector(&params, volume_label);
-    write_sector(handle, partition_offset, &boot_sector)
+    let fsinfo = create_fsinfo_sector(&params);
+    let mut reserved = vec![0u8; params.reserved_sectors as usize * SECTOR_SIZE as usize];
+    reserved[0..512].copy_from_slice(&boot_sector);
+    reserved[512..1024].copy_from_slice(&fsinfo);
+    reserved[6 * 512..7 * 512].copy_from_slice(&boot_sector);
+    reserved[7 * 512..8 * 512].copy_from_slice(&fsinfo);
+    writer.write_at(volume_offset, &reserved)
         .map_err(|e| format!("Failed to write boot sector: {}", e))?;
 
-    // Write FSInfo sector
-    let fsinfo = create_fsinfo_sector();
-    write_sector(handle, partition_offset + SECTOR_SIZE as u64, &fsinfo)
-        .map_err(|e| format!("Failed to write FSInfo: {}", e))?;
+    // Both FATs are cleared entirely, so no stale entries from an earlier filesystem survive
+    let fat_bytes = params.fat_size_sectors as u64 * sector;
+    let root_bytes = (params.sectors_per_cluster as u64 * sector).max(IO_ALIGNMENT);
+    let total_bytes = NUM_FATS as u64 * fat_bytes + root_bytes;
+    let mut report = |done: u64| on_progress((done * 100 / total_bytes) as u8);
 
-    // Write backup boot sector at sector 6
-    write_sector(handle, partition_offset + 6 * SECTOR_SIZE as u64, &boot_sector)
-        .map_err(|e| format!("Failed to write backup boot sector: {}", e))?;
+    let fat_start = volume_offset + params.fat_start_sector() * sector;
+    for i in 0..NUM_FATS as u64 {
+        writer.zero(fat_start + i * fat_bytes, fat_bytes, cancel_token, |done| report(i * fat_bytes + done))
+            .map_err(|e| format!("Failed to clear FAT{}: {}", i + 1, e))?;
+    }
 
-    // Write backup FSInfo at sector 7
-    write_sector(handle, partition_offset + 7 * SECTOR_SIZE as u64, &fsinfo)
-        .map_err(|e| format!("Failed to write backup FSInfo: {}", e))?;
+    // Root directory cluster, holding only the volume label
+    let data_start = volume_offset + params.data_start_sector() * sector;
+    writer.zero(data_start, root_bytes, cancel_token, |_| {})
+        .map_err(|e| format!("Failed to clear root directory: {}", e))?;
+    let mut root_sector = [0u8; IO_ALIGNMENT as usize];
+    root_sector[0..11].copy_from_slice(&label_bytes(volume_label));
+    root_sector[11] = 0x08; // Volume label attribute
+    writer.write_at(data_start, &root_sector)
+        .map_err(|e| format!("Failed to write root directory: {}", e))?;
 
-    // Write FAT tables
-    let fat_start = partition_offset + RESERVED_SECTORS as u64 * SECTOR_SIZE as u64;
-    let fat_first_sector = create_fat_sector_with_entries();
-    let zero_sector = [0u8; 512];
+    // First FAT sectors with the reserved entries and the root directory chain (written last,
+    // so an interrupted format never looks like a valid filesystem)
+    let mut fat_first = [0u8; IO_ALIGNMENT as usize];
+    fat_first[..512].copy_from_slice(&create_fat_sector_with_entries());
+    for i in 0..NUM_FATS as u64 {
+        writer.write_at(fat_start + i * fat_bytes, &fat_first)
+            .map_err(|e| format!("Failed to write FAT{}: {}", i + 1, e))?;
+    }
 
-    // First FAT - first sector with entries
-    write_sector(handle, fat_start, &fat_first_sector)
-        .map_err(|e| format!("Failed to write FAT1: {}", e))?;
+    writer.device.flush().map_err(|e| format!("Failed to flush device: {}", e))?;
+    on_progress(100);
+    Ok(())
+}
+
+/// Primary partition entry of an MBR partition table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MbrPartition {
+    /// Boot flag (0x80); some handheld bootloaders only look at the active partition
+    pub bootable: bool,
+    pub partition_type: u8,
+    pub start_sector: u32,
+    pub sector_count: u32,
+}
 
-    // Clear a few more sectors of FAT1
-    for i in 1..16.min(params.fat_size_sectors) {
-        write_sector(handle, fat_start + i as u64 * SECTOR_SIZE as u64, &zero_sector)
-            .map_err(|e| format!("Failed to clear FAT1: {}", e))?;
+/// Build a classic MBR with up to four primary partitions (no boot code)
+pub fn create_mbr(partitions: &[MbrPartition], disk_signature: u32) -> Result<[u8; 512], String> {
+    if partitions.len() > 4 {
+        return Err(format!("An MBR holds at most 4 primary partitions ({} requested)", partitions.len()));
+    }
+    let mut sorted: Vec<&MbrPartition> = partitions.iter().collect();
+    sorted.sort_by_key(|p| p.start_sector);
+    for pair in sorted.windows(2) {
+        if pair[0].start_sector as u64 + pair[0].sector_count as u64 > pair[1].start_sector as u64 {
+            return Err("MBR partitions overlap".to_string());
+        }
     }
 
-    // Second FAT
-    let fat2_start = fat_start + params.fat_size_sectors as u64 * SECTOR_SIZE as u64;
-    write_sector(handle, fat2_start, &fat_first_sector)
-        .map_err(|e| format!("Failed to write FAT2: {}", e))?;
+    let mut mbr = [0u8; 512];
+
+    // Disk signature (Windows identifies disks by it), then two reserved bytes
+    mbr[440..444].copy_from_slice(&disk_signature.to_le_bytes());
 
-    // Clear a few more sectors of FAT2
-    for i in 1..16.min(params.fat_size_sectors) {
-        write_sector(handle, fat2_start + i as u64 * SECTOR_SIZE as u64, &zero_sector)
-            .map_err(|e| format!("Failed to clear FAT2: {}", e))?;
+    for (i, partition) in partitions.iter().enumerate() {
+        if partition.start_sector == 0 || partition.sector_count == 0 {
+            return Err(format!("Partition {} is empty or overlaps the MBR", i + 1));
+        }
+        let last_sector = partition.start_sector + (partition.sector_count - 1);
+        let entry = &mut mbr[446 + i * 16..462 + i * 16];
+        entry[0] = if partition.bootable { 0x80 } else { 0x00 };
+        entry[1..4].copy_from_slice(&chs_address(partition.start_sector));
+        entry[4] = partition.partition_type;
+        entry[5..8].copy_from_slice(&chs_address(last_sector));
+        entry[8..12].copy_from_slice(&partition.start_sector.to_le_bytes());
+        entry[12..16].copy_from_slice(&partition.sector_count.to_le_bytes());
     }
 
-    // Write root directory cluster
-    let data_start = fat_start + (NUM_FATS as u64 * params.fat_size_sectors as u64 * SECTOR_SIZE as u64);
+    // Boot signature
+    mbr[510] = 0x55;
+    mbr[511] = 0xAA;
 
-    // Create root directory sector with volume label
-    let mut root_sector = [0u8; 512];
-    let mut label_bytes = [0x20u8; 11];
-    let label_src = volume_label.as_bytes();
-    let copy_len = label_src.len().min(11);
-    label_bytes[..copy_len].copy_from_slice(&label_src[..copy_len]);
-    root_sector[0..11].copy_from_slice(&label_bytes);
-    root_sector[11] = 0x08; // Volume label attribute
+    Ok(mbr)
+}
 
-    write_sector(handle, data_start, &root_sector)
-        .map_err(|e| format!("Failed to write root directory: {}", e))?;
+/// CHS address of a sector with the usual 255 heads / 63 sectors geometry
+/// (0xFE 0xFF 0xFF beyond cylinder 1023, where only the LBA fields count)
+fn chs_address(lba: u32) -> [u8; 3] {
+    const HEADS: u32 = 255;
+    const SECTORS_PER_TRACK: u32 = 63;
 
-    // Clear rest of root directory cluster
-    let sectors_per_cluster = params.sectors_per_cluster as u32;
-    for i in 1..sectors_per_cluster {
-        write_sector(handle, data_start + i as u64 * SECTOR_SIZE as u64, &zero_sector)
-            .map_err(|e| format!("Failed to clear root directory: {}", e))?;
+    let cylinder = lba / (HEADS * SECTORS_PER_TRACK);
+    if cylinder > 1023 {
+        return [0xFE, 0xFF, 0xFF];
     }
+    let head = (lba / SECTORS_PER_TRACK) % HEADS;
+    let sector = lba % SECTORS_PER_TRACK + 1;
+    [head as u8, (sector as u8) | ((cylinder >> 2) as u8 & 0xC0), cylinder as u8]
+}
 
-    // Close handle
-    unsafe { let _ = CloseHandle(handle); }
+/// Fresh disk signature, so two cards flashed in a row don't clash on Windows
+fn new_disk_signature() -> u32 {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0x5EED);
+    let mut state = (nanos ^ ((std::process::id() as u64) << 32)) | 1;
+    crate::capacity::xorshift64(&mut state) as u32
+}
 
-    let _ = progress_tx.send(FormatProgress::Completed);
-    Ok(())
+/// Partition a whole device and format its volumes: by default one bootable FAT32 (or exFAT)
+/// partition from the first aligned sector (PARTITION_START_SECTOR by default) to the end,
+/// otherwise the partitions of `options.partitions` (the first formatted one is bootable)
+/// The space before the first partition is cleared, which also removes any old GPT header, and so
+/// is the end of the device, where a GPT keeps its backup header.
+pub fn format_device<D: Write + Seek>(
+    device: &mut D,
+    device_bytes: u64,
+    options: &FormatOptions,
+    volume_label: &str,
+    cancel_token: &CancellationToken,
+    mut on_progress: impl FnMut(u8),
+) -> Result<(), String> {
+    let planned = plan_partitions(device_bytes / SECTOR_SIZE as u64, volume_label, options)?;
+    let first_sector = planned[0].start_sector;
+    let bootable = planned.iter().position(|p| p.filesystem.is_some());
+    let entries = planned
+        .iter()
+        .enumerate()
+        .filter_map(|(i, p)| p.partition_type.map(|partition_type| (i, p, partition_type)))
+        .map(|(i, p, partition_type)| {
+            let too_large = |_| format!("Device of {} bytes is too large for an MBR partition table (2 TB maximum)", device_bytes);
+            Ok(MbrPartition {
+                bootable: Some(i) == bootable,
+                partition_type,
+                start_sector: u32::try_from(p.start_sector).map_err(too_large)?,
+                sector_count: u32::try_from(p.sectors).map_err(too_large)?,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let mbr = create_mbr(&entries, new_disk_signature())?;
+    let mut head = vec![0u8; (first_sector * SECTOR_SIZE as u64) as usize];
+    head[..512].copy_from_slice(&mbr);
+
+    // The volumes first: the partition table makes them visible only once they are complete.
+    // Progress is shared out by partition size, which is roughly what their FATs take to clear.
+    let formatted_sectors: u64 = planned.iter().filter(|p| p.filesystem.is_some()).map(|p| p.sectors).sum();
+    let mut done_sectors = 0u64;
+    for partition in &planned {
+        let mut report = |percent: u8| {
+            let done = done_sectors + partition.sectors * percent as u64 / 100;
+            on_progress((done * 100 / formatted_sectors.max(1)) as u8);
+        };
+        let (start, sectors, label) = (partition.start_sector, partition.sectors, partition.label);
+        match partition.filesystem {
+            Some(Filesystem::Fat32) => format_volume(device, start, sectors, label, options, cancel_token, &mut report)?,
+            Some(Filesystem::ExFat) => crate::exfat::format_volume(device, start, sectors, label, options, cancel_token, &mut report)?,
+            None => {
+                // Raw regions are left for the bootloader, minus any signature of an earlier filesystem
+                let clear = (sectors * SECTOR_SIZE as u64).min(IO_CHUNK_SIZE as u64) / IO_ALIGNMENT * IO_ALIGNMENT;
+                AlignedWriter::new(&mut *device)
+                    .zero(start * SECTOR_SIZE as u64, clear, cancel_token, |_| {})
+                    .map_err(|e| format!("Failed to clear partition '{}': {}", label, e))?;
+                continue;
+            }
+        }
+        done_sectors += sectors;
+    }
+
+    // Backup GPT header lives in the last sector; these clusters are still unused
+    let tail_start = (device_bytes.saturating_sub(32 * 1024) / IO_ALIGNMENT) * IO_ALIGNMENT;
+    let mut writer = AlignedWriter::new(device);
+    if tail_start >= first_sector * SECTOR_SIZE as u64 {
+        writer.zero(tail_start, (device_bytes - tail_start) / IO_ALIGNMENT * IO_ALIGNMENT, cancel_token, |_| {})
+            .map_err(|e| format!("Failed to clear old GPT backup: {}", e))?;
+    }
+    writer.write_at(0, &head)
+        .map_err(|e| format!("Failed to write partition table: {}", e))?;
+    writer.device.flush().map_err(|e| format!("Failed to flush device: {}", e))
 }
 
-#[cfg(not(windows))]
-#[allow(dead_code)]
-pub async fn format_fat32_large(
-    _disk_number: u32,
-    _volume_label: &str,
-    _total_bytes: u64,
+/// Partition and format using physical disk access (works even when volume isn't mounted)
+/// diskpart has only cleaned the disk; the MBR comes from format_device
+#[cfg(windows)]
+pub async fn format_physical_disk(
+    disk_number: u32,
+    options: FormatOptions,
+    volume_label: &str,
+    total_bytes: u64,
     progress_tx: mpsc::UnboundedSender<FormatProgress>,
+    cancel_token: CancellationToken,
 ) -> Result<(), String> {
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::AsRawHandle;
+    use windows::Win32::Foundation::HANDLE;
+    use windows::Win32::Storage::FileSystem::{
+        FILE_FLAG_NO_BUFFERING, FILE_FLAG_WRITE_THROUGH, FILE_SHARE_READ, FILE_SHARE_WRITE,
+    };
+    use windows::Win32::System::IO::DeviceIoControl;
+    use windows::Win32::System::Ioctl::IOCTL_DISK_UPDATE_PROPERTIES;
+
     let _ = progress_tx.send(FormatProgress::Formatting);
-    tokio::time::sleep(tokio::time::Duration::from_secs(2)).await;
-    let _ = progress_tx.send(FormatProgress::Completed);
-    eprintln!("Warning: FAT32 format simulation - not running on Windows.");
-    Ok(())
+
+    // Open the physical disk for raw access with proper flags
+    let disk_path = format!("\\\\.\\PhysicalDrive{}", disk_number);
+    let volume_label = volume_label.to_string();
+
+    tokio::task::spawn_blocking(move || {
+        let mut disk = std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .share_mode(FILE_SHARE_READ.0 | FILE_SHARE_WRITE.0)
+            .custom_flags(FILE_FLAG_NO_BUFFERING.0 | FILE_FLAG_WRITE_THROUGH.0)
+            .open(&disk_path)
+            .map_err(|e| format!("Failed to open disk {}: {}", disk_number, e))?;
+
+        // The 70-95% range of the overall format progress
+        format_device(&mut disk, total_bytes, &options, &volume_label, &cancel_token, |percent| {
+            let _ = progress_tx.send(FormatProgress::Progress { percent: crate::format::scale_progress(70, 25, percent) });
+        })?;
+
+        // Make Windows pick up the new partition table
+        let handle = HANDLE(disk.as_raw_handle() as *mut std::ffi::c_void);
+        let mut bytes_returned = 0u32;
+        let _ = unsafe {
+            DeviceIoControl(handle, IOCTL_DISK_UPDATE_PROPERTIES, None, 0, None, 0, Some(&mut bytes_returned), None)
+        };
+        Ok(())
+    })
+    .await
+    .map_err(|e| format!("Format task failed: {}", e))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Seek, SeekFrom};
+
+    #[test]
+    fn test_calculate_params() {
+        // 64 GB card: 32 KB clusters, FATs 4K aligned, data area 1 MiB aligned on the card
+        let options = FormatOptions::default();
+        let sectors = 64_000_000_000u64 / 512 - PARTITION_START_SECTOR;
+        let params = calculate_params(sectors, PARTITION_START_SECTOR, &options).unwrap();
+        assert_eq!(params.sectors_per_cluster, 64);
+        assert_eq!(params.fat_size_sectors % 8, 0);
+        assert_eq!(params.reserved_sectors % 8, 0);
+        assert!(params.reserved_sectors >= RESERVED_SECTORS);
+        assert_eq!((PARTITION_START_SECTOR + params.data_start_sector()) % 2048, 0);
+        assert!(params.fat_size_sectors as u64 * 128 >= params.cluster_count as u64 + 2);
+        assert!(params.data_start_sector() + params.cluster_count as u64 * 64 <= sectors);
+
+        assert!(calculate_params(16 * 1024 * 1024 / 512, 0, &options).is_err());
+        assert!(calculate_params(3u64 << 32, 0, &options).is_err());
+        // The boot sector can't address a volume starting past 2 TB
+        assert!(calculate_params(sectors, 1 << 32, &options).is_err());
+    }
+
+    #[test]
+    fn test_custom_cluster_size_and_alignment() {
+        let sectors = 8_000_000_000u64 / 512 - 8192;
+        let options = FormatOptions { cluster_size: Some(32 * 1024), alignment: 4 * 1024 * 1024, ..Default::default() };
+        let params = calculate_params(sectors, 8192, &options).unwrap();
+        assert_eq!(params.sectors_per_cluster, 64);
+        assert_eq!((8192 + params.data_start_sector()) % 8192, 0);
+
+        // Too few clusters for FAT32, not a power of two, too large
+        let small = FormatOptions { cluster_size: Some(64 * 1024), ..Default::default() };
+        assert!(calculate_params(2_000_000_000 / 512, 2048, &small).is_err());
+        let odd = FormatOptions { cluster_size: Some(3000), ..Default::default() };
+        assert!(calculate_params(sectors, 2048, &odd).is_err());
+        let huge = FormatOptions { cluster_size: Some(128 * 1024), ..Default::default() };
+        assert!(calculate_params(sectors, 2048, &huge).is_err());
+
+        // Just above a cluster size boundary the 4 MiB padding would leave too few clusters,
+        // so a smaller size is picked when the repository doesn't set one
+        let aligned = FormatOptions { alignment: 4 * 1024 * 1024, ..Default::default() };
+        for mib in (65..=67).chain(129..=131).chain(257..=259) {
+            let params = calculate_params(mib * 1024 * 1024 / 512, 8192, &aligned).unwrap();
+            assert!(params.cluster_count as u64 >= MIN_CLUSTERS, "{} MiB", mib);
+            assert_eq!((8192 + params.data_start_sector()) % 8192, 0);
+        }
+        assert_eq!(calculate_params(66 * 1024 * 1024 / 512, 8192, &aligned).unwrap().sectors_per_cluster, 1);
+
+        let unaligned = FormatOptions { alignment: 3 * 1024 * 1024, ..Default::default() };
+        assert!(unaligned.alignment_sectors().is_err());
+    }
+
+    #[test]
+    fn test_format_device() {
+        let mut image = tempfile::tempfile().unwrap();
+        let size = 80 * 1024 * 1024;
+        image.set_len(size).unwrap();
+        format_device(&mut image, size, &FormatOptions::default(), "SPRUCEOS", &CancellationToken::new(), |_| {}).unwrap();
+
+        let mut mbr = [0u8; 512];
+        image.seek(SeekFrom::Start(0)).unwrap();
+        image.read_exact(&mut mbr).unwrap();
+        assert_eq!(&mbr[510..], &[0x55, 0xAA]);
+        assert_eq!(mbr[446], 0x80);
+        assert_eq!(mbr[450], PARTITION_TYPE_FAT32_LBA);
+        assert_ne!(&mbr[440..444], &[0, 0, 0, 0]);
+        assert_eq!(u32::from_le_bytes(mbr[454..458].try_into().unwrap()), 2048);
+        assert_eq!(u32::from_le_bytes(mbr[458..462].try_into().unwrap()) as u64, size / 512 - 2048);
+    }
+
+    // Raw bootloader region, small FAT32 boot partition, FAT32 data partition over the rest
+    const LAYOUT: &[PartitionSpec] = &[
+        PartitionSpec { label: "UBOOT", size: Some(4 * 1024 * 1024), partition_type: None, filesystem: None },
+        PartitionSpec { label: "BOOT", size: Some(100_000_000), partition_type: Some(0x0B), filesystem: Some(Filesystem::Fat32) },
+        PartitionSpec { label: "SPRUCE", size: None, partition_type: None, filesystem: Some(Filesystem::Fat32) },
+    ];
+
+    #[test]
+    fn test_plan_partitions() {
+        let options = FormatOptions { partitions: LAYOUT, install_partition: Some("SPRUCE"), ..Default::default() };
+        let planned = plan_partitions(1_000_000, "IGNORED", &options).unwrap();
+        assert_eq!(planned.len(), 3);
+        assert_eq!((planned[0].start_sector, planned[0].sectors, planned[0].partition_type), (2048, 8192, None));
+        assert_eq!((planned[1].start_sector, planned[1].partition_type), (10_240, Some(0x0B)));
+        // 100 MB round up to whole MiB
+        assert_eq!(planned[1].sectors, 196_608);
+        assert_eq!(planned[2].start_sector, 10_240 + 196_608);
+        assert_eq!(planned[2].start_sector + planned[2].sectors, 1_000_000);
+        assert_eq!(planned[2].label, "SPRUCE");
+        let (number, spec) = options.install_target().unwrap();
+        assert_eq!((number, spec.unwrap().label), (2, "SPRUCE"));
+
+        // Everything starts on the alignment; the default install partition is the first formatted one
+        let options = FormatOptions { partitions: LAYOUT, alignment: 4 * 1024 * 1024, ..Default::default() };
+        let planned = plan_partitions(1_000_000, "IGNORED", &options).unwrap();
+        assert_eq!(planned[1].start_sector, 8192 + 8192);
+        assert_eq!(planned[2].start_sector, 8192 + 8192 + 196_608);
+        assert_eq!(options.install_target().unwrap().0, 1);
+
+        let missing = FormatOptions { partitions: LAYOUT, install_partition: Some("DATA"), ..Default::default() };
+        assert!(plan_partitions(1_000_000, "", &missing).is_err());
+        let raw = FormatOptions { partitions: LAYOUT, install_partition: Some("UBOOT"), ..Default::default() };
+        assert!(raw.install_target().is_err());
+        assert!(plan_partitions(100_000, "", &FormatOptions { partitions: LAYOUT, ..Default::default() }).is_err());
+    }
+
+    #[test]
+    fn test_format_device_layout() {
+        let mut image = tempfile::tempfile().unwrap();
+        let size = 200 * 1024 * 1024;
+        image.set_len(size).unwrap();
+        let options = FormatOptions { partitions: LAYOUT, install_partition: Some("SPRUCE"), ..Default::default() };
+        let mut last = 0;
+        format_device(&mut image, size, &options, "IGNORED", &CancellationToken::new(), |p| last = p).unwrap();
+        assert_eq!(last, 100);
+
+        let mut read_sector = |sector: u64| {
+            let mut buffer = [0u8; 512];
+            image.seek(SeekFrom::Start(sector * 512)).unwrap();
+            image.read_exact(&mut buffer).unwrap();
+            buffer
+        };
+        // Two table entries; the raw region stays out of the table
+        let mbr = read_sector(0);
+        assert_eq!((mbr[446], mbr[450]), (0x80, 0x0B));
+        assert_eq!(u32::from_le_bytes(mbr[454..458].try_into().unwrap()), 10_240);
+        assert_eq!((mbr[462], mbr[466]), (0x00, PARTITION_TYPE_FAT32_LBA));
+        assert_eq!(u32::from_le_bytes(mbr[470..474].try_into().unwrap()), 10_240 + 196_608);
+        assert_eq!(mbr[482], 0);
+        assert_eq!(&read_sector(10_240)[71..82], b"BOOT       ");
+        assert_eq!(&read_sector(10_240 + 196_608)[71..82], b"SPRUCE     ");
+    }
+
+    #[test]
+    fn test_create_mbr() {
+        let boot = MbrPartition { bootable: true, partition_type: 0x0C, start_sector: 2048, sector_count: 262_144 };
+        let data = MbrPartition { bootable: false, partition_type: 0x07, start_sector: 264_192, sector_count: 1_000_000 };
+        let mbr = create_mbr(&[boot.clone(), data.clone()], 0xDEADBEEF).unwrap();
+        assert_eq!(&mbr[440..444], &0xDEADBEEFu32.to_le_bytes());
+        assert_eq!(&mbr[446..450], &[0x80, 0x20, 0x21, 0x00]);
+        assert_eq!(mbr[462], 0x00);
+        assert_eq!(mbr[466], 0x07);
+        assert_eq!(&mbr[478..510], &[0u8; 32][..]);
+
+        let overlapping = MbrPartition { start_sector: 100_000, ..data };
+        assert!(create_mbr(&[boot.clone(), overlapping], 1).is_err());
+        assert!(create_mbr(&[boot.clone(), boot.clone(), boot.clone(), boot.clone(), boot], 1).is_err());
+    }
+
+    #[test]
+    fn test_chs_address() {
+        assert_eq!(chs_address(0), [0, 1, 0]);
+        assert_eq!(chs_address(2048), [0x20, 0x21, 0x00]);
+        assert_eq!(chs_address(u32::MAX), [0xFE, 0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn test_label_bytes() {
+        assert_eq!(&label_bytes("spruce"), b"SPRUCE     ");
+        assert_eq!(&label_bytes("A-VERY-LONG-LABEL"), b"A-VERY-LONG");
+    }
+
+    #[test]
+    fn test_format_volume() {
+        let mut image = tempfile::tempfile().unwrap();
+        let sectors = 64 * 1024 * 1024 / 512;
+        image.set_len((PARTITION_START_SECTOR + sectors) * 512).unwrap();
+        // Leftovers of an earlier filesystem in the FAT area must be cleared
+        image.seek(SeekFrom::Start((PARTITION_START_SECTOR + 40) * 512)).unwrap();
+        image.write_all(&[0xAB; 512]).unwrap();
+
+        let mut last = 0;
+        let options = FormatOptions::default();
+        format_volume(&mut image, PARTITION_START_SECTOR, sectors, "spruce", &options, &CancellationToken::new(), |p| last = p).unwrap();
+        assert_eq!(last, 100);
+
+        let params = calculate_params(sectors, PARTITION_START_SECTOR, &options).unwrap();
+        let mut read_sector = |sector: u64| {
+            let mut buffer = [0u8; 512];
+            image.seek(SeekFrom::Start((PARTITION_START_SECTOR + sector) * 512)).unwrap();
+            image.read_exact(&mut buffer).unwrap();
+            buffer
+        };
+        let boot = read_sector(0);
+        assert_eq!(&boot[510..], &[0x55, 0xAA]);
+        assert_eq!(&boot[71..82], b"SPRUCE     ");
+        assert_eq!(read_sector(6), boot);
+        assert_eq!(&read_sector(1)[..4], b"RRaA");
+        assert_eq!(read_sector(40), [0u8; 512]);
+        assert_eq!(&read_sector(params.fat_start_sector() + params.fat_size_sectors as u64)[8..12], &0x0FFFFFFFu32.to_le_bytes());
+        let root = read_sector(params.data_start_sector());
+        assert_eq!(&root[..11], b"SPRUCE     ");
+        assert_eq!(root[11], 0x08);
+    }
 }
@@ -0,0 +1,947 @@
+// Copyright (C) 2026 SpruceOS Team
+// Licensed under GPL-3.0-or-later
+
+// Direct FAT32 writer
+// Fills a freshly formatted FAT32 volume (see fat32.rs) with directories and files
+// without mounting it, so archive installs work where the card can't be mounted after
+// formatting (pkexec, headless sessions, systems without udisks). File data is laid
+// out in contiguous clusters as it is written; directories (with long filenames), the
+// FATs and FSInfo are written by finish(), so an interrupted write leaves an empty
+// volume rather than a half-linked one.
+
+use crate::copy::CopyProgress;
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_ARCHIVE: u8 = 0x20;
+const ATTR_LONG_NAME: u8 = 0x0F;
+const END_OF_CHAIN: u32 = 0x0FFF_FFFF;
+/// FAT32 limits: 65536 entries per directory, files below 4 GB
+const MAX_DIR_ENTRIES: usize = 65_536;
+const MAX_FILE_SIZE: u64 = u32::MAX as u64;
+const MAX_LONG_NAME: usize = 255;
+/// Characters allowed in short (8.3) names besides A-Z and 0-9
+const SHORT_NAME_SPECIALS: &[u8] = b"!#$%&'()-@^_`{}~";
+/// Unbuffered device I/O needs aligned buffers, offsets and sizes
+const IO_ALIGNMENT: usize = 4096;
+const IO_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// A directory created by the writer; the root directory is ROOT_DIR
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DirId(usize);
+
+pub const ROOT_DIR: DirId = DirId(0);
+
+struct Directory {
+    first_cluster: u32,
+    entries: Vec<[u8; 32]>,
+    /// Long and short names in use, uppercased (FAT names are case-insensitive)
+    names: HashSet<String>,
+    short_names: HashSet<[u8; 11]>,
+    subdirs: HashMap<String, DirId>,
+}
+
+impl Directory {
+    fn new(first_cluster: u32) -> Self {
+        Self {
+            first_cluster,
+            entries: Vec::new(),
+            names: HashSet::new(),
+            short_names: HashSet::new(),
+            subdirs: HashMap::new(),
+        }
+    }
+}
+
+/// Geometry read from the boot sector (all offsets in bytes from the start of the device)
+#[derive(Debug)]
+struct Layout {
+    bytes_per_sector: u64,
+    bytes_per_cluster: u64,
+    fat_offset: u64,
+    fat_bytes: u64,
+    num_fats: u64,
+    data_offset: u64,
+    /// Highest valid cluster number + 1
+    cluster_limit: u32,
+    root_cluster: u32,
+    fsinfo_sectors: Vec<u64>,
+    volume_label: [u8; 11],
+}
+
+impl Layout {
+    fn from_boot_sector(boot: &[u8], volume_offset: u64) -> Result<Self, String> {
+        let u16_at = |i: usize| u16::from_le_bytes([boot[i], boot[i + 1]]) as u64;
+        let u32_at = |i: usize| u32::from_le_bytes([boot[i], boot[i + 1], boot[i + 2], boot[i + 3]]) as u64;
+
+        if boot[510..512] != [0x55, 0xAA] || &boot[82..90] != b"FAT32   " || u16_at(22) != 0 {
+            return Err("No FAT32 volume found on the device".to_string());
+        }
+        let bytes_per_sector = u16_at(11);
+        let sectors_per_cluster = boot[13] as u64;
+        let reserved_sectors = u16_at(14);
+        let num_fats = boot[16] as u64;
+        let total_sectors = u32_at(32);
+        let fat_sectors = u32_at(36);
+        let root_cluster = u32_at(44) as u32;
+        if !bytes_per_sector.is_power_of_two() || bytes_per_sector < 512 || sectors_per_cluster == 0 || num_fats == 0 || fat_sectors == 0 {
+            return Err("Invalid FAT32 boot sector".to_string());
+        }
+
+        let data_sectors = total_sectors.saturating_sub(reserved_sectors + num_fats * fat_sectors);
+        let clusters = (data_sectors / sectors_per_cluster).min(fat_sectors * bytes_per_sector / 4 - 2);
+        let mut fsinfo_sectors = vec![u16_at(48)];
+        let backup_boot = u16_at(50);
+        if backup_boot != 0 && backup_boot != 0xFFFF {
+            fsinfo_sectors.push(backup_boot + 1);
+        }
+
+        let mut volume_label = [0u8; 11];
+        volume_label.copy_from_slice(&boot[71..82]);
+
+        Ok(Self {
+            bytes_per_sector,
+            bytes_per_cluster: bytes_per_sector * sectors_per_cluster,
+            fat_offset: volume_offset + reserved_sectors * bytes_per_sector,
+            fat_bytes: fat_sectors * bytes_per_sector,
+            num_fats,
+            data_offset: volume_offset + (reserved_sectors + num_fats * fat_sectors) * bytes_per_sector,
+            cluster_limit: (clusters + 2) as u32,
+            root_cluster,
+            fsinfo_sectors: fsinfo_sectors.into_iter().map(|s| volume_offset + s * bytes_per_sector).collect(),
+            volume_label,
+        })
+    }
+
+    fn cluster_offset(&self, cluster: u32) -> u64 {
+        self.data_offset + (cluster as u64 - 2) * self.bytes_per_cluster
+    }
+}
+
+/// Reads and writes at any offset through aligned blocks (read-modify-write at the edges)
+struct BlockIo<D: Read + Write + Seek> {
+    device: D,
+    storage: Vec<u8>,
+    start: usize,
+}
+
+impl<D: Read + Write + Seek> BlockIo<D> {
+    fn new(device: D) -> Self {
+        let storage = vec![0u8; IO_CHUNK_SIZE + IO_ALIGNMENT];
+        let start = storage.as_ptr().align_offset(IO_ALIGNMENT);
+        Self { device, storage, start }
+    }
+
+    fn buffer(&mut self, len: usize) -> &mut [u8] {
+        &mut self.storage[self.start..self.start + len]
+    }
+
+    /// Read one aligned block; past the end of an image file reads as zeros
+    fn read_block(&mut self, offset: u64) -> Result<(), String> {
+        self.device
+            .seek(SeekFrom::Start(offset))
+            .map_err(|e| format!("Seek failed: {}", e))?;
+        let mut filled = 0;
+        while filled < IO_ALIGNMENT {
+            let start = self.start;
+            match self.device.read(&mut self.storage[start + filled..start + IO_ALIGNMENT]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(format!("Read failed at offset {}: {}", offset, e)),
+            }
+        }
+        self.buffer(IO_ALIGNMENT)[filled..].fill(0);
+        Ok(())
+    }
+
+    fn write_buffer(&mut self, offset: u64, len: usize) -> Result<(), String> {
+        self.device
+            .seek(SeekFrom::Start(offset))
+            .map_err(|e| format!("Seek failed: {}", e))?;
+        let start = self.start;
+        self.device
+            .write_all(&self.storage[start..start + len])
+            .map_err(|e| format!("Write failed at offset {}: {}", offset, e))
+    }
+
+    fn read_at(&mut self, offset: u64, data: &mut [u8]) -> Result<(), String> {
+        let align = IO_ALIGNMENT as u64;
+        let mut done = 0;
+        while done < data.len() {
+            let pos = offset + done as u64;
+            let block = pos / align * align;
+            self.read_block(block)?;
+            let within = (pos - block) as usize;
+            let n = (IO_ALIGNMENT - within).min(data.len() - done);
+            data[done..done + n].copy_from_slice(&self.buffer(IO_ALIGNMENT)[within..within + n]);
+            done += n;
+        }
+        Ok(())
+    }
+
+    fn write_at(&mut self, offset: u64, data: &[u8]) -> Result<(), String> {
+        let align = IO_ALIGNMENT as u64;
+        let mut done = 0;
+        while done < data.len() {
+            let pos = offset + done as u64;
+            let remaining = data.len() - done;
+            if pos.is_multiple_of(align) && remaining >= IO_ALIGNMENT {
+                // Whole blocks straight through the aligned buffer
+                let n = (remaining / IO_ALIGNMENT * IO_ALIGNMENT).min(IO_CHUNK_SIZE);
+                self.buffer(n).copy_from_slice(&data[done..done + n]);
+                self.write_buffer(pos, n)?;
+                done += n;
+            } else {
+                // Partial block: keep the bytes around it
+                let block = pos / align * align;
+                let within = (pos - block) as usize;
+                let n = (IO_ALIGNMENT - within).min(remaining);
+                self.read_block(block)?;
+                self.buffer(IO_ALIGNMENT)[within..within + n].copy_from_slice(&data[done..done + n]);
+                self.write_buffer(block, IO_ALIGNMENT)?;
+                done += n;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes directories and files into a freshly formatted FAT32 volume
+pub struct Fat32Writer<D: Read + Write + Seek> {
+    io: BlockIo<D>,
+    layout: Layout,
+    /// FAT entries for every cluster allocated so far (clusters are handed out in order)
+    fat: Vec<u32>,
+    dirs: Vec<Directory>,
+    /// DOS date and time used for directories
+    now: (u16, u16),
+}
+
+impl<D: Read + Write + Seek> Fat32Writer<D> {
+    /// Open the FAT32 volume starting `volume_offset` bytes into `device`
+    /// The volume must be empty apart from its label, as left by fat32::format_volume.
+    pub fn open(device: D, volume_offset: u64) -> Result<Self, String> {
+        let mut io = BlockIo::new(device);
+        let mut boot = [0u8; 512];
+        io.read_at(volume_offset, &mut boot)?;
+        let layout = Layout::from_boot_sector(&boot, volume_offset)?;
+        crate::debug::log(&format!("FAT32 layout: {:?}", layout));
+
+        // Only clusters up to the root directory may be in use
+        let root = layout.root_cluster;
+        let mut first_fat = vec![0u8; IO_ALIGNMENT];
+        io.read_at(layout.fat_offset, &mut first_fat)?;
+        let in_use = first_fat
+            .chunks(4)
+            .skip(root as usize + 1)
+            .any(|entry| u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]) & 0x0FFF_FFFF != 0);
+        if in_use || root < 2 || root >= layout.cluster_limit {
+            return Err("The FAT32 volume is not empty; files can only be written to a freshly formatted card".to_string());
+        }
+
+        let mut fat = vec![0u32; root as usize + 1];
+        fat[0] = 0x0FFF_FFF8;
+        fat[1] = END_OF_CHAIN;
+        fat[root as usize] = END_OF_CHAIN;
+
+        let mut root_dir = Directory::new(root);
+        if layout.volume_label != *b"NO NAME    " && layout.volume_label != [0u8; 11] {
+            root_dir.entries.push(short_entry(&layout.volume_label, ATTR_VOLUME_ID, 0, 0, dos_timestamp(SystemTime::now())));
+        }
+
+        Ok(Self {
+            io,
+            layout,
+            fat,
+            dirs: vec![root_dir],
+            now: dos_timestamp(SystemTime::now()),
+        })
+    }
+
+    /// Space left for file data and directories
+    pub fn free_bytes(&self) -> u64 {
+        (self.layout.cluster_limit as u64).saturating_sub(self.fat.len() as u64) * self.layout.bytes_per_cluster
+    }
+
+    /// Allocate `count` contiguous clusters as one chain, returning the first
+    fn allocate(&mut self, count: u64) -> Result<u32, String> {
+        let first = self.fat.len() as u64;
+        if first + count > self.layout.cluster_limit as u64 {
+            return Err(format!(
+                "Not enough space on the card ({} MB needed, {} MB free)",
+                count * self.layout.bytes_per_cluster / 1_048_576,
+                self.free_bytes() / 1_048_576
+            ));
+        }
+        for cluster in first..first + count {
+            self.fat.push(if cluster + 1 == first + count { END_OF_CHAIN } else { cluster as u32 + 1 });
+        }
+        Ok(first as u32)
+    }
+
+    /// Add the long name entries (if needed) and short entry for `name` to a directory
+    fn add_entry(&mut self, parent: DirId, name: &str, attr: u8, cluster: u32, size: u32, time: (u16, u16)) -> Result<(), String> {
+        validate_long_name(name)?;
+        let dir = &mut self.dirs[parent.0];
+        let key = name.to_uppercase();
+        if dir.names.contains(&key) {
+            return Err(format!("'{}' already exists", name));
+        }
+
+        let (short, needs_long) = short_name(name, &dir.short_names)?;
+        let mut entries = if needs_long { long_name_entries(name, short_name_checksum(&short)) } else { Vec::new() };
+        entries.push(short_entry(&short, attr, cluster, size, time));
+        if dir.entries.len() + entries.len() > MAX_DIR_ENTRIES {
+            return Err(format!("Too many entries in the folder of '{}'", name));
+        }
+
+        dir.entries.extend(entries);
+        dir.names.insert(key);
+        dir.names.insert(short_name_display(&short));
+        dir.short_names.insert(short);
+        Ok(())
+    }
+
+    /// Create a subdirectory, or return it if it was already created
+    pub fn create_dir(&mut self, parent: DirId, name: &str) -> Result<DirId, String> {
+        if let Some(&existing) = self.dirs[parent.0].subdirs.get(&name.to_uppercase()) {
+            return Ok(existing);
+        }
+
+        let cluster = self.allocate(1)?;
+        self.add_entry(parent, name, ATTR_DIRECTORY, cluster, 0, self.now)?;
+
+        // ".." of a directory in the root points at cluster 0 by convention
+        let parent_cluster = if parent == ROOT_DIR { 0 } else { self.dirs[parent.0].first_cluster };
+        let mut dir = Directory::new(cluster);
+        dir.entries.push(short_entry(b".          ", ATTR_DIRECTORY, cluster, 0, self.now));
+        dir.entries.push(short_entry(b"..         ", ATTR_DIRECTORY, parent_cluster, 0, self.now));
+
+        let id = DirId(self.dirs.len());
+        self.dirs.push(dir);
+        self.dirs[parent.0].subdirs.insert(name.to_uppercase(), id);
+        Ok(id)
+    }
+
+    /// Write a file of `size` bytes read from `reader`, reporting the bytes written so far
+    /// Cancelling stops between chunks, so a large file doesn't hold up the cancel.
+    #[allow(clippy::too_many_arguments)]
+    pub fn write_file(
+        &mut self,
+        parent: DirId,
+        name: &str,
+        reader: &mut dyn Read,
+        size: u64,
+        modified: Option<SystemTime>,
+        cancel_token: &CancellationToken,
+        mut on_progress: impl FnMut(u64),
+    ) -> Result<(), String> {
+        if size > MAX_FILE_SIZE {
+            return Err(format!("'{}' is larger than the 4 GB FAT32 file size limit", name));
+        }
+        let cluster_bytes = self.layout.bytes_per_cluster;
+        let clusters = size.div_ceil(cluster_bytes);
+        let first = if clusters == 0 { 0 } else { self.allocate(clusters)? };
+
+        // Whole clusters per write, so on cards with clusters of 4 KB or more nothing is read back
+        let chunk_size = (IO_CHUNK_SIZE as u64).max(cluster_bytes) as usize;
+        let mut chunk = vec![0u8; chunk_size];
+        let mut written = 0u64;
+        while written < size {
+            if cancel_token.is_cancelled() {
+                crate::debug::log("Copy cancelled by user");
+                return Err("Copy cancelled".to_string());
+            }
+            let n = (size - written).min(chunk_size as u64) as usize;
+            reader
+                .read_exact(&mut chunk[..n])
+                .map_err(|e| format!("Failed to read '{}': {}", name, e))?;
+            let padded = (n as u64).div_ceil(cluster_bytes) * cluster_bytes;
+            chunk[n..padded as usize].fill(0);
+            let offset = self.layout.cluster_offset(first) + written;
+            self.io.write_at(offset, &chunk[..padded as usize])
+                .map_err(|e| format!("Failed to write '{}': {}", name, e))?;
+            written += n as u64;
+            on_progress(written);
+        }
+
+        let time = modified.map(dos_timestamp).unwrap_or(self.now);
+        self.add_entry(parent, name, ATTR_ARCHIVE, first, size as u32, time)
+    }
+
+    /// Write the directories, both FATs and FSInfo, and hand the device back
+    pub fn finish(mut self) -> Result<D, String> {
+        let cluster_bytes = self.layout.bytes_per_cluster;
+
+        for i in 0..self.dirs.len() {
+            let data: Vec<u8> = self.dirs[i].entries.iter().flatten().copied().collect();
+            let clusters = (data.len() as u64).div_ceil(cluster_bytes).max(1);
+            let mut chain = vec![self.dirs[i].first_cluster];
+            if clusters > 1 {
+                let extra = self.allocate(clusters - 1)?;
+                self.fat[chain[0] as usize] = extra;
+                chain.extend(extra..extra + clusters as u32 - 1);
+            }
+
+            // Zero padding ends the listing; stale data would otherwise read as entries
+            let mut padded = data;
+            padded.resize((clusters * cluster_bytes) as usize, 0);
+            for (cluster, contents) in chain.iter().zip(padded.chunks(cluster_bytes as usize)) {
+                let offset = self.layout.cluster_offset(*cluster);
+                self.io.write_at(offset, contents)
+                    .map_err(|e| format!("Failed to write directory: {}", e))?;
+            }
+        }
+
+        let fat_bytes: Vec<u8> = self.fat.iter().flat_map(|entry| entry.to_le_bytes()).collect();
+        for i in 0..self.layout.num_fats {
+            let offset = self.layout.fat_offset + i * self.layout.fat_bytes;
+            self.io.write_at(offset, &fat_bytes)
+                .map_err(|e| format!("Failed to write FAT{}: {}", i + 1, e))?;
+        }
+
+        let free_clusters = self.layout.cluster_limit.saturating_sub(self.fat.len() as u32);
+        let next_free = self.fat.len() as u32;
+        for offset in self.layout.fsinfo_sectors.clone() {
+            let mut fsinfo = vec![0u8; self.layout.bytes_per_sector as usize];
+            self.io.read_at(offset, &mut fsinfo)?;
+            if fsinfo[0..4] == *b"RRaA" {
+                fsinfo[488..492].copy_from_slice(&free_clusters.to_le_bytes());
+                fsinfo[492..496].copy_from_slice(&next_free.to_le_bytes());
+                self.io.write_at(offset, &fsinfo)
+                    .map_err(|e| format!("Failed to write FSInfo: {}", e))?;
+            }
+        }
+
+        self.io.device.flush().map_err(|e| format!("Failed to flush device: {}", e))?;
+        Ok(self.io.device)
+    }
+
+    /// Copy a folder's contents into `dest`, sorted by name so images come out the same every time
+    /// Symbolic links are skipped: FAT32 can't store them, and following one could loop or leave the folder.
+    pub fn copy_tree(
+        &mut self,
+        source_dir: &Path,
+        dest: DirId,
+        progress_tx: &mpsc::UnboundedSender<CopyProgress>,
+        cancel_token: &CancellationToken,
+    ) -> Result<(), String> {
+        let _ = progress_tx.send(CopyProgress::Counting);
+        let (total_files, total_bytes) = count_tree(source_dir)
+            .map_err(|e| format!("Failed to scan source directory: {}", e))?;
+        crate::debug::log(&format!("Found {} files, {} bytes total", total_files, total_bytes));
+        let _ = progress_tx.send(CopyProgress::Started { total_bytes, total_files });
+
+        let mut copied_bytes = 0u64;
+        let mut pending = vec![(source_dir.to_path_buf(), dest)];
+        while let Some((dir_path, dir_id)) = pending.pop() {
+            for path in sorted_entries(&dir_path)? {
+                if cancel_token.is_cancelled() {
+                    crate::debug::log("Copy cancelled by user");
+                    return Err("Copy cancelled".to_string());
+                }
+                let name = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .ok_or_else(|| format!("Invalid file name: {}", path.display()))?;
+
+                let metadata = std::fs::symlink_metadata(&path)
+                    .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                if metadata.is_symlink() {
+                    crate::debug::log(&format!("Skipping symbolic link {}", path.display()));
+                    continue;
+                }
+                if metadata.is_dir() {
+                    let id = self.create_dir(dir_id, &name)?;
+                    pending.push((path, id));
+                    continue;
+                }
+
+                let mut file = std::fs::File::open(&path)
+                    .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+                let relative = path.strip_prefix(source_dir).unwrap_or(&path).display().to_string();
+                let base = copied_bytes;
+                self.write_file(dir_id, &name, &mut file, metadata.len(), metadata.modified().ok(), cancel_token, |written| {
+                    let _ = progress_tx.send(CopyProgress::Progress {
+                        copied_bytes: base + written,
+                        total_bytes,
+                        current_file: relative.clone(),
+                    });
+                })
+                .map_err(|e| format!("{}: {}", relative, e))?;
+                copied_bytes += metadata.len();
+            }
+        }
+        Ok(())
+    }
+}
+
+fn sorted_entries(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(dir)
+        .map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .collect();
+    entries.sort();
+    Ok(entries)
+}
+
+/// Number of files and their total size below a folder, not counting symbolic links (see `copy_tree`)
+fn count_tree(dir: &Path) -> std::io::Result<(u64, u64)> {
+    let mut files = 0;
+    let mut bytes = 0;
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_symlink() {
+            continue;
+        } else if file_type.is_dir() {
+            let (f, b) = count_tree(&entry.path())?;
+            files += f;
+            bytes += b;
+        } else {
+            files += 1;
+            bytes += entry.metadata()?.len();
+        }
+    }
+    Ok((files, bytes))
+}
+
+/// Byte offset of partition `number` (1-4) in the device's MBR
+pub fn find_partition<D: Read + Seek>(device: &mut D, number: u32) -> Result<u64, String> {
+    let mut mbr = vec![0u8; IO_ALIGNMENT];
+    device
+        .seek(SeekFrom::Start(0))
+        .and_then(|_| device.read_exact(&mut mbr))
+        .map_err(|e| format!("Failed to read partition table: {}", e))?;
+    if mbr[510..512] != [0x55, 0xAA] {
+        return Err("No MBR partition table found on the device".to_string());
+    }
+    let entry = match number {
+        1..=4 => &mbr[446 + (number as usize - 1) * 16..446 + number as usize * 16],
+        _ => return Err(format!("An MBR has no partition {}", number)),
+    };
+    // Type 0 marks an unused entry
+    if entry[4] == 0 {
+        return Err(format!("Partition {} not found on the device", number));
+    }
+    Ok(u32::from_le_bytes([entry[8], entry[9], entry[10], entry[11]]) as u64 * crate::fat32::SECTOR_SIZE as u64)
+}
+
+/// Copy a folder into a freshly formatted FAT32 partition (number 1-4) of a card without mounting it
+pub async fn copy_directory_to_device(
+    device_path: &str,
+    partition: u32,
+    source_dir: &Path,
+    progress_tx: mpsc::UnboundedSender<CopyProgress>,
+    cancel_token: CancellationToken,
+) -> Result<(), String> {
+    crate::debug::log_section("Write Files Directly");
+    crate::debug::log(&format!("Source: {:?}", source_dir));
+    crate::debug::log(&format!("Device: {} (partition {})", device_path, partition));
+
+    crate::burn::unmount_device(device_path).await?;
+
+    let result = tokio::task::spawn_blocking({
+        let device_path = device_path.to_string();
+        let source_dir = source_dir.to_path_buf();
+        let progress_tx = progress_tx.clone();
+        let cancel_token = cancel_token.clone();
+
+        move || -> Result<(), String> {
+            let mut device = crate::burn::open_device_for_writing(&device_path)?;
+            let volume_offset = find_partition(&mut device.file, partition)?;
+            let mut writer = Fat32Writer::open(&mut device.file, volume_offset)?;
+            writer.copy_tree(&source_dir, ROOT_DIR, &progress_tx, &cancel_token)?;
+            let file = writer.finish()?;
+            file.sync_all().map_err(|e| format!("Failed to flush device: {}", e))
+        }
+    })
+    .await
+    .map_err(|e| format!("Copy task failed: {}", e))?;
+
+    match &result {
+        Ok(()) => {
+            let _ = progress_tx.send(CopyProgress::Completed);
+        }
+        Err(_) if cancel_token.is_cancelled() => {
+            let _ = progress_tx.send(CopyProgress::Cancelled);
+        }
+        Err(e) => {
+            let _ = progress_tx.send(CopyProgress::Error(e.clone()));
+        }
+    }
+    result
+}
+
+/// Characters Windows refuses in file names are refused here too
+fn validate_long_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(format!("Invalid file name '{}'", name));
+    }
+    if name.encode_utf16().count() > MAX_LONG_NAME {
+        return Err(format!("File name too long for FAT32: '{}'", name));
+    }
+    if name.ends_with('.') || name.ends_with(' ') {
+        return Err(format!("File names can't end with a dot or space on FAT32: '{}'", name));
+    }
+    if let Some(c) = name.chars().find(|&c| c < ' ' || "\"*/:<>?\\|".contains(c)) {
+        return Err(format!("'{}' contains a character FAT32 doesn't allow ({:?})", name, c));
+    }
+    Ok(())
+}
+
+fn is_short_name_char(b: u8) -> bool {
+    b.is_ascii_uppercase() || b.is_ascii_digit() || SHORT_NAME_SPECIALS.contains(&b)
+}
+
+/// Short (8.3) name for `name`, and whether long name entries are needed to keep the real name
+fn short_name(name: &str, taken: &HashSet<[u8; 11]>) -> Result<([u8; 11], bool), String> {
+    let mut short = [b' '; 11];
+
+    // Names that are already valid uppercase 8.3 names are stored as they are
+    let (base, ext) = match name.rsplit_once('.') {
+        Some((base, ext)) => (base, ext),
+        None => (name, ""),
+    };
+    let exact = !base.is_empty()
+        && base.len() <= 8
+        && ext.len() <= 3
+        && !(name.ends_with('.'))
+        && base.bytes().chain(ext.bytes()).all(is_short_name_char);
+    if exact {
+        short[..base.len()].copy_from_slice(base.as_bytes());
+        short[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+        if !taken.contains(&short) {
+            return Ok((short, false));
+        }
+    }
+
+    // Otherwise a "BASE~N.EXT" alias, as Windows generates them
+    let convert = |s: &str| -> Vec<u8> {
+        s.chars()
+            .filter(|&c| c != ' ' && c != '.')
+            .map(|c| {
+                let upper = c.to_ascii_uppercase();
+                if upper.is_ascii() && is_short_name_char(upper as u8) { upper as u8 } else { b'_' }
+            })
+            .collect()
+    };
+    let trimmed = name.trim_start_matches('.');
+    let (base, ext) = match trimmed.rsplit_once('.') {
+        Some((base, ext)) => (convert(base), convert(ext)),
+        None => (convert(trimmed), Vec::new()),
+    };
+    let base = if base.is_empty() { b"_".to_vec() } else { base };
+    let ext_len = ext.len().min(3);
+
+    for n in 1..=999_999u32 {
+        let tail = format!("~{}", n);
+        let keep = base.len().min(8 - tail.len());
+        let mut candidate = [b' '; 11];
+        candidate[..keep].copy_from_slice(&base[..keep]);
+        candidate[keep..keep + tail.len()].copy_from_slice(tail.as_bytes());
+        candidate[8..8 + ext_len].copy_from_slice(&ext[..ext_len]);
+        if !taken.contains(&candidate) {
+            return Ok((candidate, true));
+        }
+    }
+    Err(format!("No free short name for '{}'", name))
+}
+
+/// Short name as it is shown, e.g. "README~1.TXT"
+fn short_name_display(short: &[u8; 11]) -> String {
+    let base = String::from_utf8_lossy(&short[..8]).trim_end().to_string();
+    let ext = String::from_utf8_lossy(&short[8..]).trim_end().to_string();
+    if ext.is_empty() { base } else { format!("{}.{}", base, ext) }
+}
+
+fn short_name_checksum(short: &[u8; 11]) -> u8 {
+    short.iter().fold(0u8, |sum, &b| sum.rotate_right(1).wrapping_add(b))
+}
+
+/// Long name entries in on-disk order (last part first), 13 UTF-16 units each
+fn long_name_entries(name: &str, checksum: u8) -> Vec<[u8; 32]> {
+    let mut units: Vec<u16> = name.encode_utf16().collect();
+    if !units.len().is_multiple_of(13) {
+        // Terminated with 0x0000, then padded with 0xFFFF
+        units.push(0);
+        while !units.len().is_multiple_of(13) {
+            units.push(0xFFFF);
+        }
+    }
+
+    let parts = units.len() / 13;
+    (0..parts)
+        .rev()
+        .map(|i| {
+            let mut entry = [0u8; 32];
+            entry[0] = (i + 1) as u8 | if i + 1 == parts { 0x40 } else { 0 };
+            entry[11] = ATTR_LONG_NAME;
+            entry[13] = checksum;
+            let chars = &units[i * 13..i * 13 + 13];
+            let positions = (1..11).step_by(2).chain((14..26).step_by(2)).chain((28..32).step_by(2));
+            for (pos, unit) in positions.zip(chars) {
+                entry[pos..pos + 2].copy_from_slice(&unit.to_le_bytes());
+            }
+            entry
+        })
+        .collect()
+}
+
+fn short_entry(short: &[u8; 11], attr: u8, cluster: u32, size: u32, (date, time): (u16, u16)) -> [u8; 32] {
+    let mut entry = [0u8; 32];
+    entry[0..11].copy_from_slice(short);
+    entry[11] = attr;
+    // Creation, last access and modification all set to the same time
+    entry[14..16].copy_from_slice(&time.to_le_bytes());
+    entry[16..18].copy_from_slice(&date.to_le_bytes());
+    entry[18..20].copy_from_slice(&date.to_le_bytes());
+    entry[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+    entry[22..24].copy_from_slice(&time.to_le_bytes());
+    entry[24..26].copy_from_slice(&date.to_le_bytes());
+    entry[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+    entry[28..32].copy_from_slice(&size.to_le_bytes());
+    entry
+}
+
+/// DOS (date, time) of a file time, in UTC as the card has no time zone
+fn dos_timestamp(time: SystemTime) -> (u16, u16) {
+    let secs = time
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let (year, month, day) = crate::backup::utc_date(secs);
+    if year < 1980 {
+        return ((1 << 5) | 1, 0); // 1980-01-01, the earliest DOS date
+    }
+    let year = year.min(2107);
+    let day_secs = secs % 86_400;
+    let date = (((year - 1980) << 9) | (month << 5) | day) as u16;
+    let time = ((day_secs / 3_600) << 11 | (day_secs / 60 % 60) << 5 | (day_secs % 60 / 2)) as u16;
+    (date, time)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::fat32::{format_device, FormatOptions, PARTITION_START_SECTOR, SECTOR_SIZE};
+
+    /// Directory listing of a cluster chain: (long or short name, attributes, first cluster, size)
+    fn list_dir<D: Read + Seek>(image: &mut D, layout: &Layout, fat: &[u32], cluster: u32) -> Vec<(String, u8, u32, u32)> {
+        let mut listing = Vec::new();
+        let mut long_name: Vec<u16> = Vec::new();
+        for data in read_chain(image, layout, fat, cluster, None).chunks(32) {
+            match data[0] {
+                0x00 => break,
+                _ if data[11] == ATTR_LONG_NAME => {
+                    let positions = (1..11).step_by(2).chain((14..26).step_by(2)).chain((28..32).step_by(2));
+                    let part: Vec<u16> = positions.map(|p| u16::from_le_bytes([data[p], data[p + 1]])).collect();
+                    long_name.splice(0..0, part);
+                }
+                _ => {
+                    let short: [u8; 11] = data[0..11].try_into().unwrap();
+                    let name = if long_name.is_empty() {
+                        short_name_display(&short)
+                    } else {
+                        let end = long_name.iter().position(|&u| u == 0).unwrap_or(long_name.len());
+                        String::from_utf16(&long_name[..end]).unwrap()
+                    };
+                    long_name.clear();
+                    let cluster = (u16::from_le_bytes([data[20], data[21]]) as u32) << 16 | u16::from_le_bytes([data[26], data[27]]) as u32;
+                    listing.push((name, data[11], cluster, u32::from_le_bytes(data[28..32].try_into().unwrap())));
+                }
+            }
+        }
+        listing
+    }
+
+    fn read_chain<D: Read + Seek>(image: &mut D, layout: &Layout, fat: &[u32], first: u32, size: Option<u32>) -> Vec<u8> {
+        let mut data = Vec::new();
+        let mut cluster = first;
+        while (2..0x0FFF_FFF8).contains(&cluster) {
+            let mut buffer = vec![0u8; layout.bytes_per_cluster as usize];
+            image.seek(SeekFrom::Start(layout.cluster_offset(cluster))).unwrap();
+            image.read_exact(&mut buffer).unwrap();
+            data.extend(buffer);
+            cluster = fat[cluster as usize];
+        }
+        if let Some(size) = size {
+            data.truncate(size as usize);
+        }
+        data
+    }
+
+    /// Boot sector layout and first FAT of the volume at `offset`
+    fn read_volume<D: Read + Seek>(image: &mut D, offset: u64) -> (Layout, Vec<u32>) {
+        let mut boot = [0u8; 512];
+        image.seek(SeekFrom::Start(offset)).unwrap();
+        image.read_exact(&mut boot).unwrap();
+        let layout = Layout::from_boot_sector(&boot, offset).unwrap();
+        let mut fat_bytes = vec![0u8; layout.fat_bytes as usize];
+        image.seek(SeekFrom::Start(layout.fat_offset)).unwrap();
+        image.read_exact(&mut fat_bytes).unwrap();
+        let fat = fat_bytes.chunks(4).map(|c| u32::from_le_bytes(c.try_into().unwrap())).collect();
+        (layout, fat)
+    }
+
+    /// Look up a '/'-separated path from the root directory of the volume at `offset`
+    /// Returns the size in the file's directory entry and the data along its cluster chain
+    pub(crate) fn read_file<D: Read + Seek>(image: &mut D, offset: u64, path: &str) -> Option<(u32, Vec<u8>)> {
+        let (layout, fat) = read_volume(image, offset);
+        let mut cluster = layout.root_cluster;
+        let mut components = path.split('/').peekable();
+        while let Some(name) = components.next() {
+            let listing = list_dir(image, &layout, &fat, cluster);
+            let (_, attributes, first, size) = listing.into_iter().find(|e| e.0 == name)?;
+            if components.peek().is_none() {
+                return (attributes & ATTR_DIRECTORY == 0).then(|| (size, read_chain(image, &layout, &fat, first, Some(size))));
+            }
+            cluster = first;
+        }
+        None
+    }
+
+    fn formatted_image() -> (std::fs::File, u64) {
+        let mut image = tempfile::tempfile().unwrap();
+        let size = 80 * 1024 * 1024;
+        image.set_len(size).unwrap();
+        format_device(&mut image, size, &FormatOptions::default(), "SPRUCEOS", &CancellationToken::new(), |_| {}).unwrap();
+        (image, PARTITION_START_SECTOR * SECTOR_SIZE as u64)
+    }
+
+    #[test]
+    fn test_open_rejects_corrupt_boot_sector() {
+        let (mut image, offset) = formatted_image();
+        // FAT size of 0 sectors
+        image.seek(SeekFrom::Start(offset + 36)).unwrap();
+        image.write_all(&[0; 4]).unwrap();
+        assert_eq!(Fat32Writer::open(&mut image, offset).err().unwrap(), "Invalid FAT32 boot sector");
+    }
+
+    #[test]
+    fn test_write_tree() {
+        let (mut image, offset) = formatted_image();
+        assert_eq!(find_partition(&mut image, 1).unwrap(), offset);
+        assert!(find_partition(&mut image, 2).is_err());
+
+        let mut writer = Fat32Writer::open(&mut image, offset).unwrap();
+        let cluster_bytes = writer.layout.bytes_per_cluster as usize;
+        let big: Vec<u8> = (0..cluster_bytes * 3 + 100).map(|i| (i % 251) as u8).collect();
+        let roms = writer.create_dir(ROOT_DIR, "Roms").unwrap();
+        assert_eq!(writer.create_dir(ROOT_DIR, "ROMS").unwrap(), roms);
+        let nested = writer.create_dir(roms, "Game Boy Advance").unwrap();
+        writer.write_file(nested, "A Long Name With Spaces.gba", &mut &big[..], big.len() as u64, None, &CancellationToken::new(), |_| {}).unwrap();
+        writer.write_file(ROOT_DIR, "README.TXT", &mut &b"hello"[..], 5, None, &CancellationToken::new(), |_| {}).unwrap();
+        writer.write_file(ROOT_DIR, "empty", &mut &b""[..], 0, None, &CancellationToken::new(), |_| {}).unwrap();
+        assert!(writer.write_file(ROOT_DIR, "readme.txt", &mut &b"x"[..], 1, None, &CancellationToken::new(), |_| {}).is_err());
+        // Enough entries to need a second cluster for the directory
+        for i in 0..(cluster_bytes / 32) {
+            writer.write_file(roms, &format!("save file {}.srm", i), &mut &b"s"[..], 1, None, &CancellationToken::new(), |_| {}).unwrap();
+        }
+        writer.finish().unwrap();
+
+        let (layout, fat) = read_volume(&mut image, offset);
+        let mut fat_bytes = vec![0u8; layout.fat_bytes as usize * 2];
+        image.seek(SeekFrom::Start(layout.fat_offset)).unwrap();
+        image.read_exact(&mut fat_bytes).unwrap();
+        let (first_fat, second_fat) = fat_bytes.split_at(layout.fat_bytes as usize);
+        assert_eq!(first_fat, second_fat);
+
+        let root = list_dir(&mut image, &layout, &fat, layout.root_cluster);
+        let names: Vec<&str> = root.iter().map(|e| e.0.as_str()).collect();
+        assert_eq!(names, ["SPRUCEOS", "Roms", "README.TXT", "empty"]);
+        assert_eq!(root[0].1, ATTR_VOLUME_ID);
+        assert_eq!(read_chain(&mut image, &layout, &fat, root[2].2, Some(root[2].3)), b"hello");
+        assert_eq!((root[3].2, root[3].3), (0, 0));
+
+        let roms_listing = list_dir(&mut image, &layout, &fat, root[1].2);
+        assert_eq!(roms_listing.len(), 3 + cluster_bytes / 32);
+        assert_eq!(roms_listing[0].0, ".");
+        assert_eq!(roms_listing[1], ("..".to_string(), ATTR_DIRECTORY, 0, 0));
+        assert_eq!(roms_listing.last().unwrap().0, format!("save file {}.srm", cluster_bytes / 32 - 1));
+
+        let gba = list_dir(&mut image, &layout, &fat, roms_listing[2].2);
+        assert_eq!(gba[1].2, root[1].2);
+        assert_eq!(gba[2].0, "A Long Name With Spaces.gba");
+        assert_eq!(read_chain(&mut image, &layout, &fat, gba[2].2, Some(gba[2].3)), big);
+        let path = "Roms/Game Boy Advance/A Long Name With Spaces.gba";
+        assert_eq!(read_file(&mut image, offset, path), Some((big.len() as u32, big)));
+        assert_eq!(read_file(&mut image, offset, "Roms"), None);
+
+        // Opening it again is refused: the writer only fills empty volumes
+        assert!(Fat32Writer::open(&mut image, offset).is_err());
+    }
+
+    #[test]
+    fn test_write_file_cancelled() {
+        let (mut image, offset) = formatted_image();
+        let mut writer = Fat32Writer::open(&mut image, offset).unwrap();
+        let cancel_token = CancellationToken::new();
+        cancel_token.cancel();
+        let result = writer.write_file(ROOT_DIR, "big.bin", &mut &[0u8; 100][..], 100, None, &cancel_token, |_| {});
+        assert_eq!(result.unwrap_err(), "Copy cancelled");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_copy_tree_skips_symlinks() {
+        let source = tempfile::tempdir().unwrap();
+        std::fs::create_dir(source.path().join("Saves")).unwrap();
+        std::fs::write(source.path().join("Saves/game.srm"), b"save").unwrap();
+        // A link back to the top would recurse forever if followed
+        std::os::unix::fs::symlink(source.path(), source.path().join("Saves/loop")).unwrap();
+        std::os::unix::fs::symlink("/etc/hostname", source.path().join("hostname")).unwrap();
+        assert_eq!(count_tree(source.path()).unwrap(), (1, 4));
+
+        let (mut image, offset) = formatted_image();
+        let mut writer = Fat32Writer::open(&mut image, offset).unwrap();
+        let (progress_tx, _progress_rx) = mpsc::unbounded_channel();
+        writer.copy_tree(source.path(), ROOT_DIR, &progress_tx, &CancellationToken::new()).unwrap();
+        let saves = writer.dirs[ROOT_DIR.0].subdirs["SAVES"];
+        assert!(!writer.dirs[ROOT_DIR.0].names.contains("HOSTNAME"));
+        assert!(writer.dirs[saves.0].names.contains("GAME.SRM") && !writer.dirs[saves.0].names.contains("LOOP"));
+    }
+
+    #[test]
+    fn test_short_name() {
+        let mut taken = HashSet::new();
+        assert_eq!(short_name("README.TXT", &taken).unwrap(), (*b"README  TXT", false));
+        assert_eq!(short_name("readme.txt", &taken).unwrap(), (*b"README~1TXT", true));
+        assert_eq!(short_name("Game Boy Advance", &taken).unwrap(), (*b"GAMEBO~1   ", true));
+        assert_eq!(short_name(".hidden.cfg", &taken).unwrap(), (*b"HIDDEN~1CFG", true));
+        taken.insert(*b"GAMEBO~1   ");
+        assert_eq!(short_name("Game Boy Color", &taken).unwrap(), (*b"GAMEBO~2   ", true));
+        assert!(validate_long_name("a:b").is_err());
+        assert!(validate_long_name("trailing.").is_err());
+    }
+
+    #[test]
+    fn test_long_name_entries() {
+        let entries = long_name_entries("Game Boy Advance", 0x42);
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0][0], 0x42);
+        assert_eq!(entries[1][0], 0x01);
+        assert!(entries.iter().all(|e| e[11] == ATTR_LONG_NAME && e[13] == 0x42));
+        // "Game Boy Adva" in the first part, "nce" + terminator in the second
+        assert_eq!(&entries[1][1..3], &[b'G', 0]);
+        assert_eq!(&entries[0][7..9], &[0, 0]);
+        assert_eq!(&entries[0][9..11], &[0xFF, 0xFF]);
+    }
+
+    #[test]
+    fn test_dos_timestamp() {
+        // 2026-10-16 12:34:56 UTC
+        let time = SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(1_792_154_096);
+        let (date, clock) = dos_timestamp(time);
+        assert_eq!(date, (46 << 9) | (10 << 5) | 16);
+        assert_eq!(clock, (12 << 11) | (34 << 5) | 28);
+        assert_eq!(dos_timestamp(SystemTime::UNIX_EPOCH), ((1 << 5) | 1, 0));
+    }
+}
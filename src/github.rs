@@ -4,8 +4,10 @@
 use crate::config::USER_AGENT;
 use crate::manifest::Manifest;
 use futures_util::StreamExt;
-use serde::Deserialize;
-use std::path::Path;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 use tokio::sync::mpsc;
@@ -17,8 +19,37 @@ pub struct Release {
     #[allow(dead_code)]
     pub name: Option<String>,
     pub assets: Vec<Asset>,
+    #[serde(default)]
+    pub prerelease: bool,
+    /// ISO 8601 timestamp, e.g. "2026-01-31T12:00:00Z"
+    #[serde(default)]
+    pub published_at: Option<String>,
 }
 
+impl Release {
+    /// Publish date as YYYY-MM-DD (empty if unknown)
+    pub fn date(&self) -> &str {
+        self.published_at
+            .as_deref()
+            .and_then(|d| d.get(..10))
+            .unwrap_or("")
+    }
+
+    /// One-line description for release pickers, e.g. "v4.0.0  2026-01-31  (pre-release)"
+    pub fn summary(&self) -> String {
+        let mut text = format!("{}  {}", self.tag_name, self.date());
+        if self.prerelease {
+            text.push_str("  (pre-release)");
+        }
+        text.trim_end().to_string()
+    }
+}
+
+/// Releases fetched per page when listing (GitHub allows up to 100)
+const RELEASES_PER_PAGE: usize = 30;
+/// Stop listing after this many pages to stay well within the API rate limit
+const MAX_RELEASE_PAGES: usize = 3;
+
 #[derive(Debug, Deserialize, Clone)]
 pub struct Asset {
     pub name: String,
@@ -31,12 +62,34 @@ pub struct Asset {
 
     #[serde(skip_serializing_if = "Option::is_none", default)]
     pub devices: Option<String>,
+
+    // Expected SHA-256 (hex), from manifest.json or a SHA256SUMS release asset
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub sha256: Option<String>,
+
+    // Decompressed size of a compressed disk image, from manifest.json
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub uncompressed_size: Option<u64>,
+
+    // URL of a .bmap block map for a raw image, from manifest.json or a release asset
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub bmap_url: Option<String>,
+
+    // Expected SHA-256 (hex) of the bmap, from a SHA256SUMS release asset
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub bmap_sha256: Option<String>,
 }
 
 #[derive(Debug)]
 pub enum DownloadProgress {
     Started { total_bytes: u64 },
     Progress { downloaded: u64, total: u64 },
+    /// Continuing an interrupted download (already `downloaded` bytes on disk)
+    Resuming { downloaded: u64, total: u64 },
+    /// A chunk lost its connection and is retried after `delay_secs`
+    Retrying { attempt: u32, max_attempts: u32, delay_secs: u64 },
+    /// Hashing the finished download against the expected SHA-256
+    Verifying { verified: u64, total: u64 },
     Completed,
     Cancelled,
     #[allow(dead_code)]
@@ -47,13 +100,64 @@ pub async fn get_latest_release(repo_url: &str) -> Result<Release, String> {
     let (owner, repo) = parse_github_url(repo_url)?;
     let api_url = format!("https://api.github.com/repos/{}/{}/releases/latest", owner, repo);
 
+    github_api_get(&api_url)
+        .await?
+        .json::<Release>()
+        .await
+        .map_err(|e| format!("Failed to parse release data: {}. The release format may be invalid.", e))
+}
+
+/// Fetch a specific release by its tag name
+pub async fn get_release_by_tag(repo_url: &str, tag: &str) -> Result<Release, String> {
+    let (owner, repo) = parse_github_url(repo_url)?;
+    let api_url = format!("https://api.github.com/repos/{}/{}/releases/tags/{}", owner, repo, tag);
+
+    github_api_get(&api_url)
+        .await
+        .map_err(|e| if e.contains("404") { format!("Release '{}' not found", tag) } else { e })?
+        .json::<Release>()
+        .await
+        .map_err(|e| format!("Failed to parse release data: {}. The release format may be invalid.", e))
+}
+
+/// List published releases (including pre-releases), newest first
+/// Follows the paginated /releases endpoint up to MAX_RELEASE_PAGES pages
+pub async fn list_releases(repo_url: &str) -> Result<Vec<Release>, String> {
+    let (owner, repo) = parse_github_url(repo_url)?;
+    let mut releases = Vec::new();
+
+    for page in 1..=MAX_RELEASE_PAGES {
+        let api_url = format!(
+            "https://api.github.com/repos/{}/{}/releases?per_page={}&page={}",
+            owner, repo, RELEASES_PER_PAGE, page
+        );
+
+        let page_releases = github_api_get(&api_url)
+            .await?
+            .json::<Vec<Release>>()
+            .await
+            .map_err(|e| format!("Failed to parse release list: {}", e))?;
+
+        let is_last_page = page_releases.len() < RELEASES_PER_PAGE;
+        releases.extend(page_releases);
+        if is_last_page {
+            break;
+        }
+    }
+
+    crate::debug::log(&format!("Found {} releases for {}/{}", releases.len(), owner, repo));
+    Ok(releases)
+}
+
+/// GET a GitHub API URL, mapping network errors and rate limiting to user-friendly messages
+async fn github_api_get(api_url: &str) -> Result<reqwest::Response, String> {
     let client = reqwest::Client::builder()
         .timeout(std::time::Duration::from_secs(30))
         .build()
         .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
 
     let response = client
-        .get(&api_url)
+        .get(api_url)
         .header("User-Agent", USER_AGENT)
         .header("Accept", "application/vnd.github.v3+json")
         .send()
@@ -77,56 +181,234 @@ pub async fn get_latest_release(repo_url: &str) -> Result<Release, String> {
         return Err(format!("GitHub API returned error: {}. Please try again later.", response.status()));
     }
 
-    response
-        .json::<Release>()
-        .await
-        .map_err(|e| format!("Failed to parse release data: {}. The release format may be invalid.", e))
+    Ok(response)
 }
 
-/// Check if a release contains a manifest.json file and fetch it
-/// Returns Some(Manifest) if found and successfully parsed, None otherwise
-pub async fn get_manifest_from_release(release: &Release) -> Option<Manifest> {
-    // Look for manifest.json in the release assets
-    let manifest_asset = release.assets.iter()
-        .find(|asset| asset.name.eq_ignore_ascii_case("manifest.json"))?;
-
-    crate::debug::log("Found manifest.json in release, fetching...");
-    crate::debug::log(&format!("Manifest URL: {}", manifest_asset.browser_download_url));
+/// Download a small text file attached to a release (manifest.json, SHA256SUMS, signatures)
+async fn fetch_release_text(asset: &Asset) -> Result<String, String> {
+    fetch_text(&asset.browser_download_url, &asset.name).await
+}
 
-    // Fetch the manifest file
+/// Download a small text file (`name` is only used in error messages)
+pub async fn fetch_text(url: &str, name: &str) -> Result<String, String> {
     let client = reqwest::Client::builder()
         .timeout(std::time::Duration::from_secs(30))
         .build()
-        .ok()?;
+        .map_err(|e| format!("Failed to create HTTP client: {}", e))?;
 
     let response = client
-        .get(&manifest_asset.browser_download_url)
+        .get(url)
         .header("User-Agent", USER_AGENT)
         .send()
         .await
-        .ok()?;
+        .map_err(|e| format!("Failed to fetch {}: {}", name, e))?;
 
     if !response.status().is_success() {
-        crate::debug::log(&format!("Failed to fetch manifest: HTTP {}", response.status()));
-        return None;
+        return Err(format!("Failed to fetch {}: HTTP {}", name, response.status()));
     }
 
-    // Parse the JSON
-    let manifest_text = response.text().await.ok()?;
+    response.text().await
+        .map_err(|e| format!("Failed to read {}: {}", name, e))
+}
+
+/// Check the detached signature (<file>.minisig) of a file attached to a release
+/// Returns Ok(true) if it verified, Ok(false) if there is no signature or no key is configured,
+/// and Err if a signature is present but doesn't match
+async fn check_release_signature(release: &Release, file_name: &str, data: &[u8]) -> Result<bool, String> {
+    let signature_name = format!("{}.{}", file_name, crate::signature::SIGNATURE_EXTENSION);
+    let Some(signature_asset) = release.assets.iter()
+        .find(|asset| asset.name.eq_ignore_ascii_case(&signature_name))
+    else {
+        crate::debug::log(&format!("{} is not signed", file_name));
+        return Ok(false);
+    };
+
+    if !crate::signature::signing_enabled() {
+        crate::debug::log(&format!("Ignoring {}: no signing public key configured", signature_name));
+        return Ok(false);
+    }
+
+    let signature = fetch_release_text(signature_asset).await?;
+    crate::signature::verify_bytes(data, &signature)
+        .map_err(|e| format!("{} failed signature check: {}", file_name, e))?;
+
+    crate::debug::log(&format!("{} signature verified", file_name));
+    Ok(true)
+}
+
+/// Check if a release contains a manifest.json file and fetch it
+/// Returns Ok(Some(Manifest)) if found and successfully parsed, Ok(None) otherwise.
+/// Fails if the manifest is mis-signed, unsigned while `require_signature` is set, or lists a malformed sha256.
+pub async fn get_manifest_from_release(release: &Release, require_signature: bool) -> Result<Option<Manifest>, String> {
+    // Look for manifest.json in the release assets
+    let Some(manifest_asset) = release.assets.iter()
+        .find(|asset| asset.name.eq_ignore_ascii_case("manifest.json"))
+    else {
+        return Ok(None);
+    };
+
+    crate::debug::log("Found manifest.json in release, fetching...");
+    crate::debug::log(&format!("Manifest URL: {}", manifest_asset.browser_download_url));
+
+    // Fetch the manifest file
+    let manifest_text = match fetch_release_text(manifest_asset).await {
+        Ok(text) => text,
+        Err(e) => {
+            crate::debug::log(&format!("Failed to fetch manifest: {}", e));
+            return Ok(None);
+        }
+    };
     crate::debug::log(&format!("Manifest content length: {} bytes", manifest_text.len()));
 
+    // The manifest decides where assets are downloaded from, so verify it before using it
+    let signed = check_release_signature(release, &manifest_asset.name, manifest_text.as_bytes()).await?;
+    if require_signature && !signed {
+        return Err("manifest.json has no valid signature, but this repository requires signed releases".to_string());
+    }
+
+    // Parse the JSON
     match serde_json::from_str::<Manifest>(&manifest_text) {
         Ok(manifest) => {
             crate::debug::log(&format!("Manifest parsed successfully: {} assets found", manifest.assets.len()));
-            Some(manifest)
+            // Checksums also name cache files, so a malformed one is refused rather than passed on
+            if let Some(asset) = manifest.assets.iter().find(|a| a.sha256.as_deref().is_some_and(|h| !is_sha256(h))) {
+                return Err(format!("manifest.json has an invalid sha256 for {}", asset.name));
+            }
+            Ok(Some(manifest))
         }
         Err(e) => {
             crate::debug::log(&format!("Failed to parse manifest JSON: {}", e));
-            None
+            Ok(None)
         }
     }
 }
 
+/// Filename -> lowercase hex SHA-256, as listed in a SHA256SUMS release asset
+pub type Checksums = HashMap<String, String>;
+
+/// Check if a release contains a SHA256SUMS file and fetch it
+/// Returns an empty map if there is no usable file (or it is unsigned while `require_signature` is set).
+/// Fails if the file carries a signature that doesn't verify.
+pub async fn get_checksums_from_release(release: &Release, require_signature: bool) -> Result<Checksums, String> {
+    let Some(sums_asset) = release.assets.iter().find(|asset| {
+        asset.name.eq_ignore_ascii_case("SHA256SUMS") || asset.name.eq_ignore_ascii_case("SHA256SUMS.txt")
+    }) else {
+        return Ok(HashMap::new());
+    };
+
+    crate::debug::log(&format!("Found {} in release, fetching...", sums_asset.name));
+
+    let text = match fetch_release_text(sums_asset).await {
+        Ok(text) => text,
+        Err(e) => {
+            crate::debug::log(&format!("Failed to fetch SHA256SUMS: {}", e));
+            return Ok(HashMap::new());
+        }
+    };
+
+    let signed = check_release_signature(release, &sums_asset.name, text.as_bytes()).await?;
+    if require_signature && !signed {
+        crate::debug::log("Ignoring unsigned SHA256SUMS (signed releases required)");
+        return Ok(HashMap::new());
+    }
+
+    let sums = parse_sha256sums(&text);
+    crate::debug::log(&format!("SHA256SUMS parsed: {} entries", sums.len()));
+    Ok(sums)
+}
+
+/// Whether `hash` is a SHA-256 in hex (64 hex digits, either case)
+pub fn is_sha256(hash: &str) -> bool {
+    hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Parse `sha256sum` output ("<hex>  <name>" or "<hex> *<name>" per line)
+pub fn parse_sha256sums(text: &str) -> Checksums {
+    text.lines()
+        .filter_map(|line| {
+            let (hash, name) = line.trim().split_once(char::is_whitespace)?;
+            let name = name.trim_start().trim_start_matches('*');
+            (is_sha256(hash) && !name.is_empty()).then(|| (name.to_string(), hash.to_ascii_lowercase()))
+        })
+        .collect()
+}
+
+/// Fill in missing checksums from a SHA256SUMS listing (manifest values take precedence)
+pub fn apply_checksums(assets: &mut [Asset], sums: &Checksums) {
+    for asset in assets.iter_mut().filter(|a| a.sha256.is_none()) {
+        asset.sha256 = sums.get(&asset.name).cloned();
+    }
+}
+
+/// Attach block maps published in the release (e.g. os.img.bmap for os.img.gz) to raw images
+/// A bmap from manifest.json takes precedence. Either way its checksum comes from SHA256SUMS.
+pub fn apply_bmaps(assets: &mut [Asset], release: &Release, sums: &Checksums) {
+    for asset in assets.iter_mut().filter(|a| crate::pipeline::is_raw_image(&a.name)) {
+        if asset.bmap_url.is_none() {
+            asset.bmap_url = crate::bmap::bmap_names_for(&asset.name)
+                .iter()
+                .find_map(|name| release.assets.iter().find(|a| a.name.eq_ignore_ascii_case(name)))
+                .map(|bmap| bmap.browser_download_url.clone());
+        }
+        let bmap_name = asset.bmap_url.as_deref().and_then(|url| url.rsplit('/').next());
+        asset.bmap_sha256 = bmap_name.and_then(|name| sums.get(name).cloned());
+    }
+}
+
+/// Hash a downloaded file and compare it with the expected SHA-256
+pub async fn verify_sha256(
+    path: &Path,
+    expected: &str,
+    progress_tx: mpsc::UnboundedSender<DownloadProgress>,
+    cancel_token: CancellationToken,
+) -> Result<(), String> {
+    use sha2::{Digest, Sha256};
+    use std::io::Read;
+
+    let path = path.to_path_buf();
+    let expected = expected.trim().to_ascii_lowercase();
+
+    let actual = tokio::task::spawn_blocking(move || -> Result<String, String> {
+        let mut file = std::fs::File::open(&path)
+            .map_err(|e| format!("Failed to open download for verification: {}", e))?;
+        let total = file.metadata().map(|m| m.len()).unwrap_or(0);
+
+        let mut hasher = Sha256::new();
+        let mut buffer = vec![0u8; 4 * 1024 * 1024];
+        let mut verified = 0u64;
+
+        loop {
+            if cancel_token.is_cancelled() {
+                return Err("Verification cancelled".to_string());
+            }
+
+            let n = file.read(&mut buffer)
+                .map_err(|e| format!("Failed to read download for verification: {}", e))?;
+            if n == 0 {
+                break;
+            }
+            hasher.update(&buffer[..n]);
+            verified += n as u64;
+            let _ = progress_tx.send(DownloadProgress::Verifying { verified, total });
+        }
+
+        Ok(hasher.finalize().iter().map(|b| format!("{:02x}", b)).collect())
+    })
+    .await
+    .map_err(|e| format!("Verification task failed: {}", e))??;
+
+    if actual != expected {
+        crate::debug::log(&format!("SHA-256 mismatch: expected {}, got {}", expected, actual));
+        return Err(format!(
+            "Checksum mismatch - the download is corrupt or was tampered with (expected SHA-256 {}, got {})",
+            expected, actual
+        ));
+    }
+
+    crate::debug::log(&format!("SHA-256 verified: {}", actual));
+    Ok(())
+}
+
 /// Convert a ManifestAsset to an Asset structure
 /// This allows manifest-based assets to work with the existing installation pipeline
 impl From<crate::manifest::ManifestAsset> for Asset {
@@ -137,6 +419,10 @@ impl From<crate::manifest::ManifestAsset> for Asset {
             browser_download_url: manifest_asset.url,
             display_name: manifest_asset.display_name,
             devices: manifest_asset.devices,
+            sha256: manifest_asset.sha256,
+            uncompressed_size: manifest_asset.uncompressed_size,
+            bmap_url: manifest_asset.bmap_url,
+            bmap_sha256: None,
         }
     }
 }
@@ -187,24 +473,55 @@ pub async fn download_asset(
     let size_mb = total_size as f64 / 1_048_576.0;
     crate::debug::log(&format!("Download size: {:.1} MB ({} bytes)", size_mb, total_size));
 
-    // Use parallel download if server supports Range requests and file is large enough
-    const MIN_SIZE_FOR_PARALLEL: u64 = 10 * 1024 * 1024; // 10 MB
-    if accepts_ranges && total_size > MIN_SIZE_FOR_PARALLEL {
-        crate::debug::log("Server supports Range requests - using parallel chunked download (8 connections)");
-        download_parallel(
-            &client,
-            &asset.browser_download_url,
-            dest_path,
-            total_size,
-            progress_tx,
-            cancel_token,
-        ).await
+    // Resume an interrupted download if the server supports Range requests
+    if accepts_ranges && total_size > 0 {
+        let etag = head_response
+            .headers()
+            .get("etag")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string());
+        let state_path = download_state_path(dest_path);
+        let existing_len = std::fs::metadata(dest_path).map(|m| m.len()).ok();
+
+        let state = match DownloadState::load(&state_path) {
+            Some(state) if state.matches(&asset.browser_download_url, total_size, etag.as_deref())
+                && existing_len == Some(total_size) =>
+            {
+                crate::debug::log(&format!(
+                    "Resuming download: {} of {} bytes already downloaded",
+                    state.downloaded(),
+                    total_size
+                ));
+                state
+            }
+            _ => {
+                // Use parallel download if the file is large enough
+                const MIN_SIZE_FOR_PARALLEL: u64 = 10 * 1024 * 1024; // 10 MB
+                let num_chunks = if total_size > MIN_SIZE_FOR_PARALLEL {
+                    crate::debug::log("Server supports Range requests - using parallel chunked download (8 connections)");
+                    8
+                } else {
+                    crate::debug::log("File too small for parallel download - using a single ranged connection");
+                    1
+                };
+
+                // Create file and pre-allocate space
+                let file = std::fs::File::create(dest_path)
+                    .map_err(|e| format!("Failed to create file: {}", e))?;
+                file.set_len(total_size)
+                    .map_err(|e| format!("Failed to allocate file space: {}", e))?;
+                drop(file);
+
+                let state = DownloadState::new(&asset.browser_download_url, total_size, etag, num_chunks);
+                state.save(&state_path)?;
+                state
+            }
+        };
+
+        download_parallel(&client, dest_path, state, progress_tx, cancel_token).await
     } else {
-        if !accepts_ranges {
-            crate::debug::log("Server doesn't support Range requests - using single-connection download");
-        } else {
-            crate::debug::log("File too small for parallel download - using single-connection download");
-        }
+        crate::debug::log("Server doesn't support Range requests - using single-connection download");
+        let _ = std::fs::remove_file(download_state_path(dest_path));
         download_single(
             &client,
             &asset.browser_download_url,
@@ -216,147 +533,369 @@ pub async fn download_asset(
     }
 }
 
-/// Download using parallel connections (8 chunks)
+/// Path of the resume state kept next to a partial download (<file>.state)
+pub fn download_state_path(dest_path: &Path) -> PathBuf {
+    let mut name = dest_path.as_os_str().to_os_string();
+    name.push(".state");
+    name.into()
+}
+
+/// Whether an interrupted download that can be resumed exists at this path
+pub fn partial_download_exists(dest_path: &Path) -> bool {
+    dest_path.exists() && download_state_path(dest_path).exists()
+}
+
+/// Remove a (partial) download together with its resume state
+pub fn remove_download(dest_path: &Path) {
+    let _ = std::fs::remove_file(dest_path);
+    let _ = std::fs::remove_file(download_state_path(dest_path));
+}
+
+/// Per-chunk progress of a ranged download, saved next to the partial file so an
+/// interrupted download resumes where it stopped (after a network drop or an app restart).
+/// Keyed by URL + size + ETag: if any of them changes, the download starts over.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct DownloadState {
+    url: String,
+    size: u64,
+    etag: Option<String>,
+    chunks: Vec<ChunkState>,
+}
+
+/// Byte range `start..=end` of the file, of which the first `done` bytes are on disk
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+struct ChunkState {
+    start: u64,
+    end: u64,
+    done: u64,
+}
+
+impl ChunkState {
+    fn len(&self) -> u64 {
+        self.end + 1 - self.start
+    }
+
+    fn is_complete(&self) -> bool {
+        self.done >= self.len()
+    }
+}
+
+impl DownloadState {
+    fn new(url: &str, size: u64, etag: Option<String>, num_chunks: u64) -> Self {
+        let chunk_size = size / num_chunks;
+        let chunks = (0..num_chunks)
+            .map(|i| ChunkState {
+                start: i * chunk_size,
+                // Last chunk gets remainder
+                end: if i == num_chunks - 1 { size - 1 } else { (i + 1) * chunk_size - 1 },
+                done: 0,
+            })
+            .collect();
+
+        Self { url: url.to_string(), size, etag, chunks }
+    }
+
+    fn load(path: &Path) -> Option<Self> {
+        let text = std::fs::read_to_string(path).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+
+    /// Write atomically (temp file + rename) so a crash never leaves a torn state file
+    fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string(self)
+            .map_err(|e| format!("Failed to serialize download state: {}", e))?;
+        let mut tmp_path = path.as_os_str().to_os_string();
+        tmp_path.push(".tmp");
+        std::fs::write(&tmp_path, json)
+            .and_then(|_| std::fs::rename(&tmp_path, path))
+            .map_err(|e| format!("Failed to save download state: {}", e))
+    }
+
+    fn matches(&self, url: &str, size: u64, etag: Option<&str>) -> bool {
+        self.url == url && self.size == size && self.etag.as_deref() == etag
+    }
+
+    fn downloaded(&self) -> u64 {
+        self.chunks.iter().map(|c| c.done.min(c.len())).sum()
+    }
+}
+
+/// Retries per chunk before the download fails (the count resets whenever data arrives)
+const MAX_CHUNK_RETRIES: u32 = 5;
+
+/// Chunk progress is persisted (and the data synced to disk) at least this often
+const STATE_SAVE_INTERVAL: u64 = 8 * 1024 * 1024;
+
+/// Shared by all chunk tasks of one download
+struct ChunkedDownload {
+    client: reqwest::Client,
+    dest_path: PathBuf,
+    state_path: PathBuf,
+    state: std::sync::Mutex<DownloadState>,
+    downloaded: AtomicU64,
+    progress_tx: mpsc::UnboundedSender<DownloadProgress>,
+    cancel_token: CancellationToken,
+}
+
+impl ChunkedDownload {
+    fn chunk(&self, index: usize) -> ChunkState {
+        self.state.lock().unwrap().chunks[index]
+    }
+
+    fn save_state(&self) {
+        if let Err(e) = self.state.lock().unwrap().save(&self.state_path) {
+            crate::debug::log(&e);
+        }
+    }
+}
+
+enum ChunkError {
+    /// Network trouble - worth another attempt
+    Retry(String),
+    /// Cancellation, disk errors, unexpected server responses
+    Fatal(String),
+}
+
+/// Download the remaining parts of all chunks in parallel
 async fn download_parallel(
     client: &reqwest::Client,
-    url: &str,
     dest_path: &Path,
-    total_size: u64,
+    state: DownloadState,
     progress_tx: mpsc::UnboundedSender<DownloadProgress>,
     cancel_token: CancellationToken,
 ) -> Result<(), String> {
-    const NUM_CHUNKS: u64 = 8;
-    let chunk_size = total_size / NUM_CHUNKS;
+    let total_size = state.size;
+    let already_downloaded = state.downloaded();
+    let num_chunks = state.chunks.len();
 
     let _ = progress_tx.send(DownloadProgress::Started { total_bytes: total_size });
+    if already_downloaded > 0 {
+        let _ = progress_tx.send(DownloadProgress::Resuming { downloaded: already_downloaded, total: total_size });
+    }
 
-    // Create file and pre-allocate space
-    let file = std::fs::File::create(dest_path)
-        .map_err(|e| format!("Failed to create file: {}", e))?;
-    file.set_len(total_size)
-        .map_err(|e| format!("Failed to allocate file space: {}", e))?;
-    drop(file);
-
-    // Shared progress tracking
-    let downloaded = std::sync::Arc::new(std::sync::atomic::AtomicU64::new(0));
+    // Child token so one failed chunk stops the others
+    let abort_token = cancel_token.child_token();
+    let download = std::sync::Arc::new(ChunkedDownload {
+        client: client.clone(),
+        dest_path: dest_path.to_path_buf(),
+        state_path: download_state_path(dest_path),
+        state: std::sync::Mutex::new(state),
+        downloaded: AtomicU64::new(already_downloaded),
+        progress_tx: progress_tx.clone(),
+        cancel_token: abort_token.clone(),
+    });
 
     // Spawn chunk download tasks
-    let mut tasks = Vec::new();
-    for i in 0..NUM_CHUNKS {
-        let start = i * chunk_size;
-        let end = if i == NUM_CHUNKS - 1 {
-            total_size - 1 // Last chunk gets remainder
-        } else {
-            (i + 1) * chunk_size - 1
-        };
-
-        let client = client.clone();
-        let url = url.to_string();
-        let dest_path = dest_path.to_path_buf();
-        let progress_tx = progress_tx.clone();
-        let cancel_token = cancel_token.clone();
-        let downloaded = downloaded.clone();
-
-        let task = tokio::spawn(async move {
-            download_chunk(
-                &client,
-                &url,
-                &dest_path,
-                start,
-                end,
-                total_size,
-                progress_tx,
-                cancel_token,
-                downloaded,
-            ).await
-        });
+    let mut tasks = tokio::task::JoinSet::new();
+    for i in 0..num_chunks {
+        let download = download.clone();
+        tasks.spawn(async move { (i, download_chunk(&download, i).await) });
+    }
 
-        tasks.push(task);
+    // Wait for all chunks to complete, stopping the rest on the first failure
+    let mut first_error = None;
+    while let Some(joined) = tasks.join_next().await {
+        let result = match joined {
+            Ok((i, result)) => result.map_err(|e| format!("Chunk {} download failed: {}", i, e)),
+            Err(e) => Err(format!("Chunk task failed: {}", e)),
+        };
+        if let Err(e) = result {
+            abort_token.cancel();
+            first_error.get_or_insert(e);
+        }
     }
 
-    // Wait for all chunks to complete
-    for (i, task) in tasks.into_iter().enumerate() {
-        task.await
-            .map_err(|e| format!("Chunk {} task failed: {}", i, e))?
-            .map_err(|e| format!("Chunk {} download failed: {}", i, e))?;
+    if let Some(e) = first_error {
+        crate::debug::log(&format!(
+            "Download stopped with {} of {} bytes on disk, it will resume on the next attempt",
+            download.downloaded.load(Ordering::Relaxed),
+            total_size
+        ));
+        return Err(e);
     }
 
+    // Complete - the state file is only needed for partial downloads
+    let _ = std::fs::remove_file(&download.state_path);
     let _ = progress_tx.send(DownloadProgress::Completed);
     crate::debug::log("Parallel download complete");
     Ok(())
 }
 
-/// Download a single chunk of the file
-async fn download_chunk(
-    client: &reqwest::Client,
-    url: &str,
-    dest_path: &Path,
-    start: u64,
-    end: u64,
-    total_size: u64,
-    progress_tx: mpsc::UnboundedSender<DownloadProgress>,
-    cancel_token: CancellationToken,
-    downloaded: std::sync::Arc<std::sync::atomic::AtomicU64>,
-) -> Result<(), String> {
+/// Whether a 206 response's Content-Range ("bytes <start>-<end>/<size>") is the range we asked
+/// for, of a file of the size we started with
+fn content_range_matches(content_range: Option<&str>, start: u64, size: u64) -> bool {
+    let Some((range, total)) = content_range.and_then(|v| v.strip_prefix("bytes "))
+        .and_then(|v| v.split_once('/'))
+    else {
+        return false;
+    };
+    let range_start = range.split_once('-').and_then(|(first, _)| first.trim().parse::<u64>().ok());
+    range_start == Some(start) && (total.trim() == "*" || total.trim().parse::<u64>().ok() == Some(size))
+}
+
+/// Download what is left of one chunk, retrying with exponential backoff on network errors
+async fn download_chunk(download: &ChunkedDownload, index: usize) -> Result<(), String> {
+    let mut retries = 0;
+
+    loop {
+        let done_before = download.chunk(index).done;
+        let result = download_chunk_attempt(download, index).await;
+        download.save_state();
+
+        let error = match result {
+            Ok(()) => return Ok(()),
+            Err(ChunkError::Fatal(e)) => return Err(e),
+            Err(ChunkError::Retry(e)) => e,
+        };
+
+        // Only count consecutive attempts that made no progress
+        if download.chunk(index).done > done_before {
+            retries = 0;
+        }
+        if retries >= MAX_CHUNK_RETRIES {
+            return Err(format!("{} (gave up after {} retries)", error, MAX_CHUNK_RETRIES));
+        }
+        retries += 1;
+
+        let delay_secs = 1u64 << retries.min(5); // 2, 4, 8, 16, 32 seconds
+        crate::debug::log(&format!(
+            "Chunk {} interrupted: {} - retry {}/{} in {}s",
+            index, error, retries, MAX_CHUNK_RETRIES, delay_secs
+        ));
+        let _ = download.progress_tx.send(DownloadProgress::Retrying {
+            attempt: retries,
+            max_attempts: MAX_CHUNK_RETRIES,
+            delay_secs,
+        });
+
+        tokio::select! {
+            _ = download.cancel_token.cancelled() => return Err("Download cancelled".to_string()),
+            _ = tokio::time::sleep(std::time::Duration::from_secs(delay_secs)) => {}
+        }
+    }
+}
+
+/// One request for the missing part of a chunk; progress is recorded in the shared state
+async fn download_chunk_attempt(download: &ChunkedDownload, index: usize) -> Result<(), ChunkError> {
     use std::io::{Seek, SeekFrom, Write};
 
-    if cancel_token.is_cancelled() {
-        return Err("Download cancelled".to_string());
+    if download.cancel_token.is_cancelled() {
+        return Err(ChunkError::Fatal("Download cancelled".to_string()));
+    }
+
+    let chunk = download.chunk(index);
+    if chunk.is_complete() {
+        return Ok(());
     }
 
-    let range_header = format!("bytes={}-{}", start, end);
+    let (url, etag, total_size) = {
+        let state = download.state.lock().unwrap();
+        (state.url.clone(), state.etag.clone(), state.size)
+    };
+
+    let range_header = format!("bytes={}-{}", chunk.start + chunk.done, chunk.end);
     crate::debug::log(&format!("Downloading chunk: {}", range_header));
 
-    let response = client
-        .get(url)
+    let mut request = download.client
+        .get(&url)
         .header("User-Agent", USER_AGENT)
-        .header("Range", range_header)
+        .header("Range", range_header);
+    // If the file changed on the server we get the whole new file (200) instead of a range
+    // If-Range only works with strong ETags; otherwise the Content-Range check below has to do
+    if let Some(etag) = etag.as_deref().filter(|e| !e.starts_with("W/")) {
+        request = request.header("If-Range", etag);
+    }
+
+    let response = request
         .send()
         .await
-        .map_err(|e| format!("Failed to start chunk download: {}", e))?;
+        .map_err(|e| ChunkError::Retry(format!("Failed to start chunk download: {}", e)))?;
+
+    let status = response.status();
+    if status.is_server_error() || status == 408 || status == 429 {
+        return Err(ChunkError::Retry(format!("Chunk download failed with status: {}", status)));
+    }
+    if status != 206 {
+        return Err(ChunkError::Fatal(if status.is_success() {
+            "The file changed on the server during the download. Please try again.".to_string()
+        } else {
+            format!("Chunk download failed with status: {}", status)
+        }));
+    }
 
-    if !response.status().is_success() && response.status() != 206 {
-        return Err(format!("Chunk download failed with status: {}", response.status()));
+    let content_range = response.headers().get("content-range").and_then(|v| v.to_str().ok());
+    if !content_range_matches(content_range, chunk.start + chunk.done, total_size) {
+        return Err(ChunkError::Fatal(
+            "The file changed on the server during the download. Please try again.".to_string(),
+        ));
     }
 
     // Open file for writing at correct position
     let mut file = std::fs::OpenOptions::new()
         .write(true)
-        .open(dest_path)
-        .map_err(|e| format!("Failed to open file for writing chunk: {}", e))?;
+        .open(&download.dest_path)
+        .map_err(|e| ChunkError::Fatal(format!("Failed to open file for writing chunk: {}", e)))?;
 
-    file.seek(SeekFrom::Start(start))
-        .map_err(|e| format!("Failed to seek to chunk position: {}", e))?;
+    file.seek(SeekFrom::Start(chunk.start + chunk.done))
+        .map_err(|e| ChunkError::Fatal(format!("Failed to seek to chunk position: {}", e)))?;
 
     // Stream the chunk and report progress as we go
     let mut stream = response.bytes_stream();
-    let mut chunk_bytes_written = 0u64;
-
-    while let Some(chunk_result) = stream.next().await {
-        if cancel_token.is_cancelled() {
-            return Err("Download cancelled".to_string());
-        }
+    let mut unsaved = 0u64;
 
-        let chunk = chunk_result
-            .map_err(|e| format!("Failed to download chunk data: {}", e))?;
-
-        file.write_all(&chunk)
-            .map_err(|e| format!("Failed to write chunk data: {}", e))?;
-
-        chunk_bytes_written += chunk.len() as u64;
+    let result = async {
+        while let Some(chunk_result) = stream.next().await {
+            if download.cancel_token.is_cancelled() {
+                return Err(ChunkError::Fatal("Download cancelled".to_string()));
+            }
 
-        // Update global progress
-        let total_downloaded = downloaded.fetch_add(chunk.len() as u64, std::sync::atomic::Ordering::Relaxed) + chunk.len() as u64;
-        let _ = progress_tx.send(DownloadProgress::Progress {
-            downloaded: total_downloaded,
-            total: total_size,
-        });
-    }
+            let data = chunk_result
+                .map_err(|e| ChunkError::Retry(format!("Failed to download chunk data: {}", e)))?;
+
+            // Never write past the end of the chunk, even if the server sends too much
+            let current = download.chunk(index);
+            let remaining = current.len() - current.done;
+            let data = &data[..data.len().min(remaining as usize)];
+
+            file.write_all(data)
+                .map_err(|e| ChunkError::Fatal(format!("Failed to write chunk data: {}", e)))?;
+
+            download.state.lock().unwrap().chunks[index].done += data.len() as u64;
+            unsaved += data.len() as u64;
+
+            // Update global progress
+            let total_downloaded = download.downloaded.fetch_add(data.len() as u64, Ordering::Relaxed) + data.len() as u64;
+            let _ = download.progress_tx.send(DownloadProgress::Progress {
+                downloaded: total_downloaded,
+                total: total_size,
+            });
+
+            // Data must be on disk before the state claims it is
+            if unsaved >= STATE_SAVE_INTERVAL {
+                file.sync_data()
+                    .map_err(|e| ChunkError::Fatal(format!("Failed to flush chunk data: {}", e)))?;
+                download.save_state();
+                unsaved = 0;
+            }
+        }
+        Ok(())
+    }.await;
 
     file.flush()
-        .map_err(|e| format!("Failed to flush chunk data: {}", e))?;
+        .and_then(|_| file.sync_data())
+        .map_err(|e| ChunkError::Fatal(format!("Failed to flush chunk data: {}", e)))?;
+    result?;
+
+    let chunk = download.chunk(index);
+    if !chunk.is_complete() {
+        return Err(ChunkError::Retry(format!(
+            "Connection closed early ({} of {} bytes)",
+            chunk.done,
+            chunk.len()
+        )));
+    }
 
-    crate::debug::log(&format!("Chunk complete: bytes {}-{} ({} bytes written)", start, end, chunk_bytes_written));
+    crate::debug::log(&format!("Chunk complete: bytes {}-{}", chunk.start, chunk.end));
     Ok(())
 }
 
@@ -473,6 +1012,72 @@ fn parse_github_url(url: &str) -> Result<(String, String), String> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_sha256sums() {
+        let hash = "a".repeat(64);
+        let text = format!("{}  spruceOS.7z\n{} *twigUI.img.gz\nnot a checksum line\n", hash, hash.to_uppercase());
+        let sums = parse_sha256sums(&text);
+        assert_eq!(sums.len(), 2);
+        assert_eq!(sums["spruceOS.7z"], hash);
+        assert_eq!(sums["twigUI.img.gz"], hash);
+    }
+
+    #[tokio::test]
+    async fn test_verify_sha256() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("file.bin");
+        std::fs::write(&path, b"abc").unwrap();
+        // SHA-256 of "abc"
+        let good = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+        let (tx, _rx) = mpsc::unbounded_channel();
+        assert!(verify_sha256(&path, good, tx.clone(), CancellationToken::new()).await.is_ok());
+        let err = verify_sha256(&path, &"0".repeat(64), tx, CancellationToken::new()).await.unwrap_err();
+        assert!(err.contains("Checksum mismatch"));
+    }
+
+    #[test]
+    fn test_download_state() {
+        let state = DownloadState::new("https://example.com/a.7z", 100, Some("\"abc\"".to_string()), 8);
+        assert_eq!(state.chunks.len(), 8);
+        assert_eq!(state.chunks[0], ChunkState { start: 0, end: 11, done: 0 });
+        assert_eq!(state.chunks[7], ChunkState { start: 84, end: 99, done: 0 });
+        assert_eq!(state.chunks.iter().map(|c| c.len()).sum::<u64>(), 100);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = download_state_path(&dir.path().join("a.7z"));
+        let mut state = state;
+        state.chunks[7].done = 16;
+        state.save(&path).unwrap();
+
+        let loaded = DownloadState::load(&path).unwrap();
+        assert_eq!(loaded, state);
+        assert_eq!(loaded.downloaded(), 16);
+        assert!(loaded.chunks[7].is_complete());
+        assert!(loaded.matches("https://example.com/a.7z", 100, Some("\"abc\"")));
+        assert!(!loaded.matches("https://example.com/a.7z", 100, Some("\"def\"")));
+        assert!(!loaded.matches("https://example.com/a.7z", 101, Some("\"abc\"")));
+    }
+
+    #[test]
+    fn test_content_range_matches() {
+        assert!(content_range_matches(Some("bytes 100-199/1000"), 100, 1000));
+        assert!(content_range_matches(Some("bytes 100-199/*"), 100, 1000));
+        // Another range, a resized file, or no Content-Range at all
+        assert!(!content_range_matches(Some("bytes 0-199/1000"), 100, 1000));
+        assert!(!content_range_matches(Some("bytes 100-199/2000"), 100, 1000));
+        assert!(!content_range_matches(None, 100, 1000));
+    }
+
+    #[test]
+    fn test_release_summary() {
+        let release: Release = serde_json::from_str(
+            r#"{"tag_name": "v4.0.0-beta", "name": null, "assets": [], "prerelease": true, "published_at": "2026-01-31T12:00:00Z"}"#,
+        ).unwrap();
+        assert_eq!(release.date(), "2026-01-31");
+        assert_eq!(release.summary(), "v4.0.0-beta  2026-01-31  (pre-release)");
+    }
+
     #[test]
     fn test_parse_github_url() {
         assert_eq!(
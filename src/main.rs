@@ -4,18 +4,30 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 mod app;
+mod backup;
+mod benchmark;
+mod bmap;
 mod burn;
+mod cache;
+mod capacity;
+mod card_image;
+mod cli;
+mod compression;
 mod config;
 mod copy;
 mod debug;
 mod delete;
 mod drives;
 mod eject;
+mod exfat;
 mod extract;
 mod fat32;
+mod fat32_writer;
 mod format;
 mod github;
 mod manifest;
+mod pipeline;
+mod signature;
 
 #[cfg(target_os = "macos")]
 mod mac;
@@ -57,6 +69,11 @@ fn check_and_request_privileges() {
                     cmd.arg(format!("XDG_RUNTIME_DIR={}", xdg_runtime));
                 }
 
+                // Session bus for the file picker (xdg-desktop-portal)
+                if let Ok(dbus) = std::env::var("DBUS_SESSION_BUS_ADDRESS") {
+                    cmd.arg(format!("DBUS_SESSION_BUS_ADDRESS={}", dbus));
+                }
+
                 cmd.arg(current_exe);
                 cmd
             } else {
@@ -90,6 +107,12 @@ fn check_and_request_privileges() {
 }
 
 fn main() -> eframe::Result<()> {
+    // Headless command-line mode (install, list-drives, ...) never opens a window
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Some(code) = cli::run(&args) {
+        std::process::exit(code);
+    }
+
     // Call the privilege check at the very beginning of main (not needed on Windows due to manifest)
     #[cfg(not(windows))]
     check_and_request_privileges();
@@ -34,4 +34,16 @@ pub struct ManifestAsset {
     /// Optional compatible devices description (e.g., "Anbernic RG351P/V/M, Odroid Go Advance")
     #[serde(skip_serializing_if = "Option::is_none")]
     pub devices: Option<String>,
+
+    /// Optional SHA-256 of the file as lowercase hex; the download is verified against it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+
+    /// Optional decompressed size in bytes of a compressed disk image (for burn progress)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uncompressed_size: Option<u64>,
+
+    /// Optional URL of a bmaptool block map for a raw image (only mapped blocks are written)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bmap_url: Option<String>,
 }
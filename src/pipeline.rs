@@ -0,0 +1,1602 @@
+// Copyright (C) 2026 SpruceOS Team
+// Licensed under GPL-3.0-or-later
+
+// Installation pipeline
+// The full install sequence, independent of any UI:
+//   Archive mode:   [save user data] → [backup] → [capacity check] → [benchmark] → format (or delete update dirs) → download → extract → copy → verify → [restore user data]
+//                   (if the new volume can't be mounted on Linux, copy writes into it directly and verify/restore are skipped)
+//   Raw image mode: download → [save user data] → [backup] → [capacity check] → [benchmark] → burn (with verification)
+//
+// Progress is reported as a single InstallEvent stream. The GUI derives its
+// AppState from the Stage/Completed/Cancelled/Failed events, the CLI prints them.
+
+use crate::backup::{backup_device, find_card_folder, BackupProgress, BackupTarget, UserDataBackup};
+use crate::benchmark::{benchmark_drive, BenchmarkMode, BenchmarkProgress, BenchmarkTest};
+use crate::bmap::Bmap;
+use crate::burn::{burn_image, BurnOptions, BurnProgress};
+use crate::capacity::{probe_capacity, CapacityReport, ProbeProgress};
+use crate::cache::DownloadCache;
+use crate::config::{Filesystem, RepoOption, TEMP_PREFIX, VERIFY_COPIED_FILES, VOLUME_LABEL};
+use crate::copy::{copy_directory_with_progress, verify_copied_files, CopyProgress, VerifyProgress};
+use crate::delete::{delete_directories, DeleteProgress};
+use crate::drives::DriveInfo;
+use crate::extract::{extract_archive, ArchiveFormat, ExtractProgress};
+use crate::fat32_writer::copy_directory_to_device;
+use crate::fat32::FormatOptions;
+use crate::format::{format_drive, FormatProgress};
+use crate::github::{download_asset, partial_download_exists, remove_download, verify_sha256, Asset, DownloadProgress};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+
+/// A step of the installation that the UI shows as its own state
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InstallStage {
+    SavingUserData,
+    BackingUp,
+    CheckingCapacity,
+    Benchmarking,
+    Formatting,
+    Deleting,
+    Downloading,
+    Burning,
+    Extracting,
+    Copying,
+    Verifying,
+    RestoringUserData,
+}
+
+impl InstallStage {
+    /// Noun used in "<label> cancelled" / "<label> error: ..." messages
+    pub fn label(&self) -> &'static str {
+        match self {
+            InstallStage::SavingUserData => "User data backup",
+            InstallStage::BackingUp => "Backup",
+            InstallStage::CheckingCapacity => "Capacity check",
+            InstallStage::Benchmarking => "Benchmark",
+            InstallStage::Formatting => "Format",
+            InstallStage::Deleting => "Deletion",
+            InstallStage::Downloading => "Download",
+            InstallStage::Burning => "Burn",
+            InstallStage::Extracting => "Extract",
+            InstallStage::Copying => "Copy",
+            InstallStage::Verifying => "Verification",
+            InstallStage::RestoringUserData => "Restore",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum InstallEvent {
+    /// A new stage has started
+    Stage(InstallStage),
+    /// Line for the user-visible log (also written to the debug log)
+    Log(String),
+    /// Status text for the current stage (counters unchanged)
+    Status(String),
+    /// Progress within the current stage
+    Progress { current: u64, total: u64, message: String },
+    Completed,
+    Cancelled,
+    /// The installation stopped with this error (not repeated as a Log event)
+    Failed(String),
+}
+
+/// Check if an asset is a raw disk image (burned to the device) rather than an archive
+pub fn is_raw_image(name: &str) -> bool {
+    name.ends_with(".img.gz") ||
+    name.ends_with(".img.xz") ||
+    name.ends_with(".img.zst") ||
+    name.ends_with(".img.bz2") ||
+    name.ends_with(".img")
+}
+
+/// Check if a file is an archive that can be extracted onto the card (.7z, .zip, .tar.*)
+pub fn is_archive(name: &str) -> bool {
+    !is_raw_image(name) && ArchiveFormat::from_name(name).is_some()
+}
+
+/// Extensions offered when picking a local file (without the leading dot)
+pub const LOCAL_FILE_EXTENSIONS: &[&str] = &["7z", "zip", "tar", "tgz", "txz", "tzst", "img", "gz", "xz", "zst", "bz2"];
+
+/// Write files into the volume directly when the formatted card can't be mounted
+/// (only Linux formats with fat32.rs and leaves the card unmounted afterwards; FAT32 volumes only)
+const DIRECT_WRITE_FALLBACK: bool = cfg!(target_os = "linux");
+
+pub struct InstallPipeline {
+    pub drive: DriveInfo,
+    pub asset: Asset,
+    pub repo: &'static RepoOption,
+    /// Keep the existing installation and only replace repo.update_directories
+    pub update_mode: bool,
+    pub volume_label: String,
+    /// Filesystem a fresh archive install formats the card with (repo.filesystem unless overridden)
+    pub filesystem: Filesystem,
+    /// Where the download and temporary extraction folder are placed
+    pub temp_dir: PathBuf,
+    /// Install from this file instead of downloading the asset
+    pub local_file: Option<PathBuf>,
+    /// Keep downloads here for later installs (None = download to temp_dir and delete afterwards)
+    pub cache: Option<DownloadCache>,
+    /// Back the card up to an image file before it is erased (ignored in update mode)
+    pub backup: Option<BackupTarget>,
+    /// Save repo.backup_directories to the computer before the card is erased (ignored in update mode)
+    pub user_data: Option<UserDataBackup>,
+    /// Check the card for fake capacity before it is erased (ignored in update mode)
+    pub probe_capacity: bool,
+    /// Measure the card's speed before installing (raw device test, or a test file in update mode)
+    pub benchmark: bool,
+}
+
+impl InstallPipeline {
+    pub fn new(drive: DriveInfo, asset: Asset, repo: &'static RepoOption, update_mode: bool) -> Self {
+        Self {
+            drive,
+            asset,
+            repo,
+            update_mode,
+            // A partition layout names the install volume itself
+            volume_label: match FormatOptions::for_repo(repo).install_target() {
+                Ok((_, Some(spec))) => spec.label.to_string(),
+                _ => VOLUME_LABEL.to_string(),
+            },
+            filesystem: repo.filesystem,
+            temp_dir: get_cache_dir(),
+            local_file: None,
+            cache: DownloadCache::from_config(),
+            backup: None,
+            user_data: None,
+            probe_capacity: false,
+            benchmark: false,
+        }
+    }
+
+    /// Install from an archive or image already on disk instead of a release download
+    pub fn from_local_file(drive: DriveInfo, path: PathBuf, repo: &'static RepoOption, update_mode: bool) -> Result<Self, String> {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .ok_or_else(|| format!("Invalid file path: {}", path.display()))?;
+
+        if !is_raw_image(&name) && !is_archive(&name) {
+            return Err(format!("Unsupported file type: {}", name));
+        }
+
+        let size = std::fs::metadata(&path)
+            .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?
+            .len();
+
+        let asset = Asset {
+            name,
+            size,
+            browser_download_url: String::new(),
+            display_name: None,
+            devices: None,
+            sha256: None,
+            uncompressed_size: None,
+            bmap_url: None,
+            bmap_sha256: None,
+        };
+
+        // Update mode doesn't apply to raw images
+        let update_mode = update_mode && !is_raw_image(&asset.name);
+        let mut pipeline = Self::new(drive, asset, repo, update_mode);
+        pipeline.local_file = Some(path);
+        Ok(pipeline)
+    }
+
+    pub fn is_raw_image(&self) -> bool {
+        is_raw_image(&self.asset.name)
+    }
+
+    pub fn download_path(&self) -> PathBuf {
+        match &self.cache {
+            Some(cache) => cache.entry_path(&self.asset),
+            None => self.temp_dir.join(&self.asset.name),
+        }
+    }
+
+    /// Whether a complete download of the asset is already in the cache
+    pub fn is_cached(&self) -> bool {
+        self.local_file.is_none() && self.cache.as_ref().is_some_and(|cache| cache.lookup(&self.asset).is_some())
+    }
+
+    /// The archive or image the install works from (local file or download)
+    pub fn source_path(&self) -> PathBuf {
+        self.local_file.clone().unwrap_or_else(|| self.download_path())
+    }
+
+    pub fn extract_dir(&self) -> PathBuf {
+        self.temp_dir.join(format!("{}_extract", TEMP_PREFIX))
+    }
+
+    /// Run the whole installation
+    /// Always finishes with exactly one Completed, Cancelled or Failed event;
+    /// the same outcome is returned for callers that don't watch the stream
+    pub async fn run(
+        &self,
+        tx: mpsc::UnboundedSender<InstallEvent>,
+        cancel_token: CancellationToken,
+    ) -> Result<(), String> {
+        let events = EventSender::new(tx);
+
+        let result = self.execute(&events, &cancel_token).await;
+        let cancelled = matches!(&result, Err(e) if cancel_token.is_cancelled() || e.ends_with("cancelled"));
+
+        // Extracted files are never needed after the run. Downloads are kept only in the cache,
+        // or if interrupted, so the next attempt resumes them (local files are always kept)
+        if self.local_file.is_none() {
+            let download_path = self.download_path();
+            if partial_download_exists(&download_path) {
+                if result.is_err() && !cancelled {
+                    events.log("Keeping the partial download - retrying will resume it");
+                } else {
+                    remove_download(&download_path);
+                }
+            } else if self.cache.is_none() {
+                remove_download(&download_path);
+            }
+        }
+        let _ = std::fs::remove_dir_all(self.extract_dir());
+        crate::debug::log("Cleaned up temp files");
+
+        match &result {
+            Ok(()) => {
+                crate::debug::log("Installation complete!");
+                events.send(InstallEvent::Completed);
+            }
+            Err(e) if cancelled => {
+                events.log(e);
+                events.send(InstallEvent::Cancelled);
+            }
+            Err(e) => {
+                crate::debug::log(&format!("ERROR: {}", e));
+                events.send(InstallEvent::Failed(e.clone()));
+            }
+        }
+
+        result
+    }
+
+    async fn execute(&self, events: &EventSender, cancel_token: &CancellationToken) -> Result<(), String> {
+        crate::debug::log_section("Starting Installation");
+        crate::debug::log(&format!("Asset: {} ({} bytes)", self.asset.name, self.asset.size));
+        crate::debug::log(&format!("Cache/temp directory: {:?}", self.temp_dir));
+
+        self.check_disk_space(events)?;
+        self.check_signature(events).await?;
+
+        let is_raw_image = self.is_raw_image();
+        if is_raw_image {
+            crate::debug::log("Detected RAW IMAGE mode - will burn image to device");
+            events.log("Note: Raw image mode - this will erase the entire drive");
+        } else {
+            crate::debug::log("Detected ARCHIVE mode - will format, extract, and copy files");
+        }
+
+        if is_raw_image {
+            self.fetch_source(events, cancel_token).await?;
+            let user_data_dir = self.save_user_data(events, cancel_token).await?;
+            self.backup(events, cancel_token).await?;
+            self.check_capacity(events, cancel_token).await?;
+            self.benchmark(events, cancel_token).await?;
+            self.burn(events, cancel_token).await?;
+            if let Some(dir) = user_data_dir.filter(|_| self.user_data.as_ref().is_some_and(|u| u.restore)) {
+                events.log(&format!("User data can't be restored onto a disk image, it is saved in {}", dir.display()));
+            }
+            events.log("Installation complete! You can now safely eject the drive.");
+            return Ok(());
+        }
+
+        // User data is read through the mount, before the card image backup unmounts it
+        let user_data_dir = if self.update_mode {
+            None
+        } else {
+            self.save_user_data(events, cancel_token).await?
+        };
+
+        // Back up and probe the card, then format it before downloading, so a bad card fails fast
+        if !self.update_mode {
+            self.backup(events, cancel_token).await?;
+            self.check_capacity(events, cancel_token).await?;
+            self.benchmark(events, cancel_token).await?;
+            self.format(events, cancel_token).await?;
+        } else {
+            // Non-destructive test through a file on the existing filesystem
+            self.benchmark(events, cancel_token).await?;
+        }
+
+        // Without a mount (pkexec, headless, no udisks) the files go straight into the new volume
+        let dest_path = match self.mount(events).await {
+            Ok(path) => Some(path),
+            Err(e) if DIRECT_WRITE_FALLBACK && !self.update_mode && self.install_target().is_ok_and(|(_, fs)| fs == Filesystem::Fat32) => {
+                events.log(&format!("{}; writing files directly to the card instead", e));
+                None
+            }
+            Err(e) => return Err(e),
+        };
+        if let (true, Some(dest_path)) = (self.update_mode, &dest_path) {
+            self.delete_update_directories(dest_path, events, cancel_token).await?;
+        }
+
+        let card_log = CardLog(dest_path.as_ref().map(|path| path.join("install_log.txt")));
+        card_log.write("Format complete, starting download...");
+
+        let result = async {
+            self.fetch_source(events, cancel_token).await?;
+            card_log.write("Download complete, starting extraction...");
+            self.extract(events, cancel_token).await?;
+            card_log.write("Extraction complete");
+            let Some(dest_path) = &dest_path else {
+                return self.copy_direct(events, cancel_token).await;
+            };
+            card_log.write(&format!("Copying files: {:?} -> {:?}", self.extract_dir(), dest_path));
+            self.copy(dest_path, events, cancel_token).await?;
+            card_log.write("Copy complete");
+            if VERIFY_COPIED_FILES {
+                self.verify(dest_path, events, cancel_token).await?;
+                card_log.write("Verification complete");
+            }
+            Ok::<_, String>(())
+        }.await;
+
+        if let Err(e) = &result {
+            card_log.write(e);
+            return result;
+        }
+
+        let Some(dest_path) = dest_path else {
+            if let Some(dir) = user_data_dir {
+                events.log(&format!("User data can't be copied back without mounting the card, it is saved in {}", dir.display()));
+            }
+            events.log("Installation complete! You can now safely eject the SD card.");
+            return Ok(());
+        };
+
+        if let Some(dir) = user_data_dir.filter(|_| self.user_data.as_ref().is_some_and(|u| u.restore)) {
+            if let Err(e) = self.restore_user_data(&dir, &dest_path, events, cancel_token).await {
+                card_log.write(&e);
+                return Err(e);
+            }
+            card_log.write("User data restored");
+        }
+
+        // Copy debug log to SD card
+        events.log("Writing debug log to SD card...");
+        match crate::debug::copy_log_to(&dest_path) {
+            Ok(log_path) => events.log(&format!("Debug log saved to: {}", log_path.display())),
+            Err(e) => events.log(&format!("Warning: Could not copy debug log: {}", e)),
+        }
+
+        events.log("Installation complete! You can now safely eject the SD card.");
+        card_log.write("Installation complete!");
+        Ok(())
+    }
+
+    /// We need space for: download (asset.size) + extraction (~3x asset.size)
+    /// A local file needs no download space, and a local image needs no space at all
+    pub fn check_disk_space(&self, events: &EventSender) -> Result<(), String> {
+        let have_source = self.local_file.is_some() || self.is_cached();
+        let required_space = match (have_source, self.is_raw_image()) {
+            (false, _) => self.asset.size * 4, // 4x for safety margin
+            (true, false) => self.asset.size * 3,
+            (true, true) => 0,
+        };
+        let available_space = get_available_disk_space(&self.temp_dir);
+
+        crate::debug::log(&format!("Required disk space: {} MB", required_space / 1_048_576));
+        crate::debug::log(&format!("Available disk space: {} MB", available_space / 1_048_576));
+
+        if available_space < required_space {
+            return Err(format!(
+                "Insufficient disk space. Need {} MB, but only {} MB available in cache directory. Please free up disk space and try again.",
+                required_space / 1_048_576,
+                available_space / 1_048_576
+            ));
+        }
+
+        events.log(&format!("Disk space check passed: {} MB available", available_space / 1_048_576));
+        Ok(())
+    }
+
+    /// Refuse sources that aren't covered by a signature before touching the drive
+    /// Downloads are covered by a SHA-256 from a signed manifest.json/SHA256SUMS (checked after
+    /// download), local files by a detached .minisig next to them
+    pub async fn check_signature(&self, events: &EventSender) -> Result<(), String> {
+        let Some(path) = &self.local_file else {
+            if self.repo.require_signature && self.asset.sha256.is_none() {
+                return Err(format!(
+                    "{} has no signed checksum, but the {} repository requires signed releases",
+                    self.asset.name, self.repo.name
+                ));
+            }
+            return Ok(());
+        };
+
+        let signature_path = crate::signature::signature_path_for(path);
+        match std::fs::read_to_string(&signature_path).ok() {
+            Some(signature) if crate::signature::signing_enabled() => {
+                events.log("Verifying signature of local file...");
+                let path = path.clone();
+                tokio::task::spawn_blocking(move || crate::signature::verify_file(&path, &signature))
+                    .await
+                    .map_err(|e| format!("Signature check task failed: {}", e))?
+                    .map_err(|e| format!("{}: {}", self.asset.name, e))?;
+                events.log("Signature verified");
+                Ok(())
+            }
+            _ if self.repo.require_signature => Err(format!(
+                "No signature could be verified for {} (expected {}), but the {} repository requires signed releases",
+                self.asset.name,
+                signature_path.display(),
+                self.repo.name
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Copy the repo's backup_directories from the card to a timestamped folder on the computer
+    /// Returns that folder, or None if there was nothing to save
+    pub async fn save_user_data(&self, events: &EventSender, cancel_token: &CancellationToken) -> Result<Option<PathBuf>, String> {
+        let Some(user_data) = &self.user_data else {
+            return Ok(None);
+        };
+        let Some(mount_path) = &self.drive.mount_path else {
+            events.log("Card is not mounted, no user data to back up");
+            return Ok(None);
+        };
+
+        let folders: Vec<PathBuf> = self.repo.backup_directories
+            .iter()
+            .filter_map(|name| find_card_folder(mount_path, name))
+            .collect();
+        if folders.is_empty() {
+            events.log("No user data folders found on the card");
+            return Ok(None);
+        }
+
+        let stage = InstallStage::SavingUserData;
+        events.stage(stage);
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let backup_dir = user_data.dir_for(now);
+        let names: Vec<String> = folders.iter().filter_map(|f| f.file_name()).map(|n| n.to_string_lossy().to_string()).collect();
+        events.log(&format!("Backing up {} to {}...", names.join(", "), backup_dir.display()));
+        crate::debug::log_section("Backing Up User Data");
+
+        for (folder, name) in folders.iter().zip(&names) {
+            events.progress(0, 100, &format!("Backing up {}...", name));
+            self.copy_with_progress(folder, &backup_dir.join(name), stage, events, cancel_token).await?;
+        }
+
+        events.log(&format!("User data saved to {}", backup_dir.display()));
+        Ok(Some(backup_dir))
+    }
+
+    /// Copy the folders saved by save_user_data back onto the freshly installed card
+    pub async fn restore_user_data(
+        &self,
+        backup_dir: &Path,
+        dest_path: &Path,
+        events: &EventSender,
+        cancel_token: &CancellationToken,
+    ) -> Result<(), String> {
+        let stage = InstallStage::RestoringUserData;
+        events.stage(stage);
+        events.log("Restoring user data to SD card...");
+        crate::debug::log_section("Restoring User Data");
+
+        let result = async {
+            let folders = std::fs::read_dir(backup_dir)
+                .map_err(|e| stage_error(stage, format!("Failed to read {}: {}", backup_dir.display(), e)))?;
+            for folder in folders.filter_map(|entry| entry.ok()).map(|entry| entry.path()).filter(|p| p.is_dir()) {
+                let Some(name) = folder.file_name() else { continue };
+                events.progress(0, 100, &format!("Restoring {}...", name.to_string_lossy()));
+                self.copy_with_progress(&folder, &dest_path.join(name), stage, events, cancel_token).await?;
+            }
+            Ok(())
+        }.await;
+
+        match &result {
+            Ok(()) => events.log("User data restored"),
+            Err(_) => events.log(&format!("Your user data is still saved in {}", backup_dir.display())),
+        }
+        result
+    }
+
+    /// Read the card out to the backup file, if one was requested
+    pub async fn backup(&self, events: &EventSender, cancel_token: &CancellationToken) -> Result<(), String> {
+        let Some(target) = &self.backup else {
+            return Ok(());
+        };
+        let stage = InstallStage::BackingUp;
+        events.stage(stage);
+        events.log(&format!("Backing up {} to {}...", self.drive.name, target.path.display()));
+        events.progress(0, 100, "Starting backup...");
+
+        let (backup_tx, backup_rx) = mpsc::unbounded_channel::<BackupProgress>();
+        let mut total = 0;
+        let handle = events.forward(backup_rx, move |prog| match prog {
+            BackupProgress::Started { total_bytes } => {
+                total = total_bytes;
+                progress_event(0, total, "Reading card...")
+            }
+            BackupProgress::Reading { read, total: t } => {
+                total = t;
+                let message = format!(
+                    "Backing up... {}% ({}/{} MB)",
+                    percent_of(read, t),
+                    read / 1_048_576,
+                    t / 1_048_576
+                );
+                progress_event(read, t, &message)
+            }
+            BackupProgress::Completed => progress_event(total, total, "Backup complete"),
+            BackupProgress::Cancelled => InstallEvent::Status("Backup cancelled".to_string()),
+            BackupProgress::Error(e) => InstallEvent::Status(format!("Backup error: {}", e)),
+        });
+
+        let result = backup_device(&self.drive, target, backup_tx, cancel_token.clone()).await;
+        let _ = handle.await;
+        let read = result.map_err(|e| stage_error(stage, e))?;
+
+        let saved = std::fs::metadata(&target.path).map(|m| m.len()).unwrap_or(0);
+        events.log(&format!(
+            "Backup complete: {} MB read, saved {} MB to {}",
+            read / 1_048_576,
+            saved / 1_048_576,
+            target.path.display()
+        ));
+        Ok(())
+    }
+
+    /// Probe the card for fake capacity, if requested
+    pub async fn check_capacity(&self, events: &EventSender, cancel_token: &CancellationToken) -> Result<(), String> {
+        if !self.probe_capacity {
+            return Ok(());
+        }
+        let stage = InstallStage::CheckingCapacity;
+        events.stage(stage);
+        events.log(&format!("Checking {} for fake capacity...", self.drive.name));
+        events.progress(0, 100, "Checking capacity...");
+
+        let (probe_tx, probe_rx) = mpsc::unbounded_channel::<ProbeProgress>();
+        let mut total = 0;
+        let handle = events.forward(probe_rx, move |prog| match prog {
+            ProbeProgress::Started { probes } => {
+                total = probes * 2;
+                progress_event(0, total, "Writing test blocks...")
+            }
+            ProbeProgress::Writing { done, total: t } => progress_event(done, t * 2, "Writing test blocks..."),
+            ProbeProgress::Reading { done, total: t } => progress_event(t + done, t * 2, "Reading test blocks back..."),
+            ProbeProgress::Restoring => InstallEvent::Status("Restoring original data...".to_string()),
+            ProbeProgress::Completed => progress_event(total, total, "Capacity check complete"),
+            ProbeProgress::Cancelled => InstallEvent::Status("Capacity check cancelled".to_string()),
+            ProbeProgress::Error(e) => InstallEvent::Status(format!("Capacity check error: {}", e)),
+        });
+
+        let result = probe_capacity(&self.drive, probe_tx, cancel_token.clone()).await;
+        let _ = handle.await;
+        match result {
+            Ok(report) => self.check_capacity_report(&report, events),
+            Err(e) if cancel_token.is_cancelled() => Err(stage_error(stage, e)),
+            Err(e) => {
+                events.log(&format!("WARNING: Capacity check failed, continuing: {}", e));
+                Ok(())
+            }
+        }
+    }
+
+    /// A fake card stops the install before anything is erased; unreadable blocks alone only warn
+    fn check_capacity_report(&self, report: &CapacityReport, events: &EventSender) -> Result<(), String> {
+        if report.is_fake() {
+            return Err(stage_error(InstallStage::CheckingCapacity, report.summary()));
+        }
+        if report.unreadable_probes > 0 {
+            events.log(&format!("WARNING: {}", report.summary()));
+        } else {
+            events.log(&report.summary());
+        }
+        Ok(())
+    }
+
+    /// Measure the card's speed, if requested: on the raw device when it is erased next anyway,
+    /// otherwise (update mode) through a test file on the mounted card
+    pub async fn benchmark(&self, events: &EventSender, cancel_token: &CancellationToken) -> Result<(), String> {
+        if !self.benchmark {
+            return Ok(());
+        }
+        let stage = InstallStage::Benchmarking;
+        events.stage(stage);
+        events.log(&format!("Testing the speed of {}...", self.drive.name));
+        events.progress(0, 100, "Testing card speed...");
+
+        let (bench_tx, bench_rx) = mpsc::unbounded_channel::<BenchmarkProgress>();
+        let handle = events.forward(bench_rx, |prog| match prog {
+            BenchmarkProgress::Started => InstallEvent::Status("Starting speed test...".to_string()),
+            BenchmarkProgress::Running { test, percent } => {
+                // Four tests of equal weight
+                let index = match test {
+                    BenchmarkTest::SequentialWrite => 0,
+                    BenchmarkTest::SequentialRead => 1,
+                    BenchmarkTest::RandomWrite => 2,
+                    BenchmarkTest::RandomRead => 3,
+                };
+                progress_event(index * 100 + percent as u64, 400, &format!("{}... {}%", test.label(), percent))
+            }
+            BenchmarkProgress::Completed => progress_event(100, 100, "Speed test complete"),
+            BenchmarkProgress::Cancelled => InstallEvent::Status("Speed test cancelled".to_string()),
+            BenchmarkProgress::Error(e) => InstallEvent::Status(format!("Speed test error: {}", e)),
+        });
+
+        let mode = if self.update_mode { BenchmarkMode::Filesystem } else { BenchmarkMode::Raw };
+        let result = benchmark_drive(&self.drive, mode, bench_tx, cancel_token.clone()).await;
+        let _ = handle.await;
+        let result = result.map_err(|e| stage_error(stage, e))?;
+
+        for line in result.summary() {
+            events.log(&line);
+        }
+        Ok(())
+    }
+
+    pub async fn format(&self, events: &EventSender, cancel_token: &CancellationToken) -> Result<(), String> {
+        let stage = InstallStage::Formatting;
+        events.stage(stage);
+        events.log(&format!("Formatting {}...", self.drive.name));
+        crate::debug::log_section("Formatting Drive");
+        events.progress(0, 100, "Formatting drive...");
+
+        let options = self.format_options();
+        let filesystem = options.filesystem;
+        let (fmt_tx, fmt_rx) = mpsc::unbounded_channel::<FormatProgress>();
+        let handle = events.forward(fmt_rx, move |prog| match prog {
+            FormatProgress::Started => InstallEvent::Status("Starting format...".to_string()),
+            FormatProgress::Unmounting => InstallEvent::Status("Unmounting drive...".to_string()),
+            #[cfg(not(target_os = "macos"))]
+            FormatProgress::CleaningDisk => InstallEvent::Status("Cleaning disk...".to_string()),
+            #[cfg(not(target_os = "macos"))]
+            FormatProgress::CreatingPartition => InstallEvent::Status("Creating partition...".to_string()),
+            FormatProgress::Formatting => InstallEvent::Status(format!("Formatting to {}...", filesystem.name())),
+            FormatProgress::Progress { percent } => percent_event(percent, "Formatting"),
+            FormatProgress::Completed => progress_event(100, 100, "Format complete"),
+            FormatProgress::Cancelled => InstallEvent::Status("Format cancelled".to_string()),
+            FormatProgress::Error(e) => InstallEvent::Status(format!("Format error: {}", e)),
+        });
+
+        // On Windows, format function expects drive letter (e.g., "E:"), not physical drive path
+        // On other platforms, device_path is correct (e.g., "/dev/sdb")
+        #[cfg(target_os = "windows")]
+        let format_path = &self.drive.name;
+        #[cfg(not(target_os = "windows"))]
+        let format_path = &self.drive.device_path;
+
+        let result = format_drive(format_path, options, &self.volume_label, fmt_tx, cancel_token.clone()).await;
+        let _ = handle.await;
+        result.map_err(|e| stage_error(stage, e))?;
+
+        events.log("Format complete");
+        Ok(())
+    }
+
+    /// The repository's format options, with the filesystem chosen for this install
+    fn format_options(&self) -> FormatOptions {
+        FormatOptions { filesystem: self.filesystem, ..FormatOptions::for_repo(self.repo) }
+    }
+
+    /// Partition table number (1-4) and filesystem of the volume the install goes to
+    fn install_target(&self) -> Result<(u32, Filesystem), String> {
+        let options = self.format_options();
+        let (number, spec) = options.install_target()?;
+        Ok((number, spec.and_then(|spec| spec.filesystem).unwrap_or(options.filesystem)))
+    }
+
+    /// Get the mount path of the (freshly formatted or existing) card
+    pub async fn mount(&self, events: &EventSender) -> Result<PathBuf, String> {
+        crate::debug::log("Getting mount path...");
+        let (partition, _) = self.install_target()?;
+        let path = get_mount_path_after_format(&self.drive, partition, &self.volume_label)
+            .await
+            .map_err(|e| format!("Error getting mount path: {}", e))?;
+
+        events.log(&format!("Destination: {}", path.display()));
+        crate::debug::log(&format!("Mount path: {:?}", path));
+        Ok(path)
+    }
+
+    pub async fn delete_update_directories(
+        &self,
+        mount_path: &Path,
+        events: &EventSender,
+        cancel_token: &CancellationToken,
+    ) -> Result<(), String> {
+        let stage = InstallStage::Deleting;
+        events.stage(stage);
+        events.log("Deleting old directories...");
+        crate::debug::log_section("Deleting Directories");
+        events.progress(0, 100, "Deleting old directories...");
+
+        let (del_tx, del_rx) = mpsc::unbounded_channel::<DeleteProgress>();
+        let mut total = 0;
+        let handle = events.forward(del_rx, move |prog| match prog {
+            DeleteProgress::Started { total_dirs } => {
+                total = total_dirs as u64;
+                progress_event(0, total, &format!("Deleting {} directories...", total_dirs))
+            }
+            DeleteProgress::DeletingDirectory { name } => InstallEvent::Status(format!("Deleting: {}", name)),
+            DeleteProgress::Completed => progress_event(total, total, "Directory deletion complete"),
+            DeleteProgress::Cancelled => InstallEvent::Status("Deletion cancelled".to_string()),
+            DeleteProgress::Error(e) => InstallEvent::Status(format!("Deletion error: {}", e)),
+        });
+
+        let result = delete_directories(mount_path, self.repo.update_directories, del_tx, cancel_token.clone()).await;
+        let _ = handle.await;
+        result.map_err(|e| stage_error(stage, e))?;
+
+        events.log("Directory deletion complete");
+        Ok(())
+    }
+
+    /// Download the asset, or use the local file as-is
+    async fn fetch_source(&self, events: &EventSender, cancel_token: &CancellationToken) -> Result<(), String> {
+        match &self.local_file {
+            Some(path) => {
+                events.log(&format!("Using local file: {}", path.display()));
+                Ok(())
+            }
+            None => self.download(events, cancel_token).await,
+        }
+    }
+
+    pub async fn download(&self, events: &EventSender, cancel_token: &CancellationToken) -> Result<(), String> {
+        let stage = InstallStage::Downloading;
+        events.stage(stage);
+        let size_mb = self.asset.size as f64 / 1_048_576.0;
+        let cached = self.is_cached();
+        if cached {
+            events.log(&format!("Using cached download ({:.1} MB)", size_mb));
+        } else {
+            events.log(&format!("Downloading release ({:.1} MB)...", size_mb));
+        }
+        crate::debug::log_section("Downloading Release");
+
+        let download_path = self.download_path();
+        crate::debug::log(&format!("Download path: {:?}", download_path));
+        if let Some(parent) = download_path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| stage_error(stage, format!("Failed to create download directory: {}", e)))?;
+        }
+
+        let (dl_tx, dl_rx) = mpsc::unbounded_channel::<DownloadProgress>();
+        let handle = events.forward(dl_rx, |prog| match prog {
+            DownloadProgress::Started { total_bytes } => progress_event(0, total_bytes, "Downloading..."),
+            DownloadProgress::Progress { downloaded, total } => {
+                progress_event(downloaded, total, &format!("Downloading... {}%", percent_of(downloaded, total)))
+            }
+            DownloadProgress::Resuming { downloaded, total } => InstallEvent::Log(format!(
+                "Resuming download ({} of {} MB already downloaded)",
+                downloaded / 1_048_576,
+                total / 1_048_576
+            )),
+            DownloadProgress::Retrying { attempt, max_attempts, delay_secs } => InstallEvent::Status(format!(
+                "Connection lost, retrying in {}s ({}/{})...",
+                delay_secs, attempt, max_attempts
+            )),
+            DownloadProgress::Verifying { verified, total } => {
+                progress_event(verified, total, &format!("Verifying download... {}%", percent_of(verified, total)))
+            }
+            DownloadProgress::Completed => InstallEvent::Status("Download complete".to_string()),
+            DownloadProgress::Cancelled => InstallEvent::Status("Download cancelled".to_string()),
+            DownloadProgress::Error(e) => InstallEvent::Status(format!("Download error: {}", e)),
+        });
+
+        let mut result = if cached {
+            Ok(())
+        } else {
+            download_asset(&self.asset, &download_path, dl_tx.clone(), cancel_token.clone()).await
+        };
+        match (&result, &self.asset.sha256) {
+            (Ok(()), Some(expected)) => {
+                events.log("Verifying SHA-256 checksum...");
+                result = verify_sha256(&download_path, expected, dl_tx, cancel_token.clone()).await;
+                // A corrupt file must not stay in the cache
+                if matches!(&result, Err(e) if !e.ends_with("cancelled")) {
+                    remove_download(&download_path);
+                }
+            }
+            (Ok(()), None) => {
+                crate::debug::log("No SHA-256 published for this asset, skipping verification");
+                drop(dl_tx);
+            }
+            _ => drop(dl_tx),
+        }
+        let _ = handle.await;
+        result.map_err(|e| stage_error(stage, e))?;
+
+        if let Some(cache) = &self.cache {
+            let freed = cache.evict(&download_path);
+            if freed > 0 {
+                events.log(&format!("Download cache full, removed {} MB of older downloads", freed / 1_048_576));
+            }
+        }
+
+        events.log("Download complete");
+        Ok(())
+    }
+
+    /// Block map for the image: next to a local file, or published alongside the release asset
+    /// A missing or unusable bmap isn't an error; the whole image is written instead.
+    async fn load_bmap(&self, events: &EventSender) -> Option<Bmap> {
+        let (xml, bmap_path) = match (&self.local_file, &self.asset.bmap_url) {
+            (Some(path), _) => {
+                let bmap_path = crate::bmap::find_local_bmap(path)?;
+                crate::debug::log(&format!("Found bmap: {:?}", bmap_path));
+                (std::fs::read_to_string(&bmap_path).map_err(|e| e.to_string()), Some(bmap_path))
+            }
+            (None, Some(url)) => {
+                crate::debug::log(&format!("Fetching bmap: {}", url));
+                (crate::github::fetch_text(url, "bmap").await, None)
+            }
+            (None, None) => return None,
+        };
+
+        let bmap = xml.and_then(|xml| {
+            self.check_bmap_source(&xml, bmap_path.as_deref())?;
+            crate::bmap::load_for_image(&xml, &self.source_path())
+        });
+        match bmap {
+            Ok(bmap) => {
+                events.log(&format!(
+                    "Using block map: writing {} MB of {} MB",
+                    bmap.mapped_bytes() / 1_048_576,
+                    bmap.image_size / 1_048_576
+                ));
+                Some(bmap)
+            }
+            Err(e) => {
+                events.log(&format!("Ignoring block map: {}", e));
+                None
+            }
+        }
+    }
+
+    /// A bmap decides which parts of the image reach the card, so it needs the same trust as
+    /// the image: a checksum from SHA256SUMS, or a .minisig next to a local bmap
+    fn check_bmap_source(&self, xml: &str, bmap_path: Option<&Path>) -> Result<(), String> {
+        if let Some(bmap_path) = bmap_path {
+            if !self.repo.require_signature {
+                return Ok(());
+            }
+            let signature_path = crate::signature::signature_path_for(bmap_path);
+            let signature = std::fs::read_to_string(&signature_path).map_err(|_| {
+                format!("no signature (expected {}), but the {} repository requires signed releases", signature_path.display(), self.repo.name)
+            })?;
+            return crate::signature::verify_file(bmap_path, &signature);
+        }
+
+        match &self.asset.bmap_sha256 {
+            Some(expected) => {
+                let actual = format!("{:x}", Sha256::digest(xml.as_bytes()));
+                if actual.eq_ignore_ascii_case(expected) {
+                    Ok(())
+                } else {
+                    Err(format!("checksum mismatch (expected {}, got {})", expected, actual))
+                }
+            }
+            None if self.repo.require_signature => Err(format!(
+                "no signed checksum, but the {} repository requires signed releases",
+                self.repo.name
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Burn the downloaded image to the device (burn.rs decompresses it on the fly)
+    pub async fn burn(&self, events: &EventSender, cancel_token: &CancellationToken) -> Result<(), String> {
+        let stage = InstallStage::Burning;
+        events.stage(stage);
+        events.log(&format!("Burning image to {}...", self.drive.name));
+        crate::debug::log_section("Burning Image");
+        crate::debug::log(&format!("Device: {}", self.drive.device_path));
+
+        let (burn_tx, burn_rx) = mpsc::unbounded_channel::<BurnProgress>();
+        let mut total = 0;
+        let handle = events.forward(burn_rx, move |prog| match prog {
+            BurnProgress::Started { total_bytes } => {
+                total = total_bytes;
+                progress_event(0, total, "Starting burn...")
+            }
+            BurnProgress::Writing { written, total: t } => {
+                total = t;
+                let message = format!(
+                    "Writing... {}% ({}/{} MB)",
+                    percent_of(written, t),
+                    written / 1_048_576,
+                    t / 1_048_576
+                );
+                progress_event(written, t, &message)
+            }
+            BurnProgress::Verifying { verified, total: t } => {
+                total = t;
+                progress_event(verified, t, &format!("Verifying... {}%", percent_of(verified, t)))
+            }
+            BurnProgress::Completed => progress_event(total, total, "Burn complete"),
+            BurnProgress::Cancelled => InstallEvent::Status("Burn cancelled".to_string()),
+            BurnProgress::Error(e) => InstallEvent::Status(format!("Burn error: {}", e)),
+        });
+
+        let options = BurnOptions {
+            size_hint: self.asset.uncompressed_size,
+            bmap: self.load_bmap(events).await,
+        };
+        let result = burn_image(&self.source_path(), &self.drive.device_path, options, burn_tx, cancel_token.clone()).await;
+        let _ = handle.await;
+        result.map_err(|e| stage_error(stage, e))?;
+
+        events.log("Image burn and verification complete");
+        Ok(())
+    }
+
+    /// Extract the downloaded archive to a temp folder on the local PC
+    pub async fn extract(&self, events: &EventSender, cancel_token: &CancellationToken) -> Result<(), String> {
+        let stage = InstallStage::Extracting;
+        events.stage(stage);
+        events.log("Extracting files to local temp folder...");
+        crate::debug::log_section("Extracting Files");
+
+        let extract_dir = self.extract_dir();
+        crate::debug::log(&format!("Temp extract dir: {:?}", extract_dir));
+        events.progress(0, 100, "Extracting files...");
+
+        // Clean up any previous extraction
+        let _ = std::fs::remove_dir_all(&extract_dir);
+        std::fs::create_dir_all(&extract_dir)
+            .map_err(|e| stage_error(stage, format!("Failed to create temp extract dir: {}", e)))?;
+
+        let (ext_tx, ext_rx) = mpsc::unbounded_channel::<ExtractProgress>();
+        let handle = events.forward(ext_rx, |prog| match prog {
+            ExtractProgress::Started => InstallEvent::Status("Starting extraction...".to_string()),
+            ExtractProgress::Extracting => InstallEvent::Status("Extracting files...".to_string()),
+            ExtractProgress::Progress { percent } => percent_event(percent, "Extracting"),
+            ExtractProgress::Writing { file, extracted, total } => {
+                progress_event(extracted, total, &format!("Extracting... {}% ({})", percent_of(extracted, total), file))
+            }
+            ExtractProgress::Completed => progress_event(100, 100, "Extraction complete"),
+            ExtractProgress::Cancelled => InstallEvent::Status("Extraction cancelled".to_string()),
+            ExtractProgress::Error(e) => InstallEvent::Status(format!("Extract error: {}", e)),
+        });
+
+        let result = extract_archive(&self.source_path(), &extract_dir, ext_tx, cancel_token.clone()).await;
+        let _ = handle.await;
+        result.map_err(|e| stage_error(stage, e))?;
+
+        events.log("Extraction complete");
+        Ok(())
+    }
+
+    /// Copy the extracted files to the SD card
+    pub async fn copy(&self, dest_path: &Path, events: &EventSender, cancel_token: &CancellationToken) -> Result<(), String> {
+        let stage = InstallStage::Copying;
+        events.stage(stage);
+        events.log("Copying files to SD card...");
+        crate::debug::log_section("Copying Files");
+        events.progress(0, 100, "Copying files...");
+
+        self.copy_with_progress(&self.extract_dir(), dest_path, stage, events, cancel_token).await?;
+
+        events.log("Copy complete");
+        Ok(())
+    }
+
+    /// Write the extracted files into the freshly formatted volume on the device, without a mount
+    pub async fn copy_direct(&self, events: &EventSender, cancel_token: &CancellationToken) -> Result<(), String> {
+        let stage = InstallStage::Copying;
+        events.stage(stage);
+        events.log("Writing files directly to SD card...");
+        events.progress(0, 100, "Copying files...");
+
+        let (copy_tx, copy_rx) = mpsc::unbounded_channel::<CopyProgress>();
+        let handle = forward_copy_progress(events, copy_rx);
+        let (partition, _) = self.install_target().map_err(|e| stage_error(stage, e))?;
+        let result = copy_directory_to_device(&self.drive.device_path, partition, &self.extract_dir(), copy_tx, cancel_token.clone()).await;
+        let _ = handle.await;
+        result.map_err(|e| stage_error(stage, e))?;
+
+        events.log("Copy complete (verification needs the card mounted and was skipped)");
+        Ok(())
+    }
+
+    /// Read the copied files back from the card and compare them with the extracted originals
+    pub async fn verify(&self, dest_path: &Path, events: &EventSender, cancel_token: &CancellationToken) -> Result<(), String> {
+        let stage = InstallStage::Verifying;
+        events.stage(stage);
+        events.log("Verifying files on SD card...");
+        events.progress(0, 100, "Verifying files...");
+
+        let (verify_tx, verify_rx) = mpsc::unbounded_channel::<VerifyProgress>();
+        let mut total = 0;
+        let handle = events.forward(verify_rx, move |prog| match prog {
+            VerifyProgress::Flushing => InstallEvent::Status("Flushing SD card...".to_string()),
+            VerifyProgress::Started { total_bytes, total_files } => {
+                total = total_bytes;
+                progress_event(0, total_bytes, &format!("Verifying {} files...", total_files))
+            }
+            VerifyProgress::Progress { verified_bytes, total_bytes, current_file } => {
+                total = total_bytes;
+                let pct = percent_of(verified_bytes, total_bytes);
+                let message = if current_file.is_empty() {
+                    format!("Verifying... {}%", pct)
+                } else {
+                    format!("Verifying {}% - {}", pct, short_file_name(current_file))
+                };
+                progress_event(verified_bytes, total_bytes, &message)
+            }
+            VerifyProgress::Mismatch { file, reason } => InstallEvent::Log(format!("Verification failed: {} ({})", file, reason)),
+            VerifyProgress::Completed => progress_event(total, total, "Verification complete"),
+            VerifyProgress::Cancelled => InstallEvent::Status("Verification cancelled".to_string()),
+            VerifyProgress::Error(e) => InstallEvent::Status(format!("Verification error: {}", e)),
+        });
+
+        let result = verify_copied_files(&self.extract_dir(), dest_path, verify_tx, cancel_token.clone()).await;
+        let _ = handle.await;
+        result.map_err(|e| stage_error(stage, e))?;
+
+        events.log("All files verified");
+        Ok(())
+    }
+
+    /// Copy a folder's contents, reporting progress for the given stage
+    async fn copy_with_progress(
+        &self,
+        source: &Path,
+        dest: &Path,
+        stage: InstallStage,
+        events: &EventSender,
+        cancel_token: &CancellationToken,
+    ) -> Result<(), String> {
+        let (copy_tx, copy_rx) = mpsc::unbounded_channel::<CopyProgress>();
+        let handle = forward_copy_progress(events, copy_rx);
+        let result = copy_directory_with_progress(source, dest, copy_tx, cancel_token.clone()).await;
+        let _ = handle.await;
+        result.map_err(|e| stage_error(stage, e))
+    }
+}
+
+/// Copy progress as install events (shared by mounted and direct copies)
+fn forward_copy_progress(events: &EventSender, copy_rx: mpsc::UnboundedReceiver<CopyProgress>) -> tokio::task::JoinHandle<()> {
+    let mut total = 0;
+    events.forward(copy_rx, move |prog| match prog {
+        CopyProgress::Counting => InstallEvent::Status("Counting files...".to_string()),
+        CopyProgress::Started { total_bytes, total_files } => {
+            total = total_bytes;
+            progress_event(0, total_bytes, &format!("Copying {} files...", total_files))
+        }
+        CopyProgress::Progress { copied_bytes, total_bytes, current_file } => {
+            total = total_bytes;
+            let pct = percent_of(copied_bytes, total_bytes);
+            let message = if current_file.is_empty() {
+                format!("Copying... {}%", pct)
+            } else {
+                format!("{}% - {}", pct, short_file_name(current_file))
+            };
+            progress_event(copied_bytes, total_bytes, &message)
+        }
+        CopyProgress::Completed => progress_event(total, total, "Copy complete"),
+        CopyProgress::Cancelled => InstallEvent::Status("Copy cancelled".to_string()),
+        CopyProgress::Error(e) => InstallEvent::Status(format!("Copy error: {}", e)),
+    })
+}
+
+/// Sending side of the event stream; a closed receiver is not an error
+pub struct EventSender(mpsc::UnboundedSender<InstallEvent>);
+
+impl EventSender {
+    pub fn new(tx: mpsc::UnboundedSender<InstallEvent>) -> Self {
+        Self(tx)
+    }
+
+    fn send(&self, event: InstallEvent) {
+        let _ = self.0.send(event);
+    }
+
+    fn stage(&self, stage: InstallStage) {
+        self.send(InstallEvent::Stage(stage));
+    }
+
+    /// Log to the user-visible log and the debug log file
+    fn log(&self, msg: &str) {
+        crate::debug::log(msg);
+        self.send(InstallEvent::Log(msg.to_string()));
+    }
+
+    fn progress(&self, current: u64, total: u64, message: &str) {
+        self.send(progress_event(current, total, message));
+    }
+
+    /// Translate a step's own progress channel into install events until the step finishes
+    fn forward<P, F>(&self, mut rx: mpsc::UnboundedReceiver<P>, mut map: F) -> tokio::task::JoinHandle<()>
+    where
+        P: Send + 'static,
+        F: FnMut(P) -> InstallEvent + Send + 'static,
+    {
+        let tx = self.0.clone();
+        tokio::spawn(async move {
+            while let Some(prog) = rx.recv().await {
+                let _ = tx.send(map(prog));
+            }
+        })
+    }
+}
+
+/// Appends timestamped lines to install_log.txt on the card (None when the card isn't mounted)
+struct CardLog(Option<PathBuf>);
+
+impl CardLog {
+    fn write(&self, msg: &str) {
+        use std::io::Write;
+        let Some(path) = &self.0 else { return };
+        if let Ok(mut file) = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+        {
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let _ = writeln!(file, "[{}] {}", timestamp, msg);
+        }
+    }
+}
+
+fn stage_error(stage: InstallStage, e: String) -> String {
+    if e.contains("cancelled") {
+        format!("{} cancelled", stage.label())
+    } else {
+        format!("{} error: {}", stage.label(), e)
+    }
+}
+
+/// Truncate long file names from the front for progress messages
+/// Counts characters, not bytes, so multi-byte names are never cut mid-character
+fn short_file_name(file: String) -> String {
+    let chars = file.chars().count();
+    if chars > 40 {
+        format!("...{}", file.chars().skip(chars - 37).collect::<String>())
+    } else {
+        file
+    }
+}
+
+fn percent_of(current: u64, total: u64) -> u32 {
+    if total > 0 {
+        (current as f64 / total as f64 * 100.0) as u32
+    } else {
+        0
+    }
+}
+
+fn progress_event(current: u64, total: u64, message: &str) -> InstallEvent {
+    InstallEvent::Progress { current, total, message: message.to_string() }
+}
+
+fn percent_event(percent: u8, label: &str) -> InstallEvent {
+    progress_event(percent as u64, 100, &format!("{}... {}%", label, percent))
+}
+
+/// Get available disk space for a given path (in bytes)
+pub fn get_available_disk_space(path: &std::path::Path) -> u64 {
+    #[cfg(target_os = "windows")]
+    {
+        use std::os::windows::ffi::OsStrExt;
+        use windows::Win32::Storage::FileSystem::GetDiskFreeSpaceExW;
+
+        let path_wide: Vec<u16> = path.as_os_str()
+            .encode_wide()
+            .chain(Some(0))
+            .collect();
+
+        let mut free_bytes = 0u64;
+        unsafe {
+            if GetDiskFreeSpaceExW(
+                windows::core::PCWSTR(path_wide.as_ptr()),
+                None,
+                None,
+                Some(&mut free_bytes),
+            ).is_ok() {
+                return free_bytes;
+            }
+        }
+        crate::debug::log("WARNING: Failed to get disk space on Windows, assuming sufficient space");
+        u64::MAX // Assume sufficient space if we can't check
+    }
+
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let path_cstr = std::ffi::CString::new(path.as_os_str().as_bytes()).unwrap_or_default();
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+
+        unsafe {
+            if libc::statvfs(path_cstr.as_ptr(), &mut stat) == 0 {
+                // Available space = block size * available blocks
+                // Cast both to u64 to handle platforms where they're u32 (macOS, ARM32)
+                return (stat.f_bavail as u64) * (stat.f_bsize as u64);
+            }
+        }
+        crate::debug::log("WARNING: Failed to get disk space on Unix, assuming sufficient space");
+        u64::MAX // Assume sufficient space if we can't check
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+    {
+        crate::debug::log("WARNING: Disk space check not supported on this platform");
+        u64::MAX // Assume sufficient space on unsupported platforms
+    }
+}
+
+/// Get the directory used for downloads and temporary extraction
+/// On Linux/macOS, use cache dir to avoid temp space issues
+/// Linux: ~/.cache, macOS: ~/Library/Caches
+pub fn get_cache_dir() -> std::path::PathBuf {
+    #[cfg(target_os = "linux")]
+    {
+        // If running as root via sudo or pkexec, try to use the actual user's cache directory
+        if unsafe { libc::geteuid() } == 0 {
+            // First check for SUDO_USER (command-line sudo)
+            if let Ok(sudo_user) = std::env::var("SUDO_USER") {
+                let user_home = std::path::PathBuf::from(format!("/home/{}", sudo_user));
+                if user_home.exists() {
+                    let user_cache = user_home.join(".cache");
+                    crate::debug::log(&format!("Using cache dir for sudo user {}: {:?}", sudo_user, user_cache));
+                    user_cache
+                } else {
+                    crate::debug::log(&format!("User home not found at {:?}, using default", user_home));
+                    dirs::cache_dir().unwrap_or_else(std::env::temp_dir)
+                }
+            }
+            // Check for PKEXEC_UID (GUI elevation via pkexec)
+            else if let Ok(pkexec_uid) = std::env::var("PKEXEC_UID") {
+                if let Ok(uid) = pkexec_uid.parse::<u32>() {
+                    // Get username from UID using libc
+                    let pwd = unsafe { libc::getpwuid(uid) };
+                    if !pwd.is_null() {
+                        let username = unsafe {
+                            std::ffi::CStr::from_ptr((*pwd).pw_name)
+                                .to_string_lossy()
+                                .to_string()
+                        };
+                        let user_home = std::path::PathBuf::from(format!("/home/{}", username));
+                        if user_home.exists() {
+                            let user_cache = user_home.join(".cache");
+                            crate::debug::log(&format!("Using cache dir for pkexec user {} (UID {}): {:?}", username, uid, user_cache));
+                            user_cache
+                        } else {
+                            crate::debug::log(&format!("User home not found at {:?}, using default", user_home));
+                            dirs::cache_dir().unwrap_or_else(std::env::temp_dir)
+                        }
+                    } else {
+                        crate::debug::log(&format!("Failed to get username for UID {}, using default", uid));
+                        dirs::cache_dir().unwrap_or_else(std::env::temp_dir)
+                    }
+                } else {
+                    crate::debug::log(&format!("Failed to parse PKEXEC_UID '{}', using default", pkexec_uid));
+                    dirs::cache_dir().unwrap_or_else(std::env::temp_dir)
+                }
+            }
+            else {
+                crate::debug::log("Running as root, using root's cache dir");
+                dirs::cache_dir().unwrap_or_else(std::env::temp_dir)
+            }
+        } else {
+            dirs::cache_dir().unwrap_or_else(std::env::temp_dir)
+        }
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        dirs::cache_dir().unwrap_or_else(std::env::temp_dir)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    {
+        std::env::temp_dir()
+    }
+}
+
+/// Get the mount path after formatting, handling platform differences
+#[cfg(target_os = "windows")]
+pub async fn get_mount_path_after_format(drive: &DriveInfo, _partition: u32, _volume_label: &str) -> Result<PathBuf, String> {
+    // On Windows, the drive letter remains the same after formatting
+    // The mount_path should be set (e.g., "E:\")
+    drive.mount_path.clone().ok_or_else(|| {
+        format!("No mount path available for drive {}", drive.name)
+    })
+}
+
+#[cfg(target_os = "macos")]
+pub async fn get_mount_path_after_format(_drive: &DriveInfo, _partition: u32, volume_label: &str) -> Result<PathBuf, String> {
+    // macOS automatically mounts at /Volumes/LABEL after diskutil eraseDisk
+    // Wait a moment for the mount to complete
+    tokio::time::sleep(tokio::time::Duration::from_millis(1000)).await;
+
+    let mount_path = PathBuf::from(format!("/Volumes/{}", volume_label));
+
+    // Wait for the mount point to appear (up to 10 seconds)
+    for _ in 0..20 {
+        if mount_path.exists() {
+            return Ok(mount_path);
+        }
+        tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+    }
+
+    Err(format!("Mount point {} did not appear after formatting", mount_path.display()))
+}
+
+#[cfg(target_os = "linux")]
+pub async fn get_mount_path_after_format(drive: &DriveInfo, partition: u32, volume_label: &str) -> Result<PathBuf, String> {
+    use tokio::process::Command;
+
+    // Determine the partition path
+    let partition_path = if drive.device_path.contains("mmcblk") || drive.device_path.contains("nvme") {
+        format!("{}p{}", drive.device_path, partition)
+    } else {
+        format!("{}{}", drive.device_path, partition)
+    };
+
+    // Use udisksctl to mount - this registers with the udisks2 daemon so it won't
+    // auto-remount when we later unmount. The daemon chooses the mount point
+    // (typically /media/username/LABEL or /run/media/username/LABEL).
+    crate::debug::log(&format!("Mounting {} via udisksctl...", partition_path));
+    let output = Command::new("udisksctl")
+        .args(["mount", "-b", &partition_path])
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run udisksctl mount: {}", e))?;
+
+    if output.status.success() {
+        // Parse mount point from udisksctl output: "Mounted /dev/sdb1 at /media/user/LABEL"
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        crate::debug::log(&format!("udisksctl output: {}", stdout.trim()));
+
+        if let Some(mount_point) = stdout.split(" at ").nth(1) {
+            let mount_path = PathBuf::from(mount_point.trim().trim_end_matches('.'));
+            crate::debug::log(&format!("Mount point: {:?}", mount_path));
+            return Ok(mount_path);
+        }
+    }
+
+    // Check stderr for errors
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    crate::debug::log(&format!("udisksctl error: {}", stderr.trim()));
+
+    // Check if already mounted - extract existing mount path from error message
+    // Error format: "...AlreadyMounted: Device /dev/xxx is already mounted at `/path/to/mount'."
+    if stderr.contains("AlreadyMounted") {
+        if let Some(start) = stderr.find("already mounted at `") {
+            let after_prefix = &stderr[start + "already mounted at `".len()..];
+            if let Some(end) = after_prefix.find("'") {
+                let existing_mount = &after_prefix[..end];
+                crate::debug::log(&format!("Device already mounted, using existing mount point: {}", existing_mount));
+                return Ok(PathBuf::from(existing_mount));
+            }
+        }
+        // Also try alternate format without backticks
+        if let Some(start) = stderr.find("already mounted at ") {
+            let after_prefix = &stderr[start + "already mounted at ".len()..];
+            // Take until end of line or period
+            let mount_path: String = after_prefix
+                .chars()
+                .take_while(|&c| c != '.' && c != '\n' && c != '\'' && c != '`')
+                .collect();
+            let mount_path = mount_path.trim();
+            if !mount_path.is_empty() {
+                crate::debug::log(&format!("Device already mounted, using existing mount point: {}", mount_path));
+                return Ok(PathBuf::from(mount_path));
+            }
+        }
+    }
+
+    // Fallback: use raw mount if udisksctl fails (e.g., no udisks2 daemon)
+    crate::debug::log("udisksctl mount failed, falling back to raw mount...");
+
+    let cache_dir = dirs::cache_dir().unwrap_or_else(|| PathBuf::from("/tmp"));
+    let mount_point = cache_dir.join(format!("{}_{}", TEMP_PREFIX, volume_label));
+
+    // Create the mount directory if it doesn't exist
+    let _ = std::fs::create_dir_all(&mount_point);
+
+    // Mount the partition
+    let output = Command::new("mount")
+        .args([&partition_path, mount_point.to_str().unwrap()])
+        .output()
+        .await
+        .map_err(|e| format!("Failed to mount partition: {}", e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("Failed to mount partition: {}", stderr));
+    }
+
+    Ok(mount_point)
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux", target_os = "macos")))]
+pub async fn get_mount_path_after_format(_drive: &DriveInfo, _partition: u32, _volume_label: &str) -> Result<PathBuf, String> {
+    Err("Mounting not supported on this platform".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::REPO_OPTIONS;
+
+    fn test_pipeline(asset_name: &str, asset_size: u64) -> InstallPipeline {
+        let drive = DriveInfo {
+            name: "sdz".to_string(),
+            device_path: "/dev/sdz".to_string(),
+            mount_path: None,
+            label: String::new(),
+            size_bytes: 0,
+        };
+        let asset = Asset {
+            name: asset_name.to_string(),
+            size: asset_size,
+            browser_download_url: String::new(),
+            display_name: None,
+            devices: None,
+            sha256: None,
+            uncompressed_size: None,
+            bmap_url: None,
+            bmap_sha256: None,
+        };
+        let mut pipeline = InstallPipeline::new(drive, asset, &REPO_OPTIONS[0], false);
+        pipeline.temp_dir = std::env::temp_dir();
+        pipeline.cache = None;
+        pipeline
+    }
+
+    #[test]
+    fn test_is_raw_image() {
+        assert!(is_raw_image("twigUI.img.gz"));
+        assert!(is_raw_image("card.img"));
+        assert!(!is_raw_image("spruceOS.7z"));
+        assert!(!is_raw_image("image.zip"));
+    }
+
+    #[test]
+    fn test_stage_error() {
+        assert_eq!(stage_error(InstallStage::Downloading, "Download cancelled by user".to_string()), "Download cancelled");
+        assert_eq!(stage_error(InstallStage::Copying, "disk full".to_string()), "Copy error: disk full");
+    }
+
+    #[test]
+    fn test_short_file_name() {
+        assert_eq!(short_file_name("Roms/GB/game.gb".to_string()), "Roms/GB/game.gb");
+
+        let long = format!("Roms/{}", "a".repeat(60));
+        assert_eq!(short_file_name(long), format!("...{}", "a".repeat(37)));
+
+        // A byte cut at len - 37 would land inside one of these characters
+        let name = format!("Roms/GB/{}.gb", "ファイアーエムブレム聖戦の系譜".repeat(2));
+        let short = short_file_name(name.clone());
+        assert_eq!(short.chars().count(), 40);
+        assert!(short.starts_with("...") && name.ends_with(&short[3..]));
+    }
+
+    #[test]
+    fn test_from_local_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = dir.path().join("card.img.gz");
+        std::fs::write(&image, b"data").unwrap();
+        let text = dir.path().join("notes.txt");
+        std::fs::write(&text, b"data").unwrap();
+
+        let drive = test_pipeline("x.7z", 0).drive;
+        let pipeline = InstallPipeline::from_local_file(drive.clone(), image.clone(), &REPO_OPTIONS[0], true).unwrap();
+        assert_eq!(pipeline.asset.size, 4);
+        assert_eq!(pipeline.source_path(), image);
+        assert!(!pipeline.update_mode, "update mode never applies to raw images");
+
+        assert!(InstallPipeline::from_local_file(drive, text, &REPO_OPTIONS[0], false).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_run_fails_before_touching_drive_without_disk_space() {
+        let pipeline = test_pipeline("huge.img.gz", u64::MAX / 4);
+        let (tx, mut rx) = mpsc::unbounded_channel();
+
+        let result = pipeline.run(tx, CancellationToken::new()).await;
+        assert!(result.unwrap_err().starts_with("Insufficient disk space"));
+
+        let mut events = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            events.push(event);
+        }
+        assert!(!events.iter().any(|e| matches!(e, InstallEvent::Stage(_))));
+        assert!(matches!(events.last(), Some(InstallEvent::Failed(_))));
+    }
+
+    #[test]
+    fn test_fake_capacity_stops_install() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let events = EventSender::new(tx);
+        let pipeline = test_pipeline("spruceOS.7z", 1);
+        let report = CapacityReport {
+            reported_bytes: 64_000_000_000,
+            verified_bytes: 8_000_000_000,
+            probes: 8,
+            failed_probes: 7,
+            unreadable_probes: 0,
+        };
+
+        // The error ends the run before the card is formatted
+        let error = pipeline.check_capacity_report(&report, &events).unwrap_err();
+        assert!(error.starts_with("Capacity check error: ") && error.contains("counterfeit"), "{}", error);
+        assert!(rx.try_recv().is_err());
+
+        // Unreadable blocks alone point to a failing card: warn and go on
+        let failing = CapacityReport { failed_probes: 0, unreadable_probes: 1, ..report };
+        assert!(pipeline.check_capacity_report(&failing, &events).is_ok());
+        assert!(matches!(rx.try_recv(), Ok(InstallEvent::Log(msg)) if msg.starts_with("WARNING: ")));
+    }
+
+    static SIGNED_REPO: RepoOption = RepoOption {
+        name: "Signed",
+        url: "example/signed",
+        info: "",
+        supports_update_mode: false,
+        update_directories: &[],
+        backup_directories: &[],
+        allowed_extensions: None,
+        asset_display_mappings: None,
+        require_signature: true,
+        filesystem: Filesystem::Fat32,
+        cluster_size: None,
+        partition_alignment: None,
+        partitions: &[],
+        install_partition: None,
+    };
+
+    #[tokio::test]
+    async fn test_check_signature_required() {
+        let (tx, _rx) = mpsc::unbounded_channel();
+        let events = EventSender::new(tx);
+
+        let mut pipeline = test_pipeline("spruceOS.7z", 1);
+        assert!(pipeline.check_signature(&events).await.is_ok());
+
+        pipeline.repo = &SIGNED_REPO;
+        let err = pipeline.check_signature(&events).await.unwrap_err();
+        assert!(err.contains("requires signed releases"));
+
+        pipeline.asset.sha256 = Some("0".repeat(64));
+        assert!(pipeline.check_signature(&events).await.is_ok());
+    }
+
+    #[test]
+    fn test_check_bmap_source() {
+        let xml = "<bmap version=\"2.0\"></bmap>";
+        let mut pipeline = test_pipeline("os.img.gz", 1);
+        assert!(pipeline.check_bmap_source(xml, None).is_ok());
+
+        // Signed releases need the bmap in SHA256SUMS, and it has to match
+        pipeline.repo = &SIGNED_REPO;
+        let error = pipeline.check_bmap_source(xml, None).unwrap_err();
+        assert!(error.contains("requires signed releases"), "{}", error);
+        pipeline.asset.bmap_sha256 = Some(format!("{:x}", Sha256::digest(xml.as_bytes())));
+        assert!(pipeline.check_bmap_source(xml, None).is_ok());
+        let error = pipeline.check_bmap_source("<bmap version=\"2.0\"> </bmap>", None).unwrap_err();
+        assert!(error.starts_with("checksum mismatch"), "{}", error);
+
+        // A local bmap without a signature beside it is refused
+        let dir = tempfile::tempdir().unwrap();
+        let bmap_path = dir.path().join("os.img.bmap");
+        std::fs::write(&bmap_path, xml).unwrap();
+        let error = pipeline.check_bmap_source(xml, Some(&bmap_path)).unwrap_err();
+        assert!(error.contains("requires signed releases"), "{}", error);
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright (C) 2026 SpruceOS Team
+// Licensed under GPL-3.0-or-later
+
+// Release signature verification
+// Detached minisign (ed25519) signatures are checked against SIGNING_PUBLIC_KEY
+// from config.rs. Signing manifest.json / SHA256SUMS is enough to cover the
+// assets themselves, since every download is checked against its SHA-256.
+
+use crate::config::SIGNING_PUBLIC_KEY;
+use minisign_verify::{PublicKey, Signature};
+use std::io::Read;
+use std::path::Path;
+
+/// File extension of detached minisign signatures
+pub const SIGNATURE_EXTENSION: &str = "minisig";
+
+/// Whether a public key is configured (signatures are ignored otherwise)
+pub fn signing_enabled() -> bool {
+    SIGNING_PUBLIC_KEY.is_some()
+}
+
+fn public_key() -> Result<PublicKey, String> {
+    let key = SIGNING_PUBLIC_KEY
+        .ok_or_else(|| "No signing public key is configured".to_string())?;
+    PublicKey::from_base64(key.trim())
+        .map_err(|e| format!("Invalid signing public key in config: {}", e))
+}
+
+fn decode_signature(signature: &str) -> Result<Signature, String> {
+    Signature::decode(signature.trim())
+        .map_err(|e| format!("Malformed signature: {}", e))
+}
+
+/// Verify a detached signature over in-memory data (manifest.json, SHA256SUMS)
+pub fn verify_bytes(data: &[u8], signature: &str) -> Result<(), String> {
+    verify_bytes_with_key(&public_key()?, data, signature)
+}
+
+fn verify_bytes_with_key(key: &PublicKey, data: &[u8], signature: &str) -> Result<(), String> {
+    let signature = decode_signature(signature)?;
+    // Legacy (non-prehashed) signatures are accepted for small files
+    key.verify(data, &signature, true)
+        .map_err(|e| format!("Signature verification failed: {}", e))
+}
+
+/// Verify a detached signature over a file on disk, streaming its contents
+/// Only pre-hashed signatures (the minisign default) are supported here
+pub fn verify_file(path: &Path, signature: &str) -> Result<(), String> {
+    verify_file_with_key(&public_key()?, path, signature)
+}
+
+fn verify_file_with_key(key: &PublicKey, path: &Path, signature: &str) -> Result<(), String> {
+    let signature = decode_signature(signature)?;
+    let mut verifier = key.verify_stream(&signature)
+        .map_err(|e| format!("Signature verification failed: {}", e))?;
+
+    let mut file = std::fs::File::open(path)
+        .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let mut buffer = vec![0u8; 4 * 1024 * 1024];
+    loop {
+        let n = file.read(&mut buffer)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        if n == 0 {
+            break;
+        }
+        verifier.update(&buffer[..n]);
+    }
+
+    verifier.finalize()
+        .map_err(|e| format!("Signature verification failed: {}", e))
+}
+
+/// Path of the detached signature that accompanies a local file (e.g. image.img.gz.minisig)
+pub fn signature_path_for(path: &Path) -> std::path::PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(".");
+    name.push(SIGNATURE_EXTENSION);
+    name.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test vectors from the minisign-verify crate (both signatures cover the bytes "test")
+    const TEST_KEY: &str = "RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+    const TEST_SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RWQf6LRCGA9i59SLOFxz6NxvASXDJeRtuZykwQepbDEGt87ig1BNpWaVWuNrm73YiIiJbq71Wi+dP9eKL8OC351vwIasSSbXxwA=
+trusted comment: timestamp:1555779966\tfile:test
+QtKMXWyYcwdpZAlPF7tE2ENJkRd1ujvKjlj1m9RtHTBnZPa5WKU5uWRs5GoP5M/VqE81QFuMKI5k/SfNQUaOAA==";
+    const TEST_PREHASHED_SIGNATURE: &str = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1556193335\tfile:test
+y/rUw2y8/hOUYjZU71eHp/Wo1KZ40fGy2VJEDl34XMJM+TX48Ss/17u3IvIfbVR1FkZZSNCisQbuQY+bHwhEBg==";
+
+    #[test]
+    fn test_verify_bytes() {
+        let key = PublicKey::from_base64(TEST_KEY).unwrap();
+        assert!(verify_bytes_with_key(&key, b"test", TEST_SIGNATURE).is_ok());
+        assert!(verify_bytes_with_key(&key, b"Test", TEST_SIGNATURE).is_err());
+        assert!(verify_bytes_with_key(&key, b"test", "not a signature").is_err());
+    }
+
+    #[test]
+    fn test_verify_file() {
+        let key = PublicKey::from_base64(TEST_KEY).unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test");
+        std::fs::write(&path, b"test").unwrap();
+        assert!(verify_file_with_key(&key, &path, TEST_PREHASHED_SIGNATURE).is_ok());
+
+        std::fs::write(&path, b"tampered").unwrap();
+        assert!(verify_file_with_key(&key, &path, TEST_PREHASHED_SIGNATURE).is_err());
+    }
+}